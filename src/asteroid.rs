@@ -0,0 +1,159 @@
+#![allow(dead_code)]
+
+use crate::sphere::Mesh;
+use crate::vector::Vector3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Parameters for a procedural rocky body
+#[derive(Debug, Clone)]
+pub struct AsteroidParams {
+    pub seed: u64,
+    pub radius: f32,
+    /// Icosphere subdivision level (3 gives 1280 triangles)
+    pub subdivisions: u32,
+    /// Per-axis stretch; values apart from 1.0 give potato shapes
+    pub elongation: Vector3,
+    /// Low frequency lumps, as a fraction of the radius
+    pub noise_amplitude: f32,
+    pub noise_frequency: f32,
+    pub noise_octaves: u32,
+    pub crater_count: u32,
+    /// Angular crater radius range in radians
+    pub crater_size: (f32, f32),
+    /// Bowl depth as a fraction of the crater radius
+    pub crater_depth: f32,
+}
+
+impl Default for AsteroidParams {
+    fn default() -> Self {
+        AsteroidParams {
+            seed: 1,
+            radius: 1.0,
+            subdivisions: 3,
+            elongation: Vector3::new(1.3, 0.85, 1.0),
+            noise_amplitude: 0.25,
+            noise_frequency: 1.6,
+            noise_octaves: 4,
+            crater_count: 12,
+            crater_size: (0.15, 0.45),
+            crater_depth: 0.25,
+        }
+    }
+}
+
+struct Crater {
+    center: Vector3,
+    radius: f32,
+    depth: f32,
+}
+
+/// Builds an irregular rocky mesh: a subdivided sphere displaced by seeded 3D fbm noise
+/// and stamped with craters, then stretched along the elongation axes.
+/// The same seed always produces the same mesh.
+pub fn generate_asteroid(params: &AsteroidParams) -> Mesh {
+    let mut rng = StdRng::seed_from_u64(params.seed);
+    let noise_seed: u32 = rng.gen();
+
+    let craters: Vec<Crater> = (0..params.crater_count)
+        .map(|_| {
+            let radius = rng.gen_range(params.crater_size.0..=params.crater_size.1);
+            Crater {
+                center: random_unit_vector(&mut rng),
+                radius,
+                depth: radius * params.crater_depth * rng.gen_range(0.6..=1.0),
+            }
+        })
+        .collect();
+
+    let mut mesh = Mesh::create_icosphere(1.0, params.subdivisions);
+
+    for vertex in &mut mesh.vertices {
+        // The icosphere is built with unit radius, so the position is the direction
+        let direction = vertex.position;
+
+        let lumps = fbm3(direction * params.noise_frequency, params.noise_octaves, noise_seed) * 2.0 - 1.0;
+        let crater_height: f32 = craters.iter().map(|crater| crater_profile(direction, crater)).sum();
+
+        let height = (1.0 + lumps * params.noise_amplitude + crater_height).max(0.2);
+        let position = direction * (height * params.radius);
+
+        vertex.position = Vector3::new(
+            position.x * params.elongation.x,
+            position.y * params.elongation.y,
+            position.z * params.elongation.z,
+        );
+    }
+
+    mesh.recompute_normals();
+    mesh
+}
+
+/// Height offset of a crater at a point on the unit sphere: a parabolic bowl
+/// with a raised rim that fades out past the edge
+fn crater_profile(direction: Vector3, crater: &Crater) -> f32 {
+    let angle = direction.dot(&crater.center).clamp(-1.0, 1.0).acos();
+    let t = angle / crater.radius;
+
+    if t < 1.0 {
+        crater.depth * (t * t - 1.0) + crater.depth * 0.2
+    } else if t < 1.5 {
+        let fade = 1.0 - (t - 1.0) / 0.5;
+        crater.depth * 0.2 * fade * fade
+    } else {
+        0.0
+    }
+}
+
+fn random_unit_vector(rng: &mut StdRng) -> Vector3 {
+    // Uniform on the sphere: uniform z and uniform azimuth
+    let z: f32 = rng.gen_range(-1.0..=1.0);
+    let azimuth: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+    let r = (1.0 - z * z).sqrt();
+    Vector3::new(r * azimuth.cos(), r * azimuth.sin(), z)
+}
+
+/// Integer hash of a lattice point to [0, 1)
+fn hash3(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+    let mut h = seed
+        ^ (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    (h & 0x00ff_ffff) as f32 / 16_777_216.0
+}
+
+/// Trilinear value noise with smoothstep fade, in [0, 1)
+fn value_noise3(p: Vector3, seed: u32) -> f32 {
+    let (xi, yi, zi) = (p.x.floor() as i32, p.y.floor() as i32, p.z.floor() as i32);
+    let fade = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (fx, fy, fz) = (fade(p.x - p.x.floor()), fade(p.y - p.y.floor()), fade(p.z - p.z.floor()));
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let corner = |dx: i32, dy: i32, dz: i32| hash3(xi + dx, yi + dy, zi + dz, seed);
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), fx);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), fx);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), fx);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), fx);
+
+    lerp(lerp(x00, x10, fy), lerp(x01, x11, fy), fz)
+}
+
+/// Fractal sum of value noise octaves, normalized to [0, 1)
+fn fbm3(p: Vector3, octaves: u32, seed: u32) -> f32 {
+    let mut value = 0.0;
+    let mut amplitude = 0.5;
+    let mut total = 0.0;
+    let mut point = p;
+
+    for octave in 0..octaves {
+        value += amplitude * value_noise3(point, seed.wrapping_add(octave));
+        total += amplitude;
+        point *= 2.0;
+        amplitude *= 0.5;
+    }
+
+    if total > 0.0 { value / total } else { 0.0 }
+}
@@ -0,0 +1,279 @@
+use crate::fragment::{triangle, ClipRect, TransformedVertex};
+use crate::framebuffer::Framebuffer;
+use crate::matrix::{Matrix, ScreenTransform};
+use crate::shaders::{ShaderColor, ShaderUniforms};
+use crate::simd::{self, Vector3Soa};
+use crate::sphere::Mesh;
+use crate::vector::Vector3;
+use rayon::prelude::*;
+use std::f32::consts::PI;
+
+/// Samples along the view ray through the shell
+const VIEW_SAMPLES: usize = 6;
+/// Samples used to precompute the optical depth towards the light
+const LIGHT_SAMPLES: usize = 16;
+/// Resolution of the optical depth table: heights x zenith angles
+const DEPTH_TABLE_HEIGHTS: usize = 32;
+const DEPTH_TABLE_ANGLES: usize = 64;
+
+/// Transparent shell around a planet that scatters light with a single-scattering
+/// Rayleigh (small molecules, blue) and Mie (haze, forward lobe) model.
+/// Both densities fall off exponentially with the height above `planet_radius`.
+pub struct Atmosphere {
+    /// Radius of the ground heights are measured from
+    pub planet_radius: f32,
+    /// Outer radius of the shell, where the air is considered gone (fixed: the shell
+    /// mesh is built with it)
+    radius: f32,
+    /// Rayleigh scattering coefficients (r, g, b) at ground level, per world unit
+    pub rayleigh: [f32; 3],
+    pub rayleigh_height: f32,
+    /// Mie scattering coefficient at ground level, the same for every channel
+    pub mie: f32,
+    pub mie_height: f32,
+    /// Henyey-Greenstein asymmetry of the Mie phase (0 = isotropic, near 1 = forward)
+    pub mie_g: f32,
+    /// Exposure applied to the scattered light
+    pub intensity: f32,
+    shell: Mesh,
+    /// Rayleigh and Mie optical depth from a height towards a zenith angle up to the
+    /// top of the shell, so the light rays do not have to be marched per pixel
+    depth_table: Vec<(f32, f32)>,
+}
+
+impl Atmosphere {
+    /// Thin air scattering mostly blue, with a little haze: blue limb and red sunsets
+    pub fn earth_like(planet_radius: f32) -> Self {
+        let thickness = planet_radius * 0.1;
+        Atmosphere {
+            planet_radius,
+            radius: planet_radius + thickness,
+            rayleigh: [1.8, 4.0, 10.0],
+            rayleigh_height: thickness * 0.25,
+            mie: 4.0,
+            mie_height: thickness * 0.1,
+            mie_g: 0.76,
+            intensity: 20.0,
+            shell: Mesh::create_sphere(planet_radius + thickness, 32, 32),
+            depth_table: Vec::new(),
+        }
+        .with_depth_table()
+    }
+
+    /// Deep, hazy envelope where Mie scattering dominates and tints the limb pale
+    pub fn gas_giant(planet_radius: f32) -> Self {
+        let thickness = planet_radius * 0.15;
+        Atmosphere {
+            planet_radius,
+            radius: planet_radius + thickness,
+            rayleigh: [2.0, 3.2, 5.5],
+            rayleigh_height: thickness * 0.3,
+            mie: 6.0,
+            mie_height: thickness * 0.2,
+            mie_g: 0.7,
+            intensity: 8.0,
+            shell: Mesh::create_sphere(planet_radius + thickness, 32, 32),
+            depth_table: Vec::new(),
+        }
+        .with_depth_table()
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn with_depth_table(mut self) -> Self {
+        let mut table = Vec::with_capacity(DEPTH_TABLE_HEIGHTS * DEPTH_TABLE_ANGLES);
+        for h in 0..DEPTH_TABLE_HEIGHTS {
+            let height = (self.radius - self.planet_radius) * h as f32 / (DEPTH_TABLE_HEIGHTS - 1) as f32;
+            let point = Vector3::new(0.0, self.planet_radius + height, 0.0);
+            for a in 0..DEPTH_TABLE_ANGLES {
+                let cos_zenith = a as f32 / (DEPTH_TABLE_ANGLES - 1) as f32 * 2.0 - 1.0;
+                let sin_zenith = (1.0 - cos_zenith * cos_zenith).max(0.0).sqrt();
+                let direction = Vector3::new(sin_zenith, cos_zenith, 0.0);
+                table.push(self.march_optical_depth(point, direction));
+            }
+        }
+        self.depth_table = table;
+        self
+    }
+
+    /// Optical depth from `point` to the edge of the shell, marched along `direction`
+    fn march_optical_depth(&self, point: Vector3, direction: Vector3) -> (f32, f32) {
+        let far = ray_sphere(point, direction, self.radius).map_or(0.0, |(_, far)| far.max(0.0));
+        let step = far / LIGHT_SAMPLES as f32;
+        let mut depth = (0.0, 0.0);
+        for i in 0..LIGHT_SAMPLES {
+            let (rayleigh, mie) = self.density(point + direction * (step * (i as f32 + 0.5)));
+            depth.0 += rayleigh * step;
+            depth.1 += mie * step;
+        }
+        depth
+    }
+
+    /// Densities (Rayleigh, Mie) at a point, relative to ground level
+    fn density(&self, point: Vector3) -> (f32, f32) {
+        let height = (point.length() - self.planet_radius).max(0.0);
+        ((-height / self.rayleigh_height).exp(), (-height / self.mie_height).exp())
+    }
+
+    /// Rayleigh and Mie optical depth from `point` to the edge of the shell towards
+    /// `direction`, or `None` if the planet is in the way (the point is in shadow)
+    fn optical_depth_to_light(&self, point: Vector3, direction: Vector3) -> Option<(f32, f32)> {
+        if let Some((near, _)) = ray_sphere(point, direction, self.planet_radius) {
+            if near > 0.0 {
+                return None;
+            }
+        }
+        // Bilinear lookup by height and zenith angle
+        let distance = point.length();
+        let height = (distance - self.planet_radius) / (self.radius - self.planet_radius);
+        let cos_zenith = point.dot(&direction) / distance.max(1e-6);
+        let hf = height.clamp(0.0, 1.0) * (DEPTH_TABLE_HEIGHTS - 1) as f32;
+        let af = (cos_zenith.clamp(-1.0, 1.0) * 0.5 + 0.5) * (DEPTH_TABLE_ANGLES - 1) as f32;
+        let (h0, a0) = ((hf as usize).min(DEPTH_TABLE_HEIGHTS - 2), (af as usize).min(DEPTH_TABLE_ANGLES - 2));
+        let (th, ta) = (hf - h0 as f32, af - a0 as f32);
+        let at = |h: usize, a: usize| self.depth_table[h * DEPTH_TABLE_ANGLES + a];
+        let lerp = |x: (f32, f32), y: (f32, f32), t: f32| (x.0 + (y.0 - x.0) * t, x.1 + (y.1 - x.1) * t);
+        let low = lerp(at(h0, a0), at(h0, a0 + 1), ta);
+        let high = lerp(at(h0 + 1, a0), at(h0 + 1, a0 + 1), ta);
+        Some(lerp(low, high, th))
+    }
+
+    /// Light scattered towards the eye along the ray `origin + t * direction` (unit
+    /// direction) and how much of what lies behind the shell is blocked, as a color
+    /// with premultiplied alpha ready to blend
+    pub fn scatter(&self, origin: Vector3, direction: Vector3, uniforms: &ShaderUniforms) -> ShaderColor {
+        let Some((enter, exit)) = ray_sphere(origin, direction, self.radius) else {
+            return ShaderColor::new(0.0, 0.0, 0.0, 0.0);
+        };
+        // The ray stops at the ground if it reaches it
+        let end = match ray_sphere(origin, direction, self.planet_radius) {
+            Some((ground, _)) if ground > 0.0 => ground.min(exit),
+            _ => exit,
+        };
+        let start = enter.max(0.0);
+        if end <= start {
+            return ShaderColor::new(0.0, 0.0, 0.0, 0.0);
+        }
+
+        let step = (end - start) / VIEW_SAMPLES as f32;
+        let mut view_depth = (0.0, 0.0);
+        let mut rayleigh_sum = [0.0f32; 3];
+        let mut mie_sum = [0.0f32; 3];
+        for i in 0..VIEW_SAMPLES {
+            let point = origin + direction * (start + step * (i as f32 + 0.5));
+            let (rayleigh_density, mie_density) = self.density(point);
+            view_depth.0 += rayleigh_density * step;
+            view_depth.1 += mie_density * step;
+
+            for light in &uniforms.lights {
+                let (light_dir, irradiance) = light.illuminate(point);
+                let Some(light_depth) = self.optical_depth_to_light(point, light_dir) else {
+                    continue;
+                };
+                let cos_theta = direction.dot(&light_dir);
+                let rayleigh_phase = 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+                let mie_phase = henyey_greenstein(cos_theta, self.mie_g);
+
+                let incoming = [irradiance.r, irradiance.g, irradiance.b];
+                for channel in 0..3 {
+                    // Light dimmed on its way in and on its way out towards the eye
+                    let tau = self.rayleigh[channel] * (view_depth.0 + light_depth.0)
+                        + self.mie * 1.1 * (view_depth.1 + light_depth.1);
+                    let attenuation = (-tau).exp() * incoming[channel] * step;
+                    rayleigh_sum[channel] += rayleigh_density * rayleigh_phase * attenuation;
+                    mie_sum[channel] += mie_density * mie_phase * attenuation;
+                }
+            }
+        }
+
+        let mut color = [0.0f32; 3];
+        let mut transmittance = 0.0;
+        for channel in 0..3 {
+            color[channel] = self.intensity * (self.rayleigh[channel] * rayleigh_sum[channel] + self.mie * mie_sum[channel]);
+            transmittance += (-(self.rayleigh[channel] * view_depth.0 + self.mie * 1.1 * view_depth.1)).exp() / 3.0;
+        }
+        // Premultiplied alpha: the scattered light is added, the background is dimmed
+        // by the average transmittance
+        ShaderColor::new(color[0], color[1], color[2], (1.0 - transmittance).clamp(0.0, 1.0))
+    }
+
+    /// Blends the scattering of every pixel covered by the front of the shell over
+    /// what is already drawn
+    pub fn render(&self, framebuffer: &mut Framebuffer, screen: &ScreenTransform, uniforms: &ShaderUniforms) {
+        let eye = uniforms.camera_position;
+        let covered = front_shell_pixels(&self.shell, framebuffer, &screen.world_to_screen(), eye, screen.width, screen.height);
+
+        let colors: Vec<ShaderColor> = covered
+            .par_iter()
+            .map(|&(_, _, shell_point)| self.scatter(eye, (shell_point - eye).normalize(), uniforms))
+            .collect();
+        for (&(x, y, _), color) in covered.iter().zip(colors) {
+            framebuffer.blend_pixel(x, y, color);
+        }
+    }
+}
+
+/// Pixels covered by the half of a sphere shell centered at the origin that faces
+/// `eye`, with the world point of the shell under each one. They are depth tested
+/// but nothing is written, so transparent layers can be shaded in parallel after.
+pub fn front_shell_pixels(
+    shell: &Mesh,
+    framebuffer: &Framebuffer,
+    mvp: &Matrix,
+    eye: Vector3,
+    width: i32,
+    height: i32,
+) -> Vec<(u32, u32, Vector3)> {
+    let positions = Vector3Soa::from_vectors(shell.vertices.iter().map(|vertex| vertex.position));
+    let mut screen_positions = Vector3Soa::default();
+    simd::transform_points(mvp, &positions, &mut screen_positions);
+    let vertices: Vec<TransformedVertex> = shell
+        .vertices
+        .iter()
+        .enumerate()
+        .map(|(i, vertex)| TransformedVertex {
+            screen_position: screen_positions.get(i),
+            world_position: vertex.position,
+            normal: vertex.normal,
+            color: ShaderColor::BLACK,
+            uv: vertex.uv,
+        })
+        .collect();
+
+    let clip = ClipRect { min_x: 0, min_y: 0, max_x: width - 1, max_y: height - 1 };
+    let mut covered = Vec::new();
+    for face in shell.indices.chunks_exact(3) {
+        let [a, b, c] = [face[0], face[1], face[2]].map(|i| &vertices[i as usize]);
+        let centroid = (a.world_position + b.world_position + c.world_position) * (1.0 / 3.0);
+        if centroid.dot(&(eye - centroid)) <= 0.0 {
+            continue;
+        }
+        triangle(a, b, c, clip, |sample| {
+            let (x, y) = (sample.x as u32, sample.y as u32);
+            if framebuffer.depth_test(x, y, sample.depth) {
+                covered.push((x, y, sample.fragment().world_position));
+            }
+        });
+    }
+    covered
+}
+
+/// Distances along a ray (unit direction) to where it enters and leaves a sphere
+/// centered at the origin, or `None` if it misses
+pub fn ray_sphere(origin: Vector3, direction: Vector3, radius: f32) -> Option<(f32, f32)> {
+    let b = origin.dot(&direction);
+    let c = origin.dot(&origin) - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    Some((-b - root, -b + root))
+}
+
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
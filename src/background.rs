@@ -0,0 +1,412 @@
+use crate::framebuffer::Framebuffer;
+use crate::matrix::Matrix;
+use crate::shaders::{blackbody_color, fbm_3d, ShaderColor};
+use crate::tone_mapping::srgb_decode;
+use crate::vector::Vector3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use raylib::prelude::Image;
+use std::f32::consts::PI;
+
+/// Output height the star sizes are tuned for; taller (or supersampled) targets scale them up
+const REFERENCE_HEIGHT: f32 = 768.0;
+/// Total light of a magnitude 0 star, spread over its few pixels
+const ZERO_MAGNITUDE_BRIGHTNESS: f32 = 30.0;
+/// The nebula is evaluated every this many pixels and interpolated in between
+const NEBULA_STEP: usize = 4;
+
+/// What is drawn behind the scene. Everything is looked up by view direction only,
+/// so it sits at infinity: it turns with the camera but does not move when it pans.
+pub enum Background {
+    Solid(ShaderColor),
+    Starfield(Starfield),
+    Skybox(Skybox),
+}
+
+impl Background {
+    /// The whole sky baked into an equirectangular map of `width` x `width / 2` texels,
+    /// for shaders that reflect or refract it (stars stay still in it: no twinkle)
+    pub fn environment_map(&self, width: usize) -> EnvironmentMap {
+        let height = (width / 2).max(1);
+        let mut pixels: Vec<ShaderColor> = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let u = ((i % width) as f32 + 0.5) / width as f32;
+                let v = ((i / width) as f32 + 0.5) / height as f32;
+                let direction = equirect_direction(u, v);
+                match self {
+                    Background::Solid(color) => *color,
+                    Background::Starfield(starfield) => {
+                        starfield.nebula.as_ref().map_or(ShaderColor::BLACK, |nebula| nebula.color(direction))
+                    }
+                    Background::Skybox(skybox) => skybox.sample(direction),
+                }
+            })
+            .collect();
+
+        // Each star lands whole in the texel it points to
+        if let Background::Starfield(starfield) = self {
+            for star in &starfield.stars {
+                let (u, v) = equirect_uv(star.direction);
+                let x = ((u * width as f32) as usize).min(width - 1);
+                let y = ((v * height as f32) as usize).min(height - 1);
+                let texel = &mut pixels[y * width + x];
+                texel.r += star.color.r;
+                texel.g += star.color.g;
+                texel.b += star.color.b;
+            }
+        }
+
+        EnvironmentMap { width, height, pixels }
+    }
+
+    /// Fills the color buffer behind everything; leaves the z-buffer untouched
+    pub fn render(&self, framebuffer: &mut Framebuffer, view_matrix: &Matrix, proj_matrix: &Matrix, time: f32) {
+        let rays = ViewRays::new(view_matrix, proj_matrix, framebuffer.width as usize, framebuffer.height as usize);
+        match self {
+            Background::Solid(color) => framebuffer.hdr.fill(*color),
+            Background::Starfield(starfield) => starfield.render(framebuffer, &rays, time),
+            Background::Skybox(skybox) => {
+                let width = rays.width;
+                framebuffer.hdr.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+                    for (x, color) in row.iter_mut().enumerate() {
+                        *color = skybox.sample(rays.direction(x as f32 + 0.5, y as f32 + 0.5));
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// World-space direction through any pixel, rebuilt from the camera matrices
+pub struct ViewRays {
+    right: Vector3,
+    up: Vector3,
+    forward: Vector3,
+    /// Inverse of the projection's x and y scale: NDC to view-space slope
+    slope_x: f32,
+    slope_y: f32,
+    width: usize,
+    height: usize,
+}
+
+impl ViewRays {
+    pub fn new(view_matrix: &Matrix, proj_matrix: &Matrix, width: usize, height: usize) -> Self {
+        let row = |i: usize| Vector3::new(view_matrix.data[i][0], view_matrix.data[i][1], view_matrix.data[i][2]);
+        ViewRays {
+            right: row(0),
+            up: row(1),
+            forward: -row(2),
+            slope_x: 1.0 / proj_matrix.data[0][0],
+            slope_y: 1.0 / proj_matrix.data[1][1],
+            width,
+            height,
+        }
+    }
+
+    pub fn direction(&self, x: f32, y: f32) -> Vector3 {
+        let ndc_x = 2.0 * x / self.width as f32 - 1.0;
+        let ndc_y = 1.0 - 2.0 * y / self.height as f32;
+        (self.right * (ndc_x * self.slope_x) + self.up * (ndc_y * self.slope_y) + self.forward).normalize()
+    }
+
+    /// Pixel where a direction lands, or `None` if it points behind the camera
+    fn project(&self, direction: Vector3) -> Option<(f32, f32)> {
+        let depth = direction.dot(&self.forward);
+        if depth <= 1e-4 {
+            return None;
+        }
+        let ndc_x = direction.dot(&self.right) / (depth * self.slope_x);
+        let ndc_y = direction.dot(&self.up) / (depth * self.slope_y);
+        Some(((ndc_x + 1.0) * 0.5 * self.width as f32, (1.0 - ndc_y) * 0.5 * self.height as f32))
+    }
+}
+
+struct Star {
+    direction: Vector3,
+    /// Linear brightness from the apparent magnitude, already tinted
+    color: ShaderColor,
+    twinkle_phase: f32,
+    twinkle_speed: f32,
+}
+
+/// Faint clouds of gas concentrated along a band of the sky, like a galactic plane
+#[derive(Debug, Clone, Copy)]
+pub struct Nebula {
+    pub first_color: ShaderColor,
+    pub second_color: ShaderColor,
+    pub intensity: f32,
+    /// Pole of the band the nebula gathers around (unit)
+    pub band_pole: Vector3,
+}
+
+impl Default for Nebula {
+    fn default() -> Self {
+        Nebula {
+            first_color: ShaderColor::new(0.45, 0.15, 0.55, 1.0),
+            second_color: ShaderColor::new(0.1, 0.35, 0.55, 1.0),
+            intensity: 0.25,
+            band_pole: Vector3::new(0.3, 1.0, 0.2).normalize(),
+        }
+    }
+}
+
+impl Nebula {
+    fn color(&self, direction: Vector3) -> ShaderColor {
+        let band = (-(direction.dot(&self.band_pole) / 0.35).powi(2)).exp();
+        let density = fbm_3d(direction * 3.0 + Vector3::new(17.0, 3.0, 5.0), 4);
+        let density = ((density - 0.35) / 0.45).clamp(0.0, 1.0).powi(2) * (0.2 + 0.8 * band);
+        let tint = fbm_3d(direction * 1.5, 2).clamp(0.0, 1.0);
+        let amount = density * self.intensity;
+        ShaderColor::new(
+            (self.first_color.r + (self.second_color.r - self.first_color.r) * tint) * amount,
+            (self.first_color.g + (self.second_color.g - self.first_color.g) * tint) * amount,
+            (self.first_color.b + (self.second_color.b - self.first_color.b) * tint) * amount,
+            1.0,
+        )
+    }
+}
+
+/// Seeded stars spread uniformly over the sky, many faint ones and few bright ones,
+/// colored by their temperature, optionally over a nebula
+pub struct Starfield {
+    stars: Vec<Star>,
+    pub nebula: Option<Nebula>,
+    /// Relative flicker of the brightness, 0 = steady
+    pub twinkle: f32,
+}
+
+impl Starfield {
+    pub fn new(seed: u64, count: usize) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Share of stars per spectral class (M, K, G, F, A, B), most are cool and red
+        let classes = [(3200.0, 0.45), (4500.0, 0.25), (5800.0, 0.13), (6800.0, 0.09), (9000.0, 0.06), (15000.0, 0.02)];
+        let (brightest, faintest) = (-1.0f32, 6.5f32);
+
+        let stars = (0..count)
+            .map(|_| {
+                let z: f32 = rng.gen_range(-1.0..1.0);
+                let angle = rng.gen_range(0.0..2.0 * PI);
+                let ring = (1.0 - z * z).sqrt();
+                let direction = Vector3::new(ring * angle.cos(), z, ring * angle.sin());
+
+                // The count of stars brighter than m grows as 10^(0.6 m): invert that distribution
+                let (low, high) = (10f32.powf(0.6 * brightest), 10f32.powf(0.6 * faintest));
+                let magnitude = (low + rng.gen::<f32>() * (high - low)).log10() / 0.6;
+                let brightness = ZERO_MAGNITUDE_BRIGHTNESS * 10f32.powf(-0.4 * magnitude);
+
+                let mut pick = rng.gen::<f32>();
+                let temperature = classes
+                    .iter()
+                    .find(|&&(_, share)| {
+                        pick -= share;
+                        pick <= 0.0
+                    })
+                    .map_or(5800.0, |&(kelvin, _)| kelvin)
+                    * rng.gen_range(0.9..1.1);
+                let tint = blackbody_color(temperature);
+
+                Star {
+                    direction,
+                    color: ShaderColor::new(tint.r * brightness, tint.g * brightness, tint.b * brightness, 1.0),
+                    twinkle_phase: rng.gen_range(0.0..2.0 * PI),
+                    twinkle_speed: rng.gen_range(1.5..5.0),
+                }
+            })
+            .collect();
+
+        Starfield { stars, nebula: Some(Nebula::default()), twinkle: 0.3 }
+    }
+
+    fn render(&self, framebuffer: &mut Framebuffer, rays: &ViewRays, time: f32) {
+        if let Some(nebula) = &self.nebula {
+            render_nebula(framebuffer, rays, nebula);
+        }
+
+        // Each star is a small Gaussian whose total energy is its brightness
+        let scale = rays.height as f32 / REFERENCE_HEIGHT;
+        let sigma = 0.6 * scale;
+        let reach = (sigma * 2.5).ceil() as i32;
+        let normalization = 1.0 / (2.0 * PI * sigma * sigma);
+        for star in &self.stars {
+            let Some((sx, sy)) = rays.project(star.direction) else {
+                continue;
+            };
+            let flicker = 1.0 + self.twinkle * (time * star.twinkle_speed + star.twinkle_phase).sin();
+            let (cx, cy) = (sx.floor() as i32, sy.floor() as i32);
+            for y in cy - reach..=cy + reach {
+                for x in cx - reach..=cx + reach {
+                    if x < 0 || y < 0 || x >= rays.width as i32 || y >= rays.height as i32 {
+                        continue;
+                    }
+                    let (dx, dy) = (x as f32 + 0.5 - sx, y as f32 + 0.5 - sy);
+                    let weight = (-(dx * dx + dy * dy) / (2.0 * sigma * sigma)).exp() * normalization * flicker;
+                    let pixel = &mut framebuffer.hdr[y as usize * rays.width + x as usize];
+                    pixel.r += star.color.r * weight;
+                    pixel.g += star.color.g * weight;
+                    pixel.b += star.color.b * weight;
+                }
+            }
+        }
+    }
+}
+
+/// Evaluates the nebula on a coarse grid and interpolates it over the pixels
+fn render_nebula(framebuffer: &mut Framebuffer, rays: &ViewRays, nebula: &Nebula) {
+    let grid_width = rays.width.div_ceil(NEBULA_STEP) + 1;
+    let grid_height = rays.height.div_ceil(NEBULA_STEP) + 1;
+    let grid: Vec<ShaderColor> = (0..grid_width * grid_height)
+        .into_par_iter()
+        .map(|i| {
+            let (gx, gy) = ((i % grid_width) * NEBULA_STEP, (i / grid_width) * NEBULA_STEP);
+            nebula.color(rays.direction(gx as f32, gy as f32))
+        })
+        .collect();
+
+    framebuffer.hdr.par_chunks_mut(rays.width).enumerate().for_each(|(y, row)| {
+        let (gy, ty) = (y / NEBULA_STEP, (y % NEBULA_STEP) as f32 / NEBULA_STEP as f32);
+        for (x, color) in row.iter_mut().enumerate() {
+            let (gx, tx) = (x / NEBULA_STEP, (x % NEBULA_STEP) as f32 / NEBULA_STEP as f32);
+            let at = |ix: usize, iy: usize| grid[iy * grid_width + ix];
+            let top = lerp_color(at(gx, gy), at(gx + 1, gy), tx);
+            let bottom = lerp_color(at(gx, gy + 1), at(gx + 1, gy + 1), tx);
+            let nebula_color = lerp_color(top, bottom, ty);
+            color.r += nebula_color.r;
+            color.g += nebula_color.g;
+            color.b += nebula_color.b;
+        }
+    });
+}
+
+/// How the faces of the sky are laid out in a skybox image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkyboxLayout {
+    /// Longitude along x, latitude along y (2:1)
+    Equirectangular,
+    /// Cubemap unfolded as a horizontal cross (4:3): -X +Z +X -Z in the middle row,
+    /// +Y above and -Y below +Z
+    Cross,
+}
+
+/// Sky image looked up by direction
+pub struct Skybox {
+    width: usize,
+    height: usize,
+    /// Linear colors (the image is decoded from sRGB)
+    pixels: Vec<ShaderColor>,
+    layout: SkyboxLayout,
+    /// Multiplies the image, to sit it under the lit scene
+    pub intensity: f32,
+}
+
+impl Skybox {
+    /// Loads an equirectangular (2:1) or horizontal cross cubemap (4:3) image
+    pub fn load(path: &str) -> Result<Self, String> {
+        let image = Image::load_image(path).map_err(|e| format!("Failed to load skybox {}: {}", path, e))?;
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let layout = if width == height * 2 {
+            SkyboxLayout::Equirectangular
+        } else if width * 3 == height * 4 {
+            SkyboxLayout::Cross
+        } else {
+            return Err(format!("Skybox must be 2:1 (equirectangular) or 4:3 (cross cubemap), got {}x{}", width, height));
+        };
+        let pixels = image
+            .get_image_data()
+            .iter()
+            .map(|c| ShaderColor::new(srgb_decode(c.r as f32 / 255.0), srgb_decode(c.g as f32 / 255.0), srgb_decode(c.b as f32 / 255.0), 1.0))
+            .collect();
+        Ok(Skybox { width, height, pixels, layout, intensity: 0.5 })
+    }
+
+    fn sample(&self, direction: Vector3) -> ShaderColor {
+        let color = match self.layout {
+            SkyboxLayout::Equirectangular => {
+                let (u, v) = equirect_uv(direction);
+                self.bilinear(u * self.width as f32, v * self.height as f32, (0, 0, self.width, self.height))
+            }
+            SkyboxLayout::Cross => {
+                let (face_x, face_y, s, t) = cube_face(direction);
+                let face = self.width / 4;
+                let rect = (face_x * face, face_y * face, face, face);
+                self.bilinear((face_x * face) as f32 + s * face as f32, (face_y * face) as f32 + t * face as f32, rect)
+            }
+        };
+        ShaderColor::new(color.r * self.intensity, color.g * self.intensity, color.b * self.intensity, 1.0)
+    }
+
+    /// Bilinear read at pixel coordinates, clamped to a (x, y, width, height) rect so
+    /// cube faces do not bleed into their neighbours in the image
+    fn bilinear(&self, x: f32, y: f32, rect: (usize, usize, usize, usize)) -> ShaderColor {
+        let (rx, ry, rw, rh) = rect;
+        let fx = (x - 0.5).clamp(rx as f32, (rx + rw - 1) as f32);
+        let fy = (y - 0.5).clamp(ry as f32, (ry + rh - 1) as f32);
+        let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(rx + rw - 1), (y0 + 1).min(ry + rh - 1));
+        let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+
+        let at = |px: usize, py: usize| self.pixels[py * self.width + px];
+        let top = lerp_color(at(x0, y0), at(x1, y0), tx);
+        let bottom = lerp_color(at(x0, y1), at(x1, y1), tx);
+        lerp_color(top, bottom, ty)
+    }
+}
+
+/// Background radiance by direction, stored as a small equirectangular image
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<ShaderColor>,
+}
+
+impl EnvironmentMap {
+    /// Bilinear lookup; wraps around in longitude
+    pub fn sample(&self, direction: Vector3) -> ShaderColor {
+        let (u, v) = equirect_uv(direction.normalize());
+        let fx = u * self.width as f32 - 0.5;
+        let fy = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (fx.floor(), fy.floor() as usize);
+        let (tx, ty) = (fx - x0, fy - y0 as f32);
+        let x0 = x0.rem_euclid(self.width as f32) as usize % self.width;
+        let x1 = (x0 + 1) % self.width;
+        let y1 = (y0 + 1).min(self.height - 1);
+
+        let at = |x: usize, y: usize| self.pixels[y * self.width + x];
+        let top = lerp_color(at(x0, y0), at(x1, y0), tx);
+        let bottom = lerp_color(at(x0, y1), at(x1, y1), tx);
+        lerp_color(top, bottom, ty)
+    }
+}
+
+/// Longitude and latitude of a unit direction in [0, 1], with -Z at the center
+fn equirect_uv(direction: Vector3) -> (f32, f32) {
+    let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+    let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+    (u, v)
+}
+
+fn equirect_direction(u: f32, v: f32) -> Vector3 {
+    let longitude = (u - 0.5) * 2.0 * PI;
+    let polar = v * PI;
+    Vector3::new(polar.sin() * longitude.sin(), polar.cos(), -polar.sin() * longitude.cos())
+}
+
+/// Cell of the horizontal cross a direction falls in, and where in that face (0-1)
+fn cube_face(d: Vector3) -> (usize, usize, f32, f32) {
+    let (ax, ay, az) = (d.x.abs(), d.y.abs(), d.z.abs());
+    let (cell, s, t, major) = if ax >= ay && ax >= az {
+        if d.x > 0.0 { ((2, 1), -d.z, -d.y, ax) } else { ((0, 1), d.z, -d.y, ax) }
+    } else if ay >= az {
+        if d.y > 0.0 { ((1, 0), d.x, d.z, ay) } else { ((1, 2), d.x, -d.z, ay) }
+    } else if d.z > 0.0 {
+        ((1, 1), d.x, -d.y, az)
+    } else {
+        ((3, 1), -d.x, -d.y, az)
+    };
+    (cell.0, cell.1, (s / major + 1.0) * 0.5, (t / major + 1.0) * 0.5)
+}
+
+fn lerp_color(a: ShaderColor, b: ShaderColor, t: f32) -> ShaderColor {
+    ShaderColor::new(a.r + (b.r - a.r) * t, a.g + (b.g - a.g) * t, a.b + (b.b - a.b) * t, 1.0)
+}
@@ -0,0 +1,205 @@
+use crate::post_process::{PostBuffers, PostEffect, PostStage};
+use crate::shaders::ShaderColor;
+use rayon::prelude::*;
+
+/// Normalized 9-tap Gaussian (sigma ~2), center weight first
+const GAUSSIAN_WEIGHTS: [f32; 5] = [0.227_027, 0.194_594_6, 0.121_621_6, 0.054_054, 0.016_216];
+
+/// Glow around the brightest parts of the HDR image: bright-pass at half resolution,
+/// Gaussian blur on a chain of smaller and smaller copies, and the sum of all of them
+/// added back on top of the color buffer before tone mapping.
+#[derive(Debug, Clone, Copy)]
+pub struct Bloom {
+    /// Luminance above which pixels start to glow
+    pub threshold: f32,
+    /// Width of the soft transition below the threshold, so the glow does not pop in
+    pub knee: f32,
+    /// Strength of the glow added back to the image
+    pub intensity: f32,
+    /// Number of blurred levels; each one halves the resolution of the previous
+    pub levels: usize,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom { threshold: 1.0, knee: 0.5, intensity: 0.5, levels: 5 }
+    }
+}
+
+impl PostEffect for Bloom {
+    fn name(&self) -> &'static str {
+        "bloom"
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Hdr
+    }
+
+    fn apply(&self, buffers: &mut PostBuffers) {
+        if self.intensity <= 0.0 || self.levels == 0 {
+            return;
+        }
+
+        let (width, height) = (buffers.width, buffers.height);
+        let mut chain = vec![self.bright_pass(buffers.color, width, height)];
+        while chain.len() < self.levels {
+            let last = &chain[chain.len() - 1];
+            if last.width < 2 || last.height < 2 {
+                break;
+            }
+            let smaller = last.downsample();
+            chain.push(smaller);
+        }
+        for level in &mut chain {
+            level.blur();
+        }
+
+        // Smallest to largest: each level carries the wider glow of those below it
+        while chain.len() > 1 {
+            let smaller = chain.pop().unwrap();
+            chain.last_mut().unwrap().add_upsampled(&smaller);
+        }
+        let glow = &chain[0];
+
+        buffers.color.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            let v = (y as f32 + 0.5) / height as f32;
+            for (x, color) in row.iter_mut().enumerate() {
+                let [r, g, b] = glow.sample((x as f32 + 0.5) / width as f32, v);
+                color.r += r * self.intensity;
+                color.g += g * self.intensity;
+                color.b += b * self.intensity;
+            }
+        });
+    }
+}
+
+impl Bloom {
+    /// Half-resolution copy keeping only the light above the threshold
+    fn bright_pass(&self, source: &[ShaderColor], source_width: usize, source_height: usize) -> Level {
+        let width = source_width.div_ceil(2);
+        let height = source_height.div_ceil(2);
+        let mut data = vec![[0.0; 3]; width * height];
+
+        data.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, texel) in row.iter_mut().enumerate() {
+                let mut sum = [0.0f32; 3];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (x * 2 + dx).min(source_width - 1);
+                    let sy = (y * 2 + dy).min(source_height - 1);
+                    let color = source[sy * source_width + sx];
+                    sum[0] += color.r.max(0.0);
+                    sum[1] += color.g.max(0.0);
+                    sum[2] += color.b.max(0.0);
+                }
+                let average = sum.map(|channel| channel * 0.25);
+                let weight = self.contribution(luminance(average));
+                *texel = average.map(|channel| channel * weight);
+            }
+        });
+
+        Level { width, height, data }
+    }
+
+    /// Fraction of a pixel with luminance `lum` that goes into the glow (soft knee)
+    fn contribution(&self, lum: f32) -> f32 {
+        let knee = self.knee.max(1e-4);
+        let soft = (lum - self.threshold + knee).clamp(0.0, 2.0 * knee);
+        let soft = soft * soft / (4.0 * knee);
+        soft.max(lum - self.threshold) / lum.max(1e-4)
+    }
+}
+
+fn luminance(color: [f32; 3]) -> f32 {
+    color[0] * 0.2126 + color[1] * 0.7152 + color[2] * 0.0722
+}
+
+/// One level of the blur chain, in linear RGB
+struct Level {
+    width: usize,
+    height: usize,
+    data: Vec<[f32; 3]>,
+}
+
+impl Level {
+    fn texel(&self, x: isize, y: isize) -> [f32; 3] {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.data[y * self.width + x]
+    }
+
+    /// Half the size, averaging 2x2 blocks
+    fn downsample(&self) -> Level {
+        let width = self.width.div_ceil(2);
+        let height = self.height.div_ceil(2);
+        let mut data = vec![[0.0; 3]; width * height];
+        data.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, texel) in row.iter_mut().enumerate() {
+                let (sx, sy) = (x as isize * 2, y as isize * 2);
+                let mut sum = [0.0f32; 3];
+                for source in [self.texel(sx, sy), self.texel(sx + 1, sy), self.texel(sx, sy + 1), self.texel(sx + 1, sy + 1)] {
+                    add_scaled(&mut sum, source, 0.25);
+                }
+                *texel = sum;
+            }
+        });
+        Level { width, height, data }
+    }
+
+    /// Separable Gaussian: horizontal pass, then vertical pass
+    fn blur(&mut self) {
+        self.data = self.convolve(1, 0);
+        self.data = self.convolve(0, 1);
+    }
+
+    fn convolve(&self, step_x: isize, step_y: isize) -> Vec<[f32; 3]> {
+        let mut output = vec![[0.0; 3]; self.data.len()];
+        output.par_chunks_mut(self.width).enumerate().for_each(|(y, row)| {
+            for (x, texel) in row.iter_mut().enumerate() {
+                let (x, y) = (x as isize, y as isize);
+                let mut sum = self.texel(x, y).map(|channel| channel * GAUSSIAN_WEIGHTS[0]);
+                for (offset, &weight) in GAUSSIAN_WEIGHTS.iter().enumerate().skip(1) {
+                    let offset = offset as isize;
+                    let before = self.texel(x - offset * step_x, y - offset * step_y);
+                    let after = self.texel(x + offset * step_x, y + offset * step_y);
+                    add_scaled(&mut sum, before, weight);
+                    add_scaled(&mut sum, after, weight);
+                }
+                *texel = sum;
+            }
+        });
+        output
+    }
+
+    /// Bilinear sample at normalized coordinates, texel centers at (i + 0.5) / size
+    fn sample(&self, u: f32, v: f32) -> [f32; 3] {
+        let fx = u * self.width as f32 - 0.5;
+        let fy = v * self.height as f32 - 0.5;
+        let (x0, y0) = (fx.floor(), fy.floor());
+        let (tx, ty) = (fx - x0, fy - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+
+        let top = lerp(self.texel(x0, y0), self.texel(x0 + 1, y0), tx);
+        let bottom = lerp(self.texel(x0, y0 + 1), self.texel(x0 + 1, y0 + 1), tx);
+        lerp(top, bottom, ty)
+    }
+
+    fn add_upsampled(&mut self, smaller: &Level) {
+        let (width, height) = (self.width, self.height);
+        self.data.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            let v = (y as f32 + 0.5) / height as f32;
+            for (x, texel) in row.iter_mut().enumerate() {
+                add_scaled(texel, smaller.sample((x as f32 + 0.5) / width as f32, v), 1.0);
+            }
+        });
+    }
+}
+
+fn add_scaled(sum: &mut [f32; 3], color: [f32; 3], weight: f32) {
+    sum[0] += color[0] * weight;
+    sum[1] += color[1] * weight;
+    sum[2] += color[2] * weight;
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
@@ -0,0 +1,307 @@
+use crate::vector::Vector3;
+
+/// Primitives per leaf; below this splitting costs more traversal than it saves
+const MAX_LEAF_SIZE: usize = 4;
+
+/// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Aabb {
+    /// Box that contains nothing; growing it by any point gives that point
+    pub fn empty() -> Self {
+        Aabb {
+            min: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vector3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points(points: &[Vector3]) -> Self {
+        points.iter().fold(Aabb::empty(), |bounds, &point| bounds.grow(point))
+    }
+
+    pub fn grow(&self, point: Vector3) -> Self {
+        Aabb {
+            min: Vector3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z)),
+            max: Vector3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z)),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        self.grow(other.min).grow(other.max)
+    }
+
+    pub fn centroid(&self) -> Vector3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Distance along the ray where it enters the box, if it does before `t_max`
+    /// (slab test; 0 when the origin is inside)
+    fn entry(&self, ray: &Ray, t_max: f32) -> Option<f32> {
+        let (mut near, mut far) = (0.0f32, t_max);
+        for axis in 0..3 {
+            let t0 = (axis_of(self.min, axis) - axis_of(ray.origin, axis)) * axis_of(ray.inverse_direction, axis);
+            let t1 = (axis_of(self.max, axis) - axis_of(ray.origin, axis)) * axis_of(ray.inverse_direction, axis);
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        (near <= far).then_some(near)
+    }
+}
+
+/// Ray with the reciprocal of its direction precomputed for the slab tests
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vector3,
+    pub direction: Vector3,
+    inverse_direction: Vector3,
+}
+
+impl Ray {
+    pub fn new(origin: Vector3, direction: Vector3) -> Self {
+        Ray {
+            origin,
+            direction,
+            inverse_direction: Vector3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> Vector3 {
+        self.origin + self.direction * distance
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum NodeKind {
+    /// Range of `Bvh::order` holding the primitives of the leaf
+    Leaf { first: usize, count: usize },
+    /// The left child always follows its parent; only the right one is stored
+    Interior { right: usize },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    kind: NodeKind,
+}
+
+/// Bounding volume hierarchy over any set of primitives, built from their boxes.
+/// It only knows boxes and indices; the caller intersects the primitives themselves,
+/// so triangles and spheres can share one tree.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Primitive indices, grouped so every leaf is a contiguous range
+    order: Vec<usize>,
+}
+
+impl Bvh {
+    /// Splits at the median centroid along the widest axis until leaves are small
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut bvh = Bvh { nodes: Vec::with_capacity(bounds.len() * 2), order: (0..bounds.len()).collect() };
+        if !bounds.is_empty() {
+            bvh.build_node(bounds, 0, bounds.len());
+        }
+        bvh
+    }
+
+    fn build_node(&mut self, bounds: &[Aabb], first: usize, count: usize) -> usize {
+        let primitives = &mut self.order[first..first + count];
+        let node_bounds = primitives.iter().fold(Aabb::empty(), |total, &i| total.union(&bounds[i]));
+        let index = self.nodes.len();
+        self.nodes.push(Node { bounds: node_bounds, kind: NodeKind::Leaf { first, count } });
+        if count <= MAX_LEAF_SIZE {
+            return index;
+        }
+
+        let centroids = primitives.iter().fold(Aabb::empty(), |total, &i| total.grow(bounds[i].centroid()));
+        let extent = centroids.max - centroids.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let half = count / 2;
+        primitives.select_nth_unstable_by(half, |&a, &b| {
+            axis_of(bounds[a].centroid(), axis).total_cmp(&axis_of(bounds[b].centroid(), axis))
+        });
+
+        self.build_node(bounds, first, half);
+        let right = self.build_node(bounds, first + half, count - half);
+        self.nodes[index].kind = NodeKind::Interior { right };
+        index
+    }
+
+    /// Closest primitive along the ray before `t_max`. `intersect` gets a primitive
+    /// index and returns the distance to it, if the ray hits it.
+    pub fn closest<F>(&self, ray: &Ray, t_max: f32, intersect: F) -> Option<(usize, f32)>
+    where
+        F: Fn(usize) -> Option<f32>,
+    {
+        let mut best: Option<(usize, f32)> = None;
+        self.traverse(ray, t_max, |primitive, limit| {
+            if let Some(distance) = intersect(primitive).filter(|&distance| distance < limit) {
+                best = Some((primitive, distance));
+                return Some(distance);
+            }
+            None
+        });
+        best
+    }
+
+    /// Whether any primitive is hit before `t_max`; stops at the first one found
+    pub fn any_hit<F>(&self, ray: &Ray, t_max: f32, intersect: F) -> bool
+    where
+        F: Fn(usize) -> Option<f32>,
+    {
+        let mut found = false;
+        self.traverse(ray, t_max, |primitive, limit| {
+            if intersect(primitive).is_some_and(|distance| distance < limit) {
+                found = true;
+                return Some(0.0);
+            }
+            None
+        });
+        found
+    }
+
+    /// Visits the leaves the ray reaches, nearest child first. `visit` returns a new,
+    /// shorter limit when it finds a hit, which prunes everything farther away.
+    fn traverse<F>(&self, ray: &Ray, t_max: f32, mut visit: F)
+    where
+        F: FnMut(usize, f32) -> Option<f32>,
+    {
+        let Some(root_entry) = self.nodes.first().and_then(|root| root.bounds.entry(ray, t_max)) else {
+            return;
+        };
+        let mut limit = t_max;
+        let mut stack = vec![(0usize, root_entry)];
+        while let Some((index, entry)) = stack.pop() {
+            if entry >= limit {
+                continue;
+            }
+            match self.nodes[index].kind {
+                NodeKind::Leaf { first, count } => {
+                    for &primitive in &self.order[first..first + count] {
+                        if let Some(distance) = visit(primitive, limit) {
+                            limit = distance;
+                        }
+                    }
+                }
+                NodeKind::Interior { right } => {
+                    let left = index + 1;
+                    let left_entry = self.nodes[left].bounds.entry(ray, limit);
+                    let right_entry = self.nodes[right].bounds.entry(ray, limit);
+                    // Pushed farthest first so the nearest child is popped next
+                    match (left_entry, right_entry) {
+                        (Some(l), Some(r)) if l <= r => stack.extend([(right, r), (left, l)]),
+                        (Some(l), Some(r)) => stack.extend([(left, l), (right, r)]),
+                        (Some(l), None) => stack.push((left, l)),
+                        (None, Some(r)) => stack.push((right, r)),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn axis_of(v: Vector3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_point(rng: &mut StdRng, extent: f32) -> Vector3 {
+        Vector3::new(rng.gen_range(-extent..extent), rng.gen_range(-extent..extent), rng.gen_range(-extent..extent))
+    }
+
+    /// Möller-Trumbore; the distance along the ray to the triangle, if it is hit in front
+    fn intersect_triangle(ray: &Ray, [a, b, c]: &[Vector3; 3]) -> Option<f32> {
+        let (edge1, edge2) = (*b - *a, *c - *a);
+        let p = ray.direction.cross(&edge2);
+        let det = edge1.dot(&p);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+        let to_origin = ray.origin - *a;
+        let u = to_origin.dot(&p) / det;
+        let q = to_origin.cross(&edge1);
+        let v = ray.direction.dot(&q) / det;
+        if u < 0.0 || v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        Some(edge2.dot(&q) / det).filter(|&t| t > 0.0)
+    }
+
+    fn random_scene(rng: &mut StdRng) -> Vec<[Vector3; 3]> {
+        (0..300)
+            .map(|_| {
+                let center = random_point(rng, 5.0);
+                [center + random_point(rng, 0.6), center + random_point(rng, 0.6), center + random_point(rng, 0.6)]
+            })
+            .collect()
+    }
+
+    fn random_ray(rng: &mut StdRng) -> Ray {
+        let origin = random_point(rng, 8.0);
+        // Aimed at the cluster so most rays hit something
+        let direction = (random_point(rng, 3.0) - origin).normalize();
+        Ray::new(origin, direction)
+    }
+
+    #[test]
+    fn closest_hit_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(47);
+        let triangles = random_scene(&mut rng);
+        let bounds: Vec<Aabb> = triangles.iter().map(|triangle| Aabb::from_points(triangle)).collect();
+        let bvh = Bvh::build(&bounds);
+
+        let mut hits = 0;
+        for _ in 0..500 {
+            let ray = random_ray(&mut rng);
+            let expected = triangles
+                .iter()
+                .enumerate()
+                .filter_map(|(i, triangle)| intersect_triangle(&ray, triangle).map(|t| (i, t)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let found = bvh.closest(&ray, f32::INFINITY, |i| intersect_triangle(&ray, &triangles[i]));
+            match (found, expected) {
+                (Some((_, t)), Some((_, expected_t))) => {
+                    assert!((t - expected_t).abs() < 1e-5, "{} vs {}", t, expected_t);
+                    hits += 1;
+                }
+                (None, None) => {}
+                _ => panic!("bvh {:?} vs brute force {:?}", found, expected),
+            }
+        }
+        assert!(hits > 100, "only {} rays hit, the test is not exercising the tree", hits);
+    }
+
+    #[test]
+    fn any_hit_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(48);
+        let triangles = random_scene(&mut rng);
+        let bounds: Vec<Aabb> = triangles.iter().map(|triangle| Aabb::from_points(triangle)).collect();
+        let bvh = Bvh::build(&bounds);
+
+        for _ in 0..500 {
+            let ray = random_ray(&mut rng);
+            let t_max = rng.gen_range(1.0..15.0);
+            let expected = triangles.iter().any(|triangle| intersect_triangle(&ray, triangle).is_some_and(|t| t < t_max));
+            assert_eq!(bvh.any_hit(&ray, t_max, |i| intersect_triangle(&ray, &triangles[i])), expected);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::atmosphere::front_shell_pixels;
+use crate::framebuffer::Framebuffer;
+use crate::matrix::{Matrix, ScreenTransform};
+use crate::quaternion::Quaternion;
+use crate::shaders::{CloudShader, ShaderColor, ShaderUniforms};
+use crate::sphere::Mesh;
+use crate::vector::Vector3;
+use rayon::prelude::*;
+
+/// Shell of clouds above a planet, with its own shader and its own rotation.
+/// It is blended over the planet after the opaque geometry, and it can darken the
+/// surface under it through `ShaderUniforms::cloud_shadow`.
+pub struct CloudLayer {
+    pub shader: CloudShader,
+    /// Rotation around Y in rad/s, independent from the planet's
+    pub rotation_speed: f32,
+    pub rotation: f32,
+    /// Tilt of the spin axis in radians, the same as the planet's
+    pub axial_tilt: f32,
+    /// Whether the clouds darken the surface below them
+    pub casts_shadows: bool,
+    /// Radius of the shell (fixed: the shell mesh is built with it)
+    radius: f32,
+    shell: Mesh,
+}
+
+impl CloudLayer {
+    pub fn new(shader: CloudShader, radius: f32, rotation_speed: f32, casts_shadows: bool) -> Self {
+        CloudLayer {
+            shader,
+            rotation_speed,
+            rotation: 0.0,
+            axial_tilt: 0.0,
+            casts_shadows,
+            radius,
+            shell: Mesh::create_sphere(radius, 32, 32),
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.rotation += self.rotation_speed * dt;
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Undoes the layer's rotation, taking world directions to its own frame
+    fn to_local(&self) -> Matrix {
+        Quaternion::tilted_spin(self.axial_tilt, self.rotation).conjugate().to_matrix()
+    }
+
+    /// Color of the clouds at a point of the shell, or `None` where the sky is clear
+    /// (the pixel is left untouched)
+    pub fn shade(&self, shell_point: Vector3, uniforms: &ShaderUniforms) -> Option<ShaderColor> {
+        let normal = shell_point.normalize();
+        let coverage = self.shader.coverage(self.to_local().transform_direction(&normal), uniforms.time);
+        (coverage > 0.0).then(|| self.shader.fragment_shader(normal * self.radius, normal, coverage, uniforms))
+    }
+
+    /// What the surface shaders need to find the clouds between them and the light,
+    /// or `None` if the layer does not cast shadows
+    pub fn shadow_caster(&self) -> Option<CloudShadow> {
+        self.casts_shadows.then(|| CloudShadow {
+            shader: self.shader,
+            radius: self.radius,
+            to_local: self.to_local(),
+        })
+    }
+
+    /// Blends the clouds over every pixel covered by the front of the shell
+    pub fn render(&self, framebuffer: &mut Framebuffer, screen: &ScreenTransform, uniforms: &ShaderUniforms) {
+        let mvp = screen.world_to_screen();
+        let covered = front_shell_pixels(&self.shell, framebuffer, &mvp, uniforms.camera_position, screen.width, screen.height);
+
+        let colors: Vec<Option<ShaderColor>> = covered
+            .par_iter()
+            .map(|&(_, _, shell_point)| self.shade(shell_point, uniforms))
+            .collect();
+        for (&(x, y, _), color) in covered.iter().zip(colors) {
+            if let Some(color) = color {
+                framebuffer.blend_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+/// Copy of a cloud layer's state taken each frame, so shaders can tell how much
+/// light the clouds let through towards a point below them
+#[derive(Debug, Clone, Copy)]
+pub struct CloudShadow {
+    shader: CloudShader,
+    radius: f32,
+    /// Undoes the layer's rotation
+    to_local: Matrix,
+}
+
+impl CloudShadow {
+    /// Fraction of the light coming from `light_dir` (unit, towards the light) that
+    /// gets through the clouds to `position`. Points on or above the layer are lit.
+    pub fn transmittance(&self, position: Vector3, light_dir: Vector3, time: f32) -> f32 {
+        if position.length() >= self.radius * 0.999 {
+            return 1.0;
+        }
+        // Where the ray towards the light leaves the shell (the point is inside it)
+        let b = position.dot(&light_dir);
+        let c = position.dot(&position) - self.radius * self.radius;
+        let exit = -b + (b * b - c).max(0.0).sqrt();
+        let crossing = (position + light_dir * exit).normalize();
+
+        1.0 - self.shader.coverage(self.to_local.transform_direction(&crossing), time) * self.shader.opacity
+    }
+}
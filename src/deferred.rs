@@ -0,0 +1,205 @@
+use crate::fragment::{triangle, ClipRect};
+use crate::framebuffer::{Framebuffer, GBuffer};
+use crate::shaders::{shade_surface, MaterialId, ShaderColor, ShaderUniforms, SurfaceSample};
+use crate::tile_rasterizer::ScreenTriangle;
+use crate::vector::Vector3;
+use rayon::prelude::*;
+
+/// What the deferred path shows: the lit image or one raw G-buffer plane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GBufferChannel {
+    Lit,
+    Albedo,
+    Normal,
+    WorldPosition,
+    Emission,
+    Specular,
+    Material,
+    Depth,
+}
+
+impl GBufferChannel {
+    pub const ALL: [GBufferChannel; 8] = [
+        GBufferChannel::Lit,
+        GBufferChannel::Albedo,
+        GBufferChannel::Normal,
+        GBufferChannel::WorldPosition,
+        GBufferChannel::Emission,
+        GBufferChannel::Specular,
+        GBufferChannel::Material,
+        GBufferChannel::Depth,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GBufferChannel::Lit => "lit",
+            GBufferChannel::Albedo => "albedo",
+            GBufferChannel::Normal => "normal",
+            GBufferChannel::WorldPosition => "position",
+            GBufferChannel::Emission => "emission",
+            GBufferChannel::Specular => "specular",
+            GBufferChannel::Material => "material",
+            GBufferChannel::Depth => "depth",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|channel| channel.name() == name)
+    }
+
+    /// Next channel in display order, wrapping around
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&channel| channel == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// Rasterizes shaded triangles into the G-buffer instead of the color buffer.
+/// `surfaces` holds the unlit surface of each triangle corner, interpolated per pixel.
+/// Hidden pixels are rejected by the depth test before interpolating anything.
+pub fn rasterize_gbuffer(framebuffer: &mut Framebuffer, triangles: &[ScreenTriangle], surfaces: &[[SurfaceSample; 3]]) {
+    let clip = ClipRect {
+        min_x: 0,
+        min_y: 0,
+        max_x: framebuffer.width as i32 - 1,
+        max_y: framebuffer.height as i32 - 1,
+    };
+
+    for ([v1, v2, v3], [s1, s2, s3]) in triangles.iter().zip(surfaces) {
+        triangle(v1, v2, v3, clip, |sample| {
+            let (x, y) = (sample.x as u32, sample.y as u32);
+            if !framebuffer.depth_test(x, y, sample.depth) {
+                return;
+            }
+
+            let (w, v, u) = sample.weights();
+            let mix = |a: ShaderColor, b: ShaderColor, c: ShaderColor| {
+                ShaderColor::new(
+                    w * a.r + v * b.r + u * c.r,
+                    w * a.g + v * b.g + u * c.g,
+                    w * a.b + v * b.b + u * c.b,
+                    w * a.a + v * b.a + u * c.a,
+                )
+            };
+            let surface = SurfaceSample {
+                albedo: mix(s1.albedo, s2.albedo, s3.albedo),
+                emission: mix(s1.emission, s2.emission, s3.emission),
+                specular: w * s1.specular + v * s2.specular + u * s3.specular,
+                material: s1.material,
+                lighting: s1.lighting,
+            };
+
+            let fragment = sample.fragment();
+            framebuffer.set_surface_with_depth(x, y, sample.depth, &surface, fragment.normal, fragment.world_position);
+        });
+    }
+}
+
+/// Fills the color buffer from the G-buffer: the lit image, or the chosen plane
+/// as a false-color debug view. Does nothing if deferred mode is off.
+pub fn resolve_gbuffer(framebuffer: &mut Framebuffer, uniforms: &ShaderUniforms, channel: GBufferChannel) {
+    match channel {
+        GBufferChannel::Lit => lighting_pass(framebuffer, uniforms),
+        GBufferChannel::Depth => show_depth(framebuffer),
+        channel => show_channel(framebuffer, channel),
+    }
+}
+
+/// Evaluates every light once per covered pixel, with pixels shaded in parallel.
+/// Background pixels keep whatever the color buffer already holds.
+pub fn lighting_pass(framebuffer: &mut Framebuffer, uniforms: &ShaderUniforms) {
+    let Some(gbuffer) = framebuffer.gbuffer.as_ref() else {
+        return;
+    };
+
+    framebuffer.hdr.par_iter_mut().enumerate().for_each(|(index, pixel)| {
+        let material = gbuffer.material[index];
+        if material == MaterialId::None {
+            return;
+        }
+
+        let surface = SurfaceSample {
+            albedo: gbuffer.albedo[index],
+            emission: gbuffer.emission[index],
+            specular: gbuffer.specular[index],
+            material,
+            lighting: gbuffer.lighting[index],
+        };
+        // Shadows are sampled per pixel here, not per vertex as in forward mode
+        *pixel = shade_surface(&surface, gbuffer.world_position[index], gbuffer.normal[index], uniforms);
+    });
+}
+
+/// Writes one G-buffer plane to the color buffer, mapped to visible colors
+fn show_channel(framebuffer: &mut Framebuffer, channel: GBufferChannel) {
+    let Some(gbuffer) = framebuffer.gbuffer.as_ref() else {
+        return;
+    };
+
+    framebuffer.hdr.par_iter_mut().enumerate().for_each(|(index, pixel)| {
+        if gbuffer.material[index] == MaterialId::None {
+            return;
+        }
+        *pixel = channel_color(gbuffer, index, channel);
+    });
+}
+
+fn channel_color(gbuffer: &GBuffer, index: usize, channel: GBufferChannel) -> ShaderColor {
+    let opaque = |r: f32, g: f32, b: f32| ShaderColor::new(r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0), 1.0);
+    // Maps [-range, range] to [0, 1]
+    let signed = |v: Vector3, range: f32| opaque(v.x / range * 0.5 + 0.5, v.y / range * 0.5 + 0.5, v.z / range * 0.5 + 0.5);
+
+    match channel {
+        GBufferChannel::Albedo => {
+            let albedo = gbuffer.albedo[index];
+            opaque(albedo.r, albedo.g, albedo.b)
+        }
+        GBufferChannel::Normal => signed(gbuffer.normal[index], 1.0),
+        GBufferChannel::WorldPosition => signed(gbuffer.world_position[index], 3.0),
+        GBufferChannel::Emission => {
+            let emission = gbuffer.emission[index];
+            opaque(emission.r, emission.g, emission.b)
+        }
+        GBufferChannel::Specular => {
+            let specular = gbuffer.specular[index];
+            opaque(specular, specular, specular)
+        }
+        GBufferChannel::Material => match gbuffer.material[index] {
+            MaterialId::None => opaque(0.0, 0.0, 0.0),
+            MaterialId::Rocky => opaque(0.6, 0.4, 0.2),
+            MaterialId::GasGiant => opaque(1.0, 0.6, 0.0),
+            MaterialId::Crystal => opaque(0.3, 0.8, 1.0),
+            MaterialId::Lava => opaque(1.0, 0.1, 0.0),
+            MaterialId::RingParticle => opaque(0.8, 0.8, 0.8),
+            MaterialId::Moon => opaque(0.4, 0.4, 1.0),
+            MaterialId::Star => opaque(1.0, 1.0, 0.6),
+        },
+        GBufferChannel::Lit | GBufferChannel::Depth => opaque(0.0, 0.0, 0.0),
+    }
+}
+
+/// Depth as grayscale, stretched over the range actually covered this frame
+/// (near is white)
+fn show_depth(framebuffer: &mut Framebuffer) {
+    let Some(gbuffer) = framebuffer.gbuffer.as_ref() else {
+        return;
+    };
+
+    let covered = |index: usize| gbuffer.material[index] != MaterialId::None;
+    let (nearest, farthest) = framebuffer
+        .zbuffer
+        .iter()
+        .enumerate()
+        .filter(|&(index, depth)| covered(index) && depth.is_finite())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(near, far), (_, &depth)| (near.min(depth), far.max(depth)));
+    let range = (farthest - nearest).max(f32::EPSILON);
+
+    let zbuffer = &framebuffer.zbuffer;
+    framebuffer.hdr.par_iter_mut().enumerate().for_each(|(index, pixel)| {
+        if !covered(index) {
+            return;
+        }
+        let value = (1.0 - (zbuffer[index] - nearest) / range).clamp(0.0, 1.0);
+        *pixel = ShaderColor::new(value, value, value, 1.0);
+    });
+}
@@ -50,103 +50,346 @@ pub struct TransformedVertex {
     pub uv: (f32, f32),           // Coordenadas UV
 }
 
-/// Calcula las coordenadas baricéntricas de un punto P respecto a un triángulo ABC
-/// Retorna (w, v, u) donde w, v, u son los pesos baricéntricos
-pub fn barycentric_coordinates(
-    p_x: f32,
-    p_y: f32,
-    a: &TransformedVertex,
-    b: &TransformedVertex,
-    c: &TransformedVertex,
-) -> (f32, f32, f32) {
-    let a_x = a.screen_position.x;
-    let b_x = b.screen_position.x;
-    let c_x = c.screen_position.x;
-    let a_y = a.screen_position.y;
-    let b_y = b.screen_position.y;
-    let c_y = c.screen_position.y;
-
-    // Calcular el área del triángulo
-    let area = (b_y - c_y) * (a_x - c_x) + (c_x - b_x) * (a_y - c_y);
-
-    // Si el área es muy pequeña, el triángulo es degenerado
-    if area.abs() < 1e-10 {
-        return (-1.0, -1.0, -1.0);
-    }
-
-    // Calcular los pesos baricéntricos
-    let w = ((b_y - c_y) * (p_x - c_x) + (c_x - b_x) * (p_y - c_y)) / area;
-    let v = ((c_y - a_y) * (p_x - c_x) + (a_x - c_x) * (p_y - c_y)) / area;
-    let u = 1.0 - w - v;
-
-    (w, v, u)
+/// Rectángulo de pixeles (inclusivo) al que se limita la rasterización
+#[derive(Debug, Clone, Copy)]
+pub struct ClipRect {
+    pub min_x: i32,
+    pub min_y: i32,
+    pub max_x: i32,
+    pub max_y: i32,
 }
 
-/// Rasteriza un triángulo y genera fragmentos
-/// Usa el algoritmo de escaneo con coordenadas baricéntricas
-pub fn triangle(
-    v1: &TransformedVertex,
-    v2: &TransformedVertex,
-    v3: &TransformedVertex,
-) -> Vec<Fragment> {
-    let mut fragments = Vec::new();
-
-    let a_x = v1.screen_position.x;
-    let b_x = v2.screen_position.x;
-    let c_x = v3.screen_position.x;
-    let a_y = v1.screen_position.y;
-    let b_y = v2.screen_position.y;
-    let c_y = v3.screen_position.y;
-
-    // Calcular el bounding box del triángulo
-    let min_x = a_x.min(b_x).min(c_x).floor() as i32;
-    let min_y = a_y.min(b_y).min(c_y).floor() as i32;
-    let max_x = a_x.max(b_x).max(c_x).ceil() as i32;
-    let max_y = a_y.max(b_y).max(c_y).ceil() as i32;
-
-    // Iterar sobre cada pixel en el bounding box
+/// Bits de precisión subpixel de las coordenadas en punto fijo (1/16 de pixel)
+const SUBPIXEL_BITS: i32 = 4;
+const SUBPIXEL_ONE: i64 = 1 << SUBPIXEL_BITS;
+
+/// Coordenadas más allá de este rango (en pixeles) se consideran inválidas
+const MAX_SCREEN_COORD: f32 = (1 << 20) as f32;
+
+/// Función de arista en punto fijo para el lado a -> b.
+/// Se evalúa en el centro del primer pixel y luego se avanza sumando los pasos.
+struct EdgeFunction {
+    step_x: i64,
+    step_y: i64,
+    row_start: i64,
+    /// 0 en aristas superiores/izquierdas, -1 en las demás: así un pixel
+    /// exactamente sobre la arista solo lo dibuja uno de los dos triángulos
+    bias: i64,
+}
+
+impl EdgeFunction {
+    fn new(a: (i64, i64), b: (i64, i64), origin: (i64, i64), orientation: i64) -> Self {
+        // E(p) = (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x),
+        // multiplicada por la orientación para que el interior sea positivo
+        let step_x = -(b.1 - a.1) * orientation;
+        let step_y = (b.0 - a.0) * orientation;
+        let value = ((b.0 - a.0) * (origin.1 - a.1) - (b.1 - a.1) * (origin.0 - a.0)) * orientation;
+
+        // Con y hacia abajo, la normal interior apunta a +x en una arista izquierda
+        // y a +y en una arista superior horizontal
+        let top_left = step_x > 0 || (step_x == 0 && step_y > 0);
+
+        EdgeFunction {
+            step_x: step_x * SUBPIXEL_ONE,
+            step_y: step_y * SUBPIXEL_ONE,
+            row_start: value,
+            bias: if top_left { 0 } else { -1 },
+        }
+    }
+}
+
+fn to_fixed(v: &TransformedVertex) -> (i64, i64) {
+    let scale = SUBPIXEL_ONE as f32;
+    (
+        (v.screen_position.x * scale).round() as i64,
+        (v.screen_position.y * scale).round() as i64,
+    )
+}
+
+/// Pixel cubierto por un triángulo. La posición y la profundidad ya están calculadas;
+/// el resto de atributos solo se interpola al pedir `fragment()`, de modo que el
+/// depth test puede hacerse antes (early-z) y los pixeles ocultos no se sombrean.
+pub struct RasterSample<'a> {
+    pub x: i32,
+    pub y: i32,
+    pub depth: f32,
+    weights: (f32, f32, f32),
+    vertices: [&'a TransformedVertex; 3],
+}
+
+impl RasterSample<'_> {
+    /// Pesos baricéntricos (w, v, u) de los tres vértices en este pixel
+    pub fn weights(&self) -> (f32, f32, f32) {
+        self.weights
+    }
+
+    /// Interpola color, posición del mundo y normal en este pixel
+    pub fn fragment(&self) -> Fragment {
+        let (w, v, u) = self.weights;
+        let [v1, v2, v3] = self.vertices;
+
+        // Interpolar el color
+        let color = ShaderColor::new(
+            w * v1.color.r + v * v2.color.r + u * v3.color.r,
+            w * v1.color.g + v * v2.color.g + u * v3.color.g,
+            w * v1.color.b + v * v2.color.b + u * v3.color.b,
+            w * v1.color.a + v * v2.color.a + u * v3.color.a,
+        );
+
+        // Interpolar la posición del mundo
+        let world_pos = v1.world_position * w + v2.world_position * v + v3.world_position * u;
+
+        // Interpolar la normal
+        let normal = (v1.normal * w + v2.normal * v + v3.normal * u).normalize();
+
+        Fragment::new_with_data(self.x as f32, self.y as f32, color, self.depth, world_pos, normal)
+    }
+}
+
+/// Rasteriza un triángulo con funciones de arista incrementales y llama a `emit`
+/// con cada pixel cubierto dentro de `clip`, sin reservar memoria.
+///
+/// Los vértices se ajustan a una rejilla subpixel en punto fijo, así que las aristas
+/// compartidas se evalúan exactamente igual desde ambos triángulos; la regla
+/// top-left decide a cuál pertenece un pixel que cae justo sobre la arista.
+/// Acepta ambos sentidos de giro.
+pub fn triangle<'a, F: FnMut(RasterSample<'a>)>(
+    v1: &'a TransformedVertex,
+    v2: &'a TransformedVertex,
+    v3: &'a TransformedVertex,
+    clip: ClipRect,
+    mut emit: F,
+) {
+    let coords = [v1, v2, v3].map(|v| (v.screen_position.x, v.screen_position.y));
+    if coords.iter().any(|&(x, y)| !(x.abs() < MAX_SCREEN_COORD && y.abs() < MAX_SCREEN_COORD)) {
+        return;
+    }
+
+    let a = to_fixed(v1);
+    let b = to_fixed(v2);
+    let c = to_fixed(v3);
+
+    // Doble del área con signo; cero significa triángulo degenerado
+    let area = (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0);
+    if area == 0 {
+        return;
+    }
+    let orientation = area.signum();
+    let inv_area = 1.0 / (area * orientation) as f32;
+
+    // Bounding box en pixeles, recortado al rectángulo
+    let min_x = (a.0.min(b.0).min(c.0) >> SUBPIXEL_BITS).max(clip.min_x as i64);
+    let min_y = (a.1.min(b.1).min(c.1) >> SUBPIXEL_BITS).max(clip.min_y as i64);
+    let max_x = (a.0.max(b.0).max(c.0) >> SUBPIXEL_BITS).min(clip.max_x as i64);
+    let max_y = (a.1.max(b.1).max(c.1) >> SUBPIXEL_BITS).min(clip.max_y as i64);
+    if min_x > max_x || min_y > max_y {
+        return;
+    }
+
+    // Centro del primer pixel en punto fijo
+    let origin = (
+        (min_x << SUBPIXEL_BITS) + SUBPIXEL_ONE / 2,
+        (min_y << SUBPIXEL_BITS) + SUBPIXEL_ONE / 2,
+    );
+
+    // Cada arista da el peso del vértice opuesto
+    let mut e1 = EdgeFunction::new(b, c, origin, orientation);
+    let mut e2 = EdgeFunction::new(c, a, origin, orientation);
+    let mut e3 = EdgeFunction::new(a, b, origin, orientation);
+
     for y in min_y..=max_y {
+        let mut w1 = e1.row_start;
+        let mut w2 = e2.row_start;
+        let mut w3 = e3.row_start;
+
         for x in min_x..=max_x {
-            let (w, v, u) = barycentric_coordinates(x as f32 + 0.5, y as f32 + 0.5, v1, v2, v3);
+            if (w1 + e1.bias) | (w2 + e2.bias) | (w3 + e3.bias) >= 0 {
+                let weights = (w1 as f32 * inv_area, w2 as f32 * inv_area, w3 as f32 * inv_area);
 
-            // Si el punto está dentro del triángulo (todas las coordenadas baricéntricas son positivas)
-            if w >= 0.0 && v >= 0.0 && u >= 0.0 {
                 // Interpolar la profundidad usando coordenadas baricéntricas
-                let depth = w * v1.screen_position.z + v * v2.screen_position.z + u * v3.screen_position.z;
-
-                // Interpolar el color
-                let color = ShaderColor::new(
-                    w * v1.color.r + v * v2.color.r + u * v3.color.r,
-                    w * v1.color.g + v * v2.color.g + u * v3.color.g,
-                    w * v1.color.b + v * v2.color.b + u * v3.color.b,
-                    w * v1.color.a + v * v2.color.a + u * v3.color.a,
-                );
-
-                // Interpolar la posición del mundo
-                let world_pos = Vector3::new(
-                    w * v1.world_position.x + v * v2.world_position.x + u * v3.world_position.x,
-                    w * v1.world_position.y + v * v2.world_position.y + u * v3.world_position.y,
-                    w * v1.world_position.z + v * v2.world_position.z + u * v3.world_position.z,
-                );
-
-                // Interpolar la normal
-                let normal = Vector3::new(
-                    w * v1.normal.x + v * v2.normal.x + u * v3.normal.x,
-                    w * v1.normal.y + v * v2.normal.y + u * v3.normal.y,
-                    w * v1.normal.z + v * v2.normal.z + u * v3.normal.z,
-                ).normalize();
-
-                fragments.push(Fragment::new_with_data(
-                    x as f32,
-                    y as f32,
-                    color,
+                let depth = weights.0 * v1.screen_position.z + weights.1 * v2.screen_position.z + weights.2 * v3.screen_position.z;
+
+                emit(RasterSample {
+                    x: x as i32,
+                    y: y as i32,
                     depth,
-                    world_pos,
-                    normal,
-                ));
+                    weights,
+                    vertices: [v1, v2, v3],
+                });
+            }
+
+            w1 += e1.step_x;
+            w2 += e2.step_x;
+            w3 += e3.step_x;
+        }
+
+        e1.row_start += e1.step_y;
+        e2.row_start += e2.step_y;
+        e3.row_start += e3.step_y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn vertex(x: f32, y: f32, z: f32) -> TransformedVertex {
+        TransformedVertex {
+            screen_position: Vector3::new(x, y, z),
+            world_position: Vector3::new(x, y, z),
+            normal: Vector3::new(0.0, 0.0, 1.0),
+            color: ShaderColor::new(1.0, 1.0, 1.0, 1.0),
+            uv: (0.0, 0.0),
+        }
+    }
+
+    /// Coordenada en la rejilla subpixel, para caer a menudo justo en centros de pixel
+    fn snapped(rng: &mut StdRng, min: f32, max: f32) -> f32 {
+        (rng.gen_range(min..max) * SUBPIXEL_ONE as f32).round() / SUBPIXEL_ONE as f32
+    }
+
+    /// Calcula las coordenadas baricéntricas de un punto P respecto a un triángulo ABC
+    /// Retorna (w, v, u) donde w, v, u son los pesos baricéntricos
+    fn barycentric_coordinates(
+        p_x: f32,
+        p_y: f32,
+        a: &TransformedVertex,
+        b: &TransformedVertex,
+        c: &TransformedVertex,
+    ) -> (f32, f32, f32) {
+        let a_x = a.screen_position.x;
+        let b_x = b.screen_position.x;
+        let c_x = c.screen_position.x;
+        let a_y = a.screen_position.y;
+        let b_y = b.screen_position.y;
+        let c_y = c.screen_position.y;
+
+        // Calcular el área del triángulo
+        let area = (b_y - c_y) * (a_x - c_x) + (c_x - b_x) * (a_y - c_y);
+
+        // Si el área es muy pequeña, el triángulo es degenerado
+        if area.abs() < 1e-10 {
+            return (-1.0, -1.0, -1.0);
+        }
+
+        // Calcular los pesos baricéntricos
+        let w = ((b_y - c_y) * (p_x - c_x) + (c_x - b_x) * (p_y - c_y)) / area;
+        let v = ((c_y - a_y) * (p_x - c_x) + (a_x - c_x) * (p_y - c_y)) / area;
+        let u = 1.0 - w - v;
+
+        (w, v, u)
+    }
+
+    const CLIP: ClipRect = ClipRect { min_x: 0, min_y: 0, max_x: 127, max_y: 127 };
+
+    #[test]
+    fn barycentric_weights_sum_to_one_and_rebuild_the_point() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for _ in 0..500 {
+            let [a, b, c] = [0; 3].map(|_| vertex(rng.gen_range(0.0..100.0), rng.gen_range(0.0..100.0), 0.0));
+            let (p_x, p_y) = (rng.gen_range(-20.0..120.0), rng.gen_range(-20.0..120.0));
+            let (w, v, u) = barycentric_coordinates(p_x, p_y, &a, &b, &c);
+            if (w, v, u) == (-1.0, -1.0, -1.0) {
+                continue;
+            }
+            assert!((w + v + u - 1.0).abs() < 1e-4);
+            let x = w * a.screen_position.x + v * b.screen_position.x + u * c.screen_position.x;
+            let y = w * a.screen_position.y + v * b.screen_position.y + u * c.screen_position.y;
+            // Triángulos muy finos amplifican el error de redondeo
+            let area = ((b.screen_position - a.screen_position).cross(&(c.screen_position - a.screen_position))).length();
+            if area > 10.0 {
+                assert!((x - p_x).abs() < 0.05 && (y - p_y).abs() < 0.05, "({}, {}) vs ({}, {})", x, y, p_x, p_y);
             }
         }
     }
 
-    fragments
+    #[test]
+    fn raster_weights_are_convex_and_interpolate_depth() {
+        let mut rng = StdRng::seed_from_u64(12);
+        for _ in 0..300 {
+            let [a, b, c] = [0; 3].map(|_| vertex(snapped(&mut rng, 0.0, 128.0), snapped(&mut rng, 0.0, 128.0), rng.gen_range(-1.0..1.0)));
+            triangle(&a, &b, &c, CLIP, |sample| {
+                let (w, v, u) = sample.weights();
+                assert!(w >= 0.0 && v >= 0.0 && u >= 0.0, "negative weight in ({}, {})", sample.x, sample.y);
+                assert!((w + v + u - 1.0).abs() < 1e-4, "weights sum to {}", w + v + u);
+                let depth = w * a.screen_position.z + v * b.screen_position.z + u * c.screen_position.z;
+                assert!((sample.depth - depth).abs() < 1e-5);
+                let z_range = a.screen_position.z.min(b.screen_position.z).min(c.screen_position.z) - 1e-5
+                    ..=a.screen_position.z.max(b.screen_position.z).max(c.screen_position.z) + 1e-5;
+                assert!(z_range.contains(&sample.depth));
+            });
+        }
+    }
+
+    #[test]
+    fn shared_edges_are_watertight() {
+        // Rejilla de vértices desplazados al azar, dos triángulos por celda con la
+        // diagonal y el sentido de giro al azar: cada pixel del interior de la malla
+        // debe dibujarse exactamente una vez, ni huecos ni pixeles dobles
+        let mut rng = StdRng::seed_from_u64(13);
+        const CELLS: usize = 12;
+        const CELL_SIZE: f32 = 9.0;
+        // Menos de un cuarto de celda, para que ningún cuadrilátero se vuelva cóncavo
+        const JITTER: f32 = 2.0;
+
+        for _ in 0..20 {
+            let grid: Vec<Vec<TransformedVertex>> = (0..=CELLS)
+                .map(|j| {
+                    (0..=CELLS)
+                        .map(|i| {
+                            let x = 8.0 + i as f32 * CELL_SIZE + snapped(&mut rng, -JITTER, JITTER) + 0.5;
+                            let y = 8.0 + j as f32 * CELL_SIZE + snapped(&mut rng, -JITTER, JITTER) + 0.5;
+                            vertex(x, y, 0.0)
+                        })
+                        .collect()
+                })
+                .collect();
+
+            let mut coverage = vec![0u32; 128 * 128];
+            let mut draw = |a: &TransformedVertex, b: &TransformedVertex, c: &TransformedVertex| {
+                triangle(a, b, c, CLIP, |sample| coverage[sample.y as usize * 128 + sample.x as usize] += 1);
+            };
+            for j in 0..CELLS {
+                for i in 0..CELLS {
+                    let (p00, p10, p01, p11) = (&grid[j][i], &grid[j][i + 1], &grid[j + 1][i], &grid[j + 1][i + 1]);
+                    let halves = if rng.gen_bool(0.5) {
+                        [[p00, p10, p11], [p00, p11, p01]]
+                    } else {
+                        [[p00, p10, p01], [p10, p11, p01]]
+                    };
+                    for [a, b, c] in halves {
+                        if rng.gen_bool(0.5) {
+                            draw(a, b, c);
+                        } else {
+                            draw(a, c, b);
+                        }
+                    }
+                }
+            }
+
+            // Fuera del alcance del desplazamiento del borde, todo pixel está dentro
+            let inner_min = (8.0 + JITTER + 1.0) as usize;
+            let inner_max = (8.0 + CELLS as f32 * CELL_SIZE - JITTER - 1.0) as usize;
+            for (index, &count) in coverage.iter().enumerate() {
+                let (x, y) = (index % 128, index / 128);
+                assert!(count <= 1, "pixel ({}, {}) drawn {} times", x, y, count);
+                if (inner_min..=inner_max).contains(&x) && (inner_min..=inner_max).contains(&y) {
+                    assert_eq!(count, 1, "hole at pixel ({}, {})", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_coordinates_are_skipped() {
+        let (a, b) = (vertex(10.0, 10.0, 0.0), vertex(50.0, 10.0, 0.0));
+        for bad in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 1e9] {
+            let c = vertex(bad, 40.0, 0.0);
+            let mut drawn = 0;
+            triangle(&a, &b, &c, CLIP, |_| drawn += 1);
+            assert_eq!(drawn, 0, "drew pixels with a vertex at x = {}", bad);
+        }
+    }
 }
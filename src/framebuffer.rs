@@ -1,6 +1,51 @@
 use raylib::prelude::*;
+use crate::shaders::{MaterialId, ShaderColor, SurfaceLighting, SurfaceSample};
+use crate::tone_mapping::ToneMapping;
+use crate::vector::Vector3;
+use rayon::prelude::*;
+
+/// Lado en pixeles de un bloque del nivel más fino del z-buffer jerárquico
+pub const HIZ_BLOCK_SIZE: u32 = 8;
+
+/// Un nivel del z-buffer jerárquico: la profundidad más lejana de cada bloque
+struct DepthLevel {
+    width: u32,
+    height: u32,
+    max_depth: Vec<f32>,
+}
+
+/// Planos del G-buffer del modo deferred: la superficie de cada pixel antes de
+/// iluminarla. El pase de luces los lee una sola vez por pixel.
+pub struct GBuffer {
+    pub albedo: Vec<ShaderColor>,
+    pub normal: Vec<Vector3>,
+    pub world_position: Vec<Vector3>,
+    pub emission: Vec<ShaderColor>,
+    pub specular: Vec<f32>,
+    pub material: Vec<MaterialId>, // MaterialId::None = fondo
+    pub lighting: Vec<SurfaceLighting>,
+}
+
+impl GBuffer {
+    fn new(total_pixels: usize) -> Self {
+        let black = ShaderColor::new(0.0, 0.0, 0.0, 0.0);
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        GBuffer {
+            albedo: vec![black; total_pixels],
+            normal: vec![zero; total_pixels],
+            world_position: vec![zero; total_pixels],
+            emission: vec![black; total_pixels],
+            specular: vec![0.0; total_pixels],
+            material: vec![MaterialId::None; total_pixels],
+            lighting: vec![SurfaceLighting::MATTE; total_pixels],
+        }
+    }
+}
 
 pub struct Framebuffer {
+    /// Color en punto flotante (HDR, lineal) donde se dibuja todo; puede pasar de 1
+    pub hdr: Vec<ShaderColor>,
+    /// Imagen de 8 bits que se muestra, calculada desde `hdr` con `resolve`
     pub pixels: Vec<Color>,
     pub width: u32,
     pub height: u32,
@@ -8,6 +53,10 @@ pub struct Framebuffer {
     pub background_color: Color,
     texture: Option<Texture2D>,
     pub zbuffer: Vec<f32>, // Z-buffer para profundidad
+    // Z-buffer jerárquico: bloques de 8x8 pixeles y luego 2x2 bloques por nivel
+    depth_levels: Vec<DepthLevel>,
+    // Solo existe en modo deferred
+    pub gbuffer: Option<GBuffer>,
 }
 
 impl Framebuffer {
@@ -15,6 +64,7 @@ impl Framebuffer {
     pub fn new(width: u32, height: u32) -> Self {
         let total_pixels = (width * height) as usize;
         Self {
+            hdr: vec![ShaderColor::BLACK; total_pixels],
             pixels: vec![Color::BLACK; total_pixels],
             width,
             height,
@@ -22,13 +72,139 @@ impl Framebuffer {
             background_color: Color::BLACK,
             texture: None,
             zbuffer: vec![f32::INFINITY; total_pixels],
+            depth_levels: Self::create_depth_levels(width, height),
+            gbuffer: None,
+        }
+    }
+
+    fn create_depth_levels(width: u32, height: u32) -> Vec<DepthLevel> {
+        let mut levels = Vec::new();
+        let mut level_width = width.div_ceil(HIZ_BLOCK_SIZE).max(1);
+        let mut level_height = height.div_ceil(HIZ_BLOCK_SIZE).max(1);
+        loop {
+            levels.push(DepthLevel {
+                width: level_width,
+                height: level_height,
+                max_depth: vec![f32::INFINITY; (level_width * level_height) as usize],
+            });
+            if level_width == 1 && level_height == 1 {
+                return levels;
+            }
+            level_width = level_width.div_ceil(2);
+            level_height = level_height.div_ceil(2);
         }
     }
 
     /// Limpia el framebuffer con un color específico
     pub fn clear(&mut self, color: Color) {
+        self.hdr.fill(ShaderColor::from_rgb(color.r, color.g, color.b));
         self.pixels.fill(color);
         self.zbuffer.fill(f32::INFINITY);
+        for level in &mut self.depth_levels {
+            level.max_depth.fill(f32::INFINITY);
+        }
+        // Basta con marcar todo como fondo, el resto de planos se sobrescribe al rasterizar
+        if let Some(gbuffer) = &mut self.gbuffer {
+            gbuffer.material.fill(MaterialId::None);
+        }
+    }
+
+    /// Activa o desactiva el modo deferred, reservando o liberando los planos del G-buffer
+    pub fn set_deferred(&mut self, enabled: bool) {
+        if enabled && self.gbuffer.is_none() {
+            self.gbuffer = Some(GBuffer::new((self.width * self.height) as usize));
+        } else if !enabled {
+            self.gbuffer = None;
+        }
+    }
+
+    /// Early depth test: indica si un fragmento a esta profundidad pasaría el z-buffer,
+    /// para decidir antes de sombrearlo
+    pub fn depth_test(&self, x: u32, y: u32, depth: f32) -> bool {
+        x < self.width && y < self.height && depth < self.zbuffer[(y * self.width + x) as usize]
+    }
+
+    /// Reconstruye el z-buffer jerárquico a partir del z-buffer.
+    /// Las escrituras posteriores solo acercan la profundidad, así que entre
+    /// reconstrucciones la jerarquía sigue siendo conservadora (nunca oculta de más).
+    pub fn update_depth_hierarchy(&mut self) {
+        let block = HIZ_BLOCK_SIZE as usize;
+        let width = self.width as usize;
+        let height = self.height as usize;
+
+        let finest = &mut self.depth_levels[0];
+        let level_width = finest.width as usize;
+        finest.max_depth.fill(f32::NEG_INFINITY);
+        for y in 0..height {
+            let row = &self.zbuffer[y * width..(y + 1) * width];
+            let blocks = &mut finest.max_depth[(y / block) * level_width..(y / block + 1) * level_width];
+            for (bx, depths) in row.chunks(block).enumerate() {
+                let farthest = depths.iter().fold(blocks[bx], |a, &b| a.max(b));
+                blocks[bx] = farthest;
+            }
+        }
+
+        for level in 1..self.depth_levels.len() {
+            let (coarser, finer) = self.depth_levels.split_at_mut(level);
+            let child = &coarser[level - 1];
+            let parent = &mut finer[0];
+            for by in 0..parent.height {
+                for bx in 0..parent.width {
+                    let mut farthest = f32::NEG_INFINITY;
+                    for cy in (by * 2)..(by * 2 + 2).min(child.height) {
+                        for cx in (bx * 2)..(bx * 2 + 2).min(child.width) {
+                            farthest = farthest.max(child.max_depth[(cy * child.width + cx) as usize]);
+                        }
+                    }
+                    parent.max_depth[(by * parent.width + bx) as usize] = farthest;
+                }
+            }
+        }
+    }
+
+    /// Consulta el z-buffer jerárquico: devuelve `true` si nada con profundidad
+    /// `nearest_depth` o mayor dentro del rectángulo (inclusivo, en pixeles) puede pasar
+    /// el depth test, de modo que la primitiva completa se puede descartar sin rasterizar.
+    /// Un rectángulo fuera de la pantalla también se considera oculto.
+    pub fn is_hidden(&self, min_x: i32, min_y: i32, max_x: i32, max_y: i32, nearest_depth: f32) -> bool {
+        let min_x = min_x.max(0);
+        let min_y = min_y.max(0);
+        let max_x = max_x.min(self.width as i32 - 1);
+        let max_y = max_y.min(self.height as i32 - 1);
+        if min_x > max_x || min_y > max_y {
+            return true;
+        }
+
+        let block = HIZ_BLOCK_SIZE as i32;
+        let rect = [min_x / block, min_y / block, max_x / block, max_y / block];
+        let top = self.depth_levels.len() - 1;
+        self.is_hidden_in_block(top, 0, 0, rect, nearest_depth)
+    }
+
+    /// Desciende por la jerarquía solo en los bloques que no bastan para ocultar
+    fn is_hidden_in_block(&self, level: usize, bx: u32, by: u32, rect: [i32; 4], nearest_depth: f32) -> bool {
+        let depth_level = &self.depth_levels[level];
+        if nearest_depth >= depth_level.max_depth[(by * depth_level.width + bx) as usize] {
+            return true;
+        }
+        if level == 0 {
+            return false;
+        }
+
+        // Rectángulo expresado en bloques del nivel inferior
+        let shift = level - 1;
+        let child = &self.depth_levels[level - 1];
+        let child_rect = [rect[0] >> shift, rect[1] >> shift, rect[2] >> shift, rect[3] >> shift];
+        for cy in (by * 2)..(by * 2 + 2).min(child.height) {
+            for cx in (bx * 2)..(bx * 2 + 2).min(child.width) {
+                let overlaps = cx as i32 >= child_rect[0] && cx as i32 <= child_rect[2] &&
+                               cy as i32 >= child_rect[1] && cy as i32 <= child_rect[3];
+                if overlaps && !self.is_hidden_in_block(level - 1, cx, cy, rect, nearest_depth) {
+                    return false;
+                }
+            }
+        }
+        true
     }
 
     pub fn set_background_color(&mut self, color: Color) {
@@ -41,27 +217,63 @@ impl Framebuffer {
 
     /// Establecer un pixel con el color actual
     pub fn set_pixel(&mut self, x: u32, y: u32) {
-        if x < self.width && y < self.height {
-            let index = (y * self.width + x) as usize;
-            self.pixels[index] = self.current_color;
-        }
+        self.set_pixel_color(x, y, self.current_color);
     }
 
     /// Establecer un pixel con un color específico
     pub fn set_pixel_color(&mut self, x: u32, y: u32, color: Color) {
         if x < self.width && y < self.height {
             let index = (y * self.width + x) as usize;
-            self.pixels[index] = color;
+            self.hdr[index] = ShaderColor::from_rgb(color.r, color.g, color.b);
         }
     }
 
     /// Establecer un pixel con profundidad (z-buffer test)
-    pub fn set_pixel_with_depth(&mut self, x: u32, y: u32, color: Color, depth: f32) {
+    pub fn set_pixel_with_depth(&mut self, x: u32, y: u32, color: ShaderColor, depth: f32) {
+        if x < self.width && y < self.height {
+            let index = (y * self.width + x) as usize;
+            if depth < self.zbuffer[index] {
+                self.hdr[index] = color;
+                self.zbuffer[index] = depth;
+            }
+        }
+    }
+
+    /// Mezclar un color sobre el pixel (operador "over"), sin tocar el z-buffer: para
+    /// capas transparentes como la atmósfera. El color viene premultiplicado por su alpha.
+    pub fn blend_pixel(&mut self, x: u32, y: u32, color: ShaderColor) {
+        if x < self.width && y < self.height {
+            let index = (y * self.width + x) as usize;
+            let pixel = &mut self.hdr[index];
+            let alpha = color.a.clamp(0.0, 1.0);
+            let over = |dst: f32, src: f32| dst * (1.0 - alpha) + src.max(0.0);
+            *pixel = ShaderColor::new(over(pixel.r, color.r), over(pixel.g, color.g), over(pixel.b, color.b), pixel.a);
+        }
+    }
+
+    /// Escribir la superficie de un pixel en el G-buffer (con z-buffer test)
+    pub fn set_surface_with_depth(
+        &mut self,
+        x: u32,
+        y: u32,
+        depth: f32,
+        surface: &SurfaceSample,
+        normal: Vector3,
+        world_position: Vector3,
+    ) {
         if x < self.width && y < self.height {
             let index = (y * self.width + x) as usize;
             if depth < self.zbuffer[index] {
-                self.pixels[index] = color;
                 self.zbuffer[index] = depth;
+                if let Some(gbuffer) = &mut self.gbuffer {
+                    gbuffer.albedo[index] = surface.albedo;
+                    gbuffer.normal[index] = normal;
+                    gbuffer.world_position[index] = world_position;
+                    gbuffer.emission[index] = surface.emission;
+                    gbuffer.specular[index] = surface.specular;
+                    gbuffer.material[index] = surface.material;
+                    gbuffer.lighting[index] = surface.lighting;
+                }
             }
         }
     }
@@ -112,12 +324,56 @@ impl Framebuffer {
     }
 
     /// Método para dibujar un punto con profundidad
-    pub fn point_with_depth(&mut self, x: i32, y: i32, color: Color, depth: f32) {
+    pub fn point_with_depth(&mut self, x: i32, y: i32, color: ShaderColor, depth: f32) {
         if x >= 0 && x < self.width as i32 && y >= 0 && y < self.height as i32 {
             self.set_pixel_with_depth(x as u32, y as u32, color, depth);
         }
     }
 
+    /// Promedia un framebuffer supersampleado (N×N muestras por pixel, cada una con su
+    /// profundidad) en este. Todas las muestras pesan igual, así un brillo que cubre
+    /// parte del pixel conserva su energía para el tone mapping y el bloom; la
+    /// profundidad que queda es la de la muestra más cercana.
+    pub fn resolve_samples(&mut self, source: &Framebuffer) {
+        let factor = (source.width / self.width).max(1) as usize;
+        let width = self.width as usize;
+        let source_width = source.width as usize;
+
+        self.hdr
+            .par_chunks_mut(width)
+            .zip(self.zbuffer.par_chunks_mut(width))
+            .enumerate()
+            .for_each(|(y, (colors, depths))| {
+                for (x, (color, depth)) in colors.iter_mut().zip(depths.iter_mut()).enumerate() {
+                    let mut sum = ShaderColor::new(0.0, 0.0, 0.0, 0.0);
+                    let mut nearest = f32::INFINITY;
+                    for sy in y * factor..(y + 1) * factor {
+                        for sx in x * factor..(x + 1) * factor {
+                            let index = sy * source_width + sx;
+                            let sample = source.hdr[index];
+                            sum.r += sample.r;
+                            sum.g += sample.g;
+                            sum.b += sample.b;
+                            sum.a += sample.a;
+                            nearest = nearest.min(source.zbuffer[index]);
+                        }
+                    }
+                    let count = (factor * factor) as f32;
+                    *color = ShaderColor::new(sum.r / count, sum.g / count, sum.b / count, sum.a / count);
+                    *depth = nearest;
+                }
+            });
+    }
+
+    /// Convierte el color HDR en la imagen de 8 bits que se muestra: exposición,
+    /// curva de tone mapping y codificación sRGB
+    pub fn resolve(&mut self, tone_mapping: &ToneMapping) {
+        self.pixels
+            .par_iter_mut()
+            .zip(self.hdr.par_iter())
+            .for_each(|(pixel, color)| *pixel = tone_mapping.map(*color));
+    }
+
     /// Actualizar la textura de Raylib con los datos del framebuffer
     pub fn swap_buffers(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread) {
         // Crear una nueva imagen
@@ -157,3 +413,27 @@ impl Framebuffer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_keeps_the_energy_of_a_bright_sample() {
+        // Un bloque de 2×2 muestras con un solo punto muy brillante
+        let mut source = Framebuffer::new(2, 2);
+        source.hdr.fill(ShaderColor::new(0.0, 0.0, 0.0, 1.0));
+        source.zbuffer.copy_from_slice(&[4.0, 3.0, 2.0, 5.0]);
+        source.hdr[0] = ShaderColor::new(10.0, 10.0, 10.0, 1.0);
+
+        let mut target = Framebuffer::new(1, 1);
+        target.resolve_samples(&source);
+
+        let color = target.hdr[0];
+        for channel in [color.r, color.g, color.b] {
+            assert!((channel - 2.5).abs() < 1e-6, "{:?}", color);
+        }
+        assert!((color.a - 1.0).abs() < 1e-6);
+        assert_eq!(target.zbuffer[0], 2.0);
+    }
+}
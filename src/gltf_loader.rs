@@ -0,0 +1,304 @@
+#![allow(dead_code)]
+
+use crate::matrix::{self, Matrix};
+use crate::obj_loader::calculate_spherical_uv;
+use crate::shaders::ShaderColor;
+use crate::sphere::{Mesh, Vertex};
+use crate::vector::Vector3;
+use std::fmt;
+use std::path::Path;
+
+/// Basic metallic-roughness material factors read from a glTF file.
+/// Textures are not sampled; only the constant factors are kept.
+#[derive(Debug, Clone)]
+pub struct PbrMaterial {
+    pub name: Option<String>,
+    pub base_color: ShaderColor,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: Vector3,
+    pub double_sided: bool,
+}
+
+impl Default for PbrMaterial {
+    /// glTF default material: white, fully metallic and rough
+    fn default() -> Self {
+        PbrMaterial {
+            name: None,
+            base_color: ShaderColor::WHITE,
+            metallic: 1.0,
+            roughness: 1.0,
+            emissive: Vector3::zero(),
+            double_sided: false,
+        }
+    }
+}
+
+/// One triangle primitive of the scene, with its node transform already applied
+#[derive(Debug)]
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub mesh: Mesh,
+    pub material: PbrMaterial,
+}
+
+#[derive(Debug)]
+pub enum GltfError {
+    Import(gltf::Error),
+    MissingPositions { mesh: String },
+    NoTriangles,
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Import(error) => write!(f, "error importing glTF: {}", error),
+            GltfError::MissingPositions { mesh } => write!(f, "mesh `{}` has a primitive without POSITION", mesh),
+            GltfError::NoTriangles => write!(f, "no triangle primitives found in glTF file"),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GltfError::Import(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<gltf::Error> for GltfError {
+    fn from(error: gltf::Error) -> Self {
+        GltfError::Import(error)
+    }
+}
+
+/// Loads every triangle primitive of the default scene of a `.gltf` or `.glb` file
+pub fn load_gltf(file_path: &str) -> Result<Vec<GltfMesh>, GltfError> {
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(file_path)?;
+    let base = Path::new(file_path).parent();
+    let buffers = gltf::import_buffers(&document, base, blob)?;
+
+    let mut meshes = Vec::new();
+    let scene = document.default_scene().or_else(|| document.scenes().next());
+
+    match scene {
+        Some(scene) => {
+            for node in scene.nodes() {
+                collect_node(&node, &Matrix::identity(), &buffers, &mut meshes)?;
+            }
+        }
+        None => {
+            // Files without scenes: take the meshes as they are
+            for mesh in document.meshes() {
+                collect_mesh(&mesh, &Matrix::identity(), &buffers, &mut meshes)?;
+            }
+        }
+    }
+
+    if meshes.is_empty() {
+        return Err(GltfError::NoTriangles);
+    }
+
+    let vertex_count: usize = meshes.iter().map(|m| m.mesh.vertices.len()).sum();
+    let index_count: usize = meshes.iter().map(|m| m.mesh.indices.len()).sum();
+    println!("Loaded glTF: {} primitives, {} vertices, {} indices", meshes.len(), vertex_count, index_count);
+
+    Ok(meshes)
+}
+
+/// Loads a glTF file as a single mesh (all primitives merged) for use as a planet or moon shape.
+/// The material of the first primitive is returned alongside.
+pub fn load_gltf_mesh(file_path: &str) -> Result<(Mesh, PbrMaterial), GltfError> {
+    let mut primitives = load_gltf(file_path)?.into_iter();
+    let first = primitives.next().ok_or(GltfError::NoTriangles)?;
+    let material = first.material;
+    let mut merged = first.mesh;
+
+    for primitive in primitives {
+        let base = merged.vertices.len() as u32;
+        let keep_tangents = !merged.tangents.is_empty() && !primitive.mesh.tangents.is_empty();
+
+        merged.vertices.extend(primitive.mesh.vertices);
+        merged.indices.extend(primitive.mesh.indices.iter().map(|i| i + base));
+        if keep_tangents {
+            merged.tangents.extend(primitive.mesh.tangents);
+        } else {
+            merged.tangents.clear();
+        }
+    }
+
+    Ok((merged, material))
+}
+
+fn collect_node(
+    node: &gltf::Node,
+    parent_transform: &Matrix,
+    buffers: &[gltf::buffer::Data],
+    out: &mut Vec<GltfMesh>,
+) -> Result<(), GltfError> {
+    let transform = parent_transform.multiply(&matrix_from_gltf(node.transform().matrix()));
+
+    if let Some(mesh) = node.mesh() {
+        collect_mesh(&mesh, &transform, buffers, out)?;
+    }
+
+    for child in node.children() {
+        collect_node(&child, &transform, buffers, out)?;
+    }
+
+    Ok(())
+}
+
+fn collect_mesh(
+    mesh: &gltf::Mesh,
+    transform: &Matrix,
+    buffers: &[gltf::buffer::Data],
+    out: &mut Vec<GltfMesh>,
+) -> Result<(), GltfError> {
+    let mesh_name = mesh.name().map(str::to_string);
+
+    for primitive in mesh.primitives() {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            // Points and lines have no surface to shade
+            continue;
+        }
+
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+
+        let positions: Vec<Vector3> = reader
+            .read_positions()
+            .ok_or_else(|| GltfError::MissingPositions {
+                mesh: mesh_name.clone().unwrap_or_else(|| format!("#{}", mesh.index())),
+            })?
+            .map(|[x, y, z]| Vector3::new(x, y, z))
+            .collect();
+
+        let normals: Option<Vec<Vector3>> = reader
+            .read_normals()
+            .map(|iter| iter.map(|[x, y, z]| Vector3::new(x, y, z)).collect());
+
+        let uvs: Option<Vec<(f32, f32)>> = reader
+            .read_tex_coords(0)
+            .map(|iter| iter.into_f32().map(|[u, v]| (u, v)).collect());
+
+        let tangents: Vec<(Vector3, f32)> = reader
+            .read_tangents()
+            .map(|iter| iter.map(|[x, y, z, w]| (Vector3::new(x, y, z), w)).collect())
+            .unwrap_or_default();
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+
+        // Drop faces that reference missing vertices instead of panicking later
+        let vertex_count = positions.len() as u32;
+        let mut indices: Vec<u32> = indices
+            .chunks_exact(3)
+            .filter(|face| face.iter().all(|&i| i < vertex_count))
+            .flatten()
+            .copied()
+            .collect();
+
+        let normals = normals
+            .filter(|normals| normals.len() == positions.len())
+            .unwrap_or_else(|| smooth_normals(&positions, &indices));
+
+        // A mirrored transform flips the winding order
+        if determinant3(transform) < 0.0 {
+            for face in indices.chunks_exact_mut(3) {
+                face.swap(1, 2);
+            }
+        }
+        let uvs = uvs.filter(|uvs| uvs.len() == positions.len());
+        let tangents = if tangents.len() == positions.len() { tangents } else { Vec::new() };
+
+        let vertices = positions
+            .iter()
+            .enumerate()
+            .map(|(i, &position)| {
+                let world_position = transform.transform_vector(&position);
+                Vertex {
+                    position: world_position,
+                    normal: transform_normal(transform, normals[i]),
+                    uv: uvs.as_ref().map(|uvs| uvs[i]).unwrap_or_else(|| calculate_spherical_uv(world_position)),
+                }
+            })
+            .collect();
+
+        let tangents = tangents
+            .into_iter()
+            .map(|(tangent, sign)| (transform.transform_direction(&tangent).normalize(), sign))
+            .collect();
+
+        out.push(GltfMesh {
+            name: mesh_name.clone(),
+            mesh: Mesh {
+                vertices,
+                indices,
+                tangents,
+            },
+            material: read_material(&primitive.material()),
+        });
+    }
+
+    Ok(())
+}
+
+fn read_material(material: &gltf::Material) -> PbrMaterial {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, a] = pbr.base_color_factor();
+    let [er, eg, eb] = material.emissive_factor();
+
+    PbrMaterial {
+        name: material.name().map(str::to_string),
+        base_color: ShaderColor::new(r, g, b, a),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        emissive: Vector3::new(er, eg, eb),
+        double_sided: material.double_sided(),
+    }
+}
+
+/// glTF matrices are column-major (`m[column][row]`)
+fn matrix_from_gltf(m: [[f32; 4]; 4]) -> Matrix {
+    matrix::new_matrix4(
+        m[0][0], m[1][0], m[2][0], m[3][0],
+        m[0][1], m[1][1], m[2][1], m[3][1],
+        m[0][2], m[1][2], m[2][2], m[3][2],
+        m[0][3], m[1][3], m[2][3], m[3][3],
+    )
+}
+
+fn column(m: &Matrix, j: usize) -> Vector3 {
+    Vector3::new(m.data[0][j], m.data[1][j], m.data[2][j])
+}
+
+fn determinant3(m: &Matrix) -> f32 {
+    column(m, 0).dot(&column(m, 1).cross(&column(m, 2)))
+}
+
+/// Transforms a normal by the cofactor matrix, which is the inverse transpose scaled by the
+/// determinant, so non-uniform scaling keeps normals perpendicular to the surface
+fn transform_normal(m: &Matrix, n: Vector3) -> Vector3 {
+    let (c0, c1, c2) = (column(m, 0), column(m, 1), column(m, 2));
+    let cofactor = c1.cross(&c2) * n.x + c2.cross(&c0) * n.y + c0.cross(&c1) * n.z;
+    (cofactor * determinant3(m).signum()).normalize()
+}
+
+/// Area-weighted vertex normals for primitives that ship without NORMAL
+fn smooth_normals(positions: &[Vector3], indices: &[u32]) -> Vec<Vector3> {
+    let mut normals = vec![Vector3::zero(); positions.len()];
+
+    for face in indices.chunks_exact(3) {
+        let (a, b, c) = (face[0] as usize, face[1] as usize, face[2] as usize);
+        let face_normal = (positions[b] - positions[a]).cross(&(positions[c] - positions[a]));
+        normals[a] += face_normal;
+        normals[b] += face_normal;
+        normals[c] += face_normal;
+    }
+
+    normals.iter().map(|n| n.normalize()).collect()
+}
@@ -0,0 +1,266 @@
+//! Golden-image regression tests: each case renders one shader headless, at a fixed
+//! time and camera pose, and compares the tone-mapped result with a reference image
+//! stored in `tests/golden`. Small differences from floating point or SIMD paths
+//! are accepted through a perceptual tolerance; anything visible fails the test and
+//! leaves the render and a diff image in `target/golden-failures`.
+//!
+//! After an intended visual change, regenerate the references with
+//! `UPDATE_GOLDEN=1 cargo test golden` and review the new images before committing.
+
+use super::{load_mesh, moon_position, render_planet_raytraced, render_planet_software, Planet, PlanetType, RasterMode};
+use crate::camera::Camera;
+use crate::framebuffer::Framebuffer;
+use crate::image_compare::{difference_image, PerceptualDiff};
+use crate::tone_mapping::ToneMapping;
+use crate::vector::Vector3;
+use raylib::prelude::Color;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 192;
+
+/// A pixel fails when its color difference is visible (Delta E above this)...
+const MAX_DELTA_E: f32 = 3.0;
+/// ...and a case fails when more than this fraction of its pixels do
+const MAX_FAILING_FRACTION: f32 = 0.005;
+
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+const FAILURE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/target/golden-failures");
+
+struct GoldenCase {
+    name: &'static str,
+    planet: PlanetType,
+    time: f32,
+    eye: Vector3,
+    target: Vector3,
+}
+
+/// Renders a case the way the window does, minus background and post-processing:
+/// rasterizer by tiles, default tone mapping
+fn render(case: &GoldenCase) -> Vec<Color> {
+    let mesh = load_mesh(concat!(env!("CARGO_MANIFEST_DIR"), "/images/sphere.obj")).expect("images/sphere.obj");
+    let mut planet = Planet::new(case.planet, mesh);
+    planet.update(case.time);
+
+    let mut camera = Camera::new();
+    camera.eye = case.eye;
+    camera.target = case.target;
+
+    let mut framebuffer = Framebuffer::new(WIDTH, HEIGHT);
+    framebuffer.clear(Color::BLACK);
+    render_planet_software(&mut framebuffer, &mut planet, &camera, case.time, WIDTH as i32, HEIGHT as i32, RasterMode::Tiled);
+    framebuffer.resolve(&ToneMapping::default());
+    framebuffer.pixels
+}
+
+/// Same case through the ray-traced reference renderer
+fn render_traced(case: &GoldenCase) -> Vec<Color> {
+    let mesh = load_mesh(concat!(env!("CARGO_MANIFEST_DIR"), "/images/sphere.obj")).expect("images/sphere.obj");
+    let mut planet = Planet::new(case.planet, mesh);
+    planet.update(case.time);
+
+    let mut camera = Camera::new();
+    camera.eye = case.eye;
+    camera.target = case.target;
+
+    let mut framebuffer = Framebuffer::new(WIDTH, HEIGHT);
+    framebuffer.clear(Color::BLACK);
+    render_planet_raytraced(&mut framebuffer, &planet, &camera, case.time);
+    framebuffer.resolve(&ToneMapping::default());
+    framebuffer.pixels
+}
+
+fn check(case: GoldenCase) {
+    let actual = render(&case);
+    let golden_path = Path::new(GOLDEN_DIR).join(format!("{}.ppm", case.name));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(GOLDEN_DIR).unwrap();
+        write_ppm(&golden_path, &actual, WIDTH, HEIGHT).unwrap();
+        return;
+    }
+
+    let expected = match read_ppm(&golden_path) {
+        Ok((pixels, width, height)) if (width, height) == (WIDTH, HEIGHT) => pixels,
+        Ok((_, width, height)) => panic!(
+            "{}: reference is {}x{}, the test renders {}x{}; regenerate it with UPDATE_GOLDEN=1",
+            case.name, width, height, WIDTH, HEIGHT
+        ),
+        Err(e) => panic!("{}: cannot read {} ({}); create it with UPDATE_GOLDEN=1", case.name, golden_path.display(), e),
+    };
+
+    let diff = PerceptualDiff::compare(&expected, &actual, MAX_DELTA_E);
+    if diff.failing_fraction() <= MAX_FAILING_FRACTION {
+        return;
+    }
+
+    let (actual_path, diff_path) = write_failure(case.name, &expected, &actual).unwrap();
+    panic!(
+        "{}: {} of {} pixels ({:.2}%) differ visibly from the reference (Delta E > {}, mean {:.2}, max {:.1})\n  render: {}\n  diff:   {}",
+        case.name,
+        diff.failing_pixels,
+        diff.total_pixels,
+        diff.failing_fraction() * 100.0,
+        MAX_DELTA_E,
+        diff.mean_delta_e,
+        diff.max_delta_e,
+        actual_path.display(),
+        diff_path.display(),
+    );
+}
+
+fn write_failure(name: &str, expected: &[Color], actual: &[Color]) -> io::Result<(PathBuf, PathBuf)> {
+    fs::create_dir_all(FAILURE_DIR)?;
+    let actual_path = Path::new(FAILURE_DIR).join(format!("{}_actual.ppm", name));
+    let diff_path = Path::new(FAILURE_DIR).join(format!("{}_diff.ppm", name));
+    write_ppm(&actual_path, actual, WIDTH, HEIGHT)?;
+    write_ppm(&diff_path, &difference_image(expected, actual, 4.0), WIDTH, HEIGHT)?;
+    Ok((actual_path, diff_path))
+}
+
+/// Binary PPM: trivial to read and write without any image library, so the
+/// references do not depend on how raylib was built
+fn write_ppm(path: &Path, pixels: &[Color], width: u32, height: u32) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write!(file, "P6\n{} {}\n255\n", width, height)?;
+    let bytes: Vec<u8> = pixels.iter().flat_map(|pixel| [pixel.r, pixel.g, pixel.b]).collect();
+    file.write_all(&bytes)?;
+    file.flush()
+}
+
+fn read_ppm(path: &Path) -> io::Result<(Vec<Color>, u32, u32)> {
+    let mut data = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut data)?;
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    // Header: magic, width, height and maximum value separated by whitespace, then
+    // a single whitespace byte before the pixels
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while fields.len() < 4 {
+        while data.get(position).is_some_and(u8::is_ascii_whitespace) {
+            position += 1;
+        }
+        let start = position;
+        while data.get(position).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid("truncated PPM header"));
+        }
+        fields.push(String::from_utf8_lossy(&data[start..position]).to_string());
+    }
+    if fields[0] != "P6" || fields[3] != "255" {
+        return Err(invalid("only 8-bit binary PPM (P6) is supported"));
+    }
+    let parse = |field: &str| field.parse::<u32>().map_err(|_| invalid("bad PPM size"));
+    let (width, height) = (parse(&fields[1])?, parse(&fields[2])?);
+
+    let pixels = data.get(position + 1..).unwrap_or_default();
+    if pixels.len() < (width * height * 3) as usize {
+        return Err(invalid("truncated PPM pixels"));
+    }
+    let colors = pixels
+        .chunks_exact(3)
+        .take((width * height) as usize)
+        .map(|rgb| Color::new(rgb[0], rgb[1], rgb[2], 255))
+        .collect();
+    Ok((colors, width, height))
+}
+
+#[test]
+fn golden_rocky_planet() {
+    check(GoldenCase {
+        name: "rocky_planet",
+        planet: PlanetType::Rocky,
+        time: 1.0,
+        eye: Vector3::new(0.0, 1.5, 4.5),
+        target: Vector3::zero(),
+    });
+}
+
+#[test]
+fn golden_gas_giant() {
+    check(GoldenCase {
+        name: "gas_giant",
+        planet: PlanetType::GasGiant,
+        time: 2.0,
+        eye: Vector3::new(0.0, 0.8, 5.0),
+        target: Vector3::zero(),
+    });
+}
+
+#[test]
+fn golden_crystal_planet() {
+    check(GoldenCase {
+        name: "crystal_planet",
+        planet: PlanetType::Crystal,
+        time: 2.0,
+        eye: Vector3::new(0.0, 0.4, 3.2),
+        target: Vector3::zero(),
+    });
+}
+
+#[test]
+fn golden_lava_planet() {
+    check(GoldenCase {
+        name: "lava_planet",
+        planet: PlanetType::Lava,
+        time: 1.5,
+        eye: Vector3::new(2.0, 0.7, 2.6),
+        target: Vector3::zero(),
+    });
+}
+
+#[test]
+fn golden_rings() {
+    // Low over the ring plane, close enough that the nearest rocks are meshes
+    check(GoldenCase {
+        name: "rings",
+        planet: PlanetType::GasGiant,
+        time: 3.0,
+        eye: Vector3::new(0.0, 0.35, 3.4),
+        target: Vector3::new(0.0, 0.0, 1.8),
+    });
+}
+
+#[test]
+fn golden_moon() {
+    let time = 0.5;
+    // Aimed at the same sphere that casts the moon's shadow
+    let moon = moon_position(time);
+    check(GoldenCase {
+        name: "moon",
+        planet: PlanetType::Rocky,
+        time,
+        eye: moon + Vector3::new(0.0, 0.3, 1.4),
+        target: moon,
+    });
+}
+
+/// `--compare` is only useful if the reference sees the moon where the rasterizer
+/// draws it: both renders must cover the same pixels
+#[test]
+fn raytraced_moon_covers_the_rasterized_moon() {
+    let time = 0.5;
+    let moon = moon_position(time);
+    let case = GoldenCase {
+        name: "moon_coverage",
+        planet: PlanetType::Rocky,
+        time,
+        eye: moon + Vector3::new(0.0, 0.3, 1.4),
+        target: moon,
+    };
+    let covered = |pixel: &Color| pixel.r as u32 + pixel.g as u32 + pixel.b as u32 > 30;
+    let raster: Vec<bool> = render(&case).iter().map(covered).collect();
+    let traced: Vec<bool> = render_traced(&case).iter().map(covered).collect();
+
+    let drawn = raster.iter().filter(|&&pixel| pixel).count();
+    let mismatched = raster.iter().zip(&traced).filter(|(a, b)| a != b).count();
+    assert!(drawn > 2000, "the moon covers only {} pixels", drawn);
+    // Only the outline may differ: the rasterized moon is a tessellated sphere with
+    // craters sunk into it, the traced one an exact sphere
+    assert!(mismatched * 10 < drawn, "{} of {} covered pixels differ between the renderers", mismatched, drawn);
+}
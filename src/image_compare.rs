@@ -0,0 +1,139 @@
+use crate::tone_mapping::srgb_decode;
+use raylib::prelude::*;
+
+/// Per-pixel comparison of two 8-bit images of the same size
+#[derive(Debug, Clone, Copy)]
+pub struct ImageDiff {
+    /// Average absolute difference per channel, 0-255
+    pub mean_error: f32,
+    /// Root mean square difference per channel, 0-255
+    pub rmse: f32,
+    /// Largest difference found in any channel
+    pub max_error: u8,
+    /// Pixels where some channel differs by more than the tolerance
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+}
+
+impl ImageDiff {
+    /// Compares the color channels (alpha is ignored). A pixel counts as different
+    /// when any channel is off by more than `tolerance`.
+    pub fn compare(a: &[Color], b: &[Color], tolerance: u8) -> Self {
+        assert_eq!(a.len(), b.len(), "the images must have the same size");
+        let mut sum = 0.0f64;
+        let mut sum_sq = 0.0f64;
+        let mut max_error = 0;
+        let mut differing_pixels = 0;
+        for (pa, pb) in a.iter().zip(b) {
+            let errors = [pa.r.abs_diff(pb.r), pa.g.abs_diff(pb.g), pa.b.abs_diff(pb.b)];
+            let largest = errors.into_iter().max().unwrap_or(0);
+            max_error = max_error.max(largest);
+            if largest > tolerance {
+                differing_pixels += 1;
+            }
+            for error in errors {
+                sum += error as f64;
+                sum_sq += (error as f64).powi(2);
+            }
+        }
+        let samples = (a.len() * 3).max(1) as f64;
+        ImageDiff {
+            mean_error: (sum / samples) as f32,
+            rmse: (sum_sq / samples).sqrt() as f32,
+            max_error,
+            differing_pixels,
+            total_pixels: a.len(),
+        }
+    }
+
+    /// Peak signal-to-noise ratio in dB (infinite for identical images)
+    pub fn psnr(&self) -> f32 {
+        20.0 * (255.0 / self.rmse).log10()
+    }
+
+    /// Fraction of the pixels that differ, 0-1
+    pub fn differing_fraction(&self) -> f32 {
+        self.differing_pixels as f32 / self.total_pixels.max(1) as f32
+    }
+}
+
+/// Perceptual comparison: the CIE76 color difference (Delta E in CIELAB) of every
+/// pixel, where about 2.3 is the smallest difference people notice. Unlike the raw
+/// channel error it weighs dark and saturated colors the way the eye does.
+#[derive(Debug, Clone, Copy)]
+pub struct PerceptualDiff {
+    pub mean_delta_e: f32,
+    pub max_delta_e: f32,
+    /// Pixels whose Delta E is above the threshold
+    pub failing_pixels: usize,
+    pub total_pixels: usize,
+}
+
+impl PerceptualDiff {
+    pub fn compare(a: &[Color], b: &[Color], max_delta_e: f32) -> Self {
+        assert_eq!(a.len(), b.len(), "the images must have the same size");
+        let mut sum = 0.0f64;
+        let mut max = 0.0f32;
+        let mut failing_pixels = 0;
+        for (&pa, &pb) in a.iter().zip(b) {
+            let difference = delta_e(pa, pb);
+            sum += difference as f64;
+            max = max.max(difference);
+            if difference > max_delta_e {
+                failing_pixels += 1;
+            }
+        }
+        PerceptualDiff {
+            mean_delta_e: (sum / a.len().max(1) as f64) as f32,
+            max_delta_e: max,
+            failing_pixels,
+            total_pixels: a.len(),
+        }
+    }
+
+    /// Fraction of the pixels above the threshold, 0-1
+    pub fn failing_fraction(&self) -> f32 {
+        self.failing_pixels as f32 / self.total_pixels.max(1) as f32
+    }
+}
+
+/// CIE76 difference between two sRGB colors
+pub fn delta_e(a: Color, b: Color) -> f32 {
+    let [l1, a1, b1] = to_lab(a);
+    let [l2, a2, b2] = to_lab(b);
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+/// sRGB -> linear -> XYZ (D65) -> CIELAB
+fn to_lab(color: Color) -> [f32; 3] {
+    let [r, g, b] = [color.r, color.g, color.b].map(|channel| srgb_decode(channel as f32 / 255.0));
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+    let f = |t: f32| if t > 0.008_856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Absolute difference of two images, multiplied by `gain` so small errors show up
+pub fn difference_image(a: &[Color], b: &[Color], gain: f32) -> Vec<Color> {
+    a.iter()
+        .zip(b)
+        .map(|(pa, pb)| {
+            let channel = |x: u8, y: u8| (x.abs_diff(y) as f32 * gain).min(255.0) as u8;
+            Color::new(channel(pa.r, pb.r), channel(pa.g, pb.g), channel(pa.b, pb.b), 255)
+        })
+        .collect()
+}
+
+/// Writes pixels to an image file; the format comes from the extension (PNG, BMP...)
+pub fn save_image(path: &str, pixels: &[Color], width: u32, height: u32) {
+    let image = Image::gen_image_color(width as i32, height as i32, Color::BLACK);
+    unsafe {
+        let image_ptr = image.data as *mut Color;
+        for (i, pixel) in pixels.iter().enumerate().take((width * height) as usize) {
+            *image_ptr.add(i) = *pixel;
+        }
+    }
+    image.export_image(path);
+}
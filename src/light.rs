@@ -0,0 +1,57 @@
+use crate::shaders::ShaderColor;
+use crate::vector::Vector3;
+
+/// Where the light of a `Light` comes from
+#[derive(Debug, Clone, Copy)]
+pub enum LightSource {
+    /// Infinitely far away: same direction (towards the light) and strength everywhere
+    Directional(Vector3),
+    /// A point in the scene such as a star, dimming with the square of the distance
+    Point(Vector3),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Light {
+    pub source: LightSource,
+    pub color: ShaderColor,
+    /// For point lights, the irradiance at one unit of distance
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn directional(direction: Vector3, color: ShaderColor, intensity: f32) -> Self {
+        Light { source: LightSource::Directional(direction.normalize()), color, intensity }
+    }
+
+    pub fn point(position: Vector3, color: ShaderColor, intensity: f32) -> Self {
+        Light { source: LightSource::Point(position), color, intensity }
+    }
+
+    /// Unit vector from `position` towards the light
+    pub fn direction_from(&self, position: Vector3) -> Vector3 {
+        match self.source {
+            LightSource::Directional(direction) => direction,
+            LightSource::Point(light_position) => (light_position - position).normalize(),
+        }
+    }
+
+    /// Direction towards the light and the colored irradiance arriving at `position`,
+    /// with inverse-square falloff for point lights
+    pub fn illuminate(&self, position: Vector3) -> (Vector3, ShaderColor) {
+        let (direction, strength) = match self.source {
+            LightSource::Directional(direction) => (direction, self.intensity),
+            LightSource::Point(light_position) => {
+                let to_light = light_position - position;
+                let distance_sq = to_light.dot(&to_light).max(1e-6);
+                (to_light * (1.0 / distance_sq.sqrt()), self.intensity / distance_sq)
+            }
+        };
+        let irradiance = ShaderColor::new(
+            self.color.r * strength,
+            self.color.g * strength,
+            self.color.b * strength,
+            1.0,
+        );
+        (direction, irradiance)
+    }
+}
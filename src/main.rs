@@ -1,27 +1,69 @@
 mod vector;
 mod matrix;
+mod quaternion;
 mod camera;
 mod shaders;
 mod sphere;
 mod obj_loader;
+mod gltf_loader;
+mod mesh_export;
+mod asteroid;
+mod ring_system;
+mod tile_rasterizer;
+mod simd;
+mod deferred;
+mod shadow_map;
+mod light;
+mod atmosphere;
+mod clouds;
+mod tone_mapping;
+mod bloom;
+mod post_process;
+mod background;
+mod bvh;
+mod raytracer;
+mod image_compare;
 mod framebuffer;
 mod fragment;
+#[cfg(test)]
+mod golden_tests;
 
 use raylib::prelude::*;
 use vector::Vector3;
+use quaternion::Quaternion;
+use matrix::ScreenTransform;
 use camera::Camera;
 use sphere::{Mesh, Vertex};
 use obj_loader::load_obj;
-use shaders::{PlanetShader, RockyPlanetShader, GasGiantShader, CrystalPlanetShader, LavaPlanetShader, RingShader, MoonShader, ShaderUniforms};
+use asteroid::AsteroidParams;
+use shaders::{PlanetShader, RockyPlanetShader, GasGiantShader, CrystalPlanetShader, LavaPlanetShader, MoonShader, StarShader, CloudShader, ShaderColor, ShaderUniforms, blackbody_color};
 use std::f32::consts::PI;
 use framebuffer::Framebuffer;
-use fragment::{TransformedVertex, triangle};
+use ring_system::RingSystem;
+use tile_rasterizer::{is_triangle_hidden, rasterize_tiled, ScreenTriangle};
+use rayon::prelude::*;
+use simd::Vector3Soa;
+use fragment::{ClipRect, TransformedVertex, triangle};
+use deferred::{rasterize_gbuffer, resolve_gbuffer, GBufferChannel};
+use shadow_map::{ShadowMap, SHADOW_MAP_SIZE};
+use light::Light;
+use atmosphere::Atmosphere;
+use clouds::CloudLayer;
+use tone_mapping::{ToneMapper, ToneMapping};
+use bloom::Bloom;
+use background::{Background, EnvironmentMap, Skybox, Starfield};
+use std::sync::Arc;
+use raytracer::{RayTracer, Scene, Sphere, SphereMaterial};
+use image_compare::{ImageDiff, PerceptualDiff};
+use post_process::{ChromaticAberration, ColorGrading, ColorLut, DepthOfField, FilmGrain, Fxaa, PostProcessChain, Vignette};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum PlanetType {
     Rocky,
     GasGiant,
     Crystal,
     Lava,
+    Star,
 }
 
 struct Planet {
@@ -29,25 +71,110 @@ struct Planet {
     shader: Box<dyn PlanetShader>,
     rotation: f32,
     rotation_speed: f32,
-    has_rings: bool,
+    /// Inclinación del eje de rotación respecto a la vertical, en radianes
+    axial_tilt: f32,
+    rings: Option<RingSystem>,
     has_moon: bool,
+    /// Capa de aire que dispersa la luz, dibujada con transparencia sobre el planeta
+    atmosphere: Option<Atmosphere>,
+    /// Capa de nubes con su propia rotación, entre la superficie y la atmósfera
+    clouds: Option<CloudLayer>,
+    /// Si el cuerpo es una estrella: es la luz de la escena y lleva corona
+    star: Option<StarShader>,
+    /// Resplandor de las partes más brillantes (grietas de lava, vetas, rayos)
+    bloom: Bloom,
+    /// Fondo de la escena que reflejan los shaders (el cristal); se asigna tras crear el fondo
+    environment: Option<Arc<EnvironmentMap>>,
+}
+
+/// Carga un mesh desde OBJ o glTF/GLB según la extensión del archivo
+fn load_mesh(path: &str) -> Result<Mesh, String> {
+    let lower = path.to_lowercase();
+    if lower.ends_with(".gltf") || lower.ends_with(".glb") {
+        gltf_loader::load_gltf_mesh(path)
+            .map(|(mesh, _material)| mesh)
+            .map_err(|e| e.to_string())
+    } else {
+        load_obj(path).map_err(|e| e.to_string())
+    }
+}
+
+/// Mesh de los planetas según la línea de comandos:
+/// `--asteroid <semilla>` genera un cuerpo irregular, `--mesh <ruta>` carga OBJ/glTF
+/// y por defecto se usa sphere.obj
+fn load_planet_mesh(args: &[String]) -> Mesh {
+    if let Some(seed) = arg_value(args, "--asteroid") {
+        let params = AsteroidParams {
+            seed: seed.parse().unwrap_or(1),
+            ..AsteroidParams::default()
+        };
+        return asteroid::generate_asteroid(&params);
+    }
+
+    let mesh_path = arg_value(args, "--mesh").unwrap_or_else(|| "images/sphere.obj".to_string());
+    load_mesh(&mesh_path)
+        .unwrap_or_else(|e| {
+            // Fallback to generated sphere if loading fails
+            println!("Warning: Could not load {} ({}), generating sphere instead", mesh_path, e);
+            Mesh::create_sphere(1.0, 32, 32)
+        })
 }
 
 impl Planet {
-    fn new(planet_type: PlanetType) -> Self {
-        // Load the sphere mesh from OBJ file
-        let mesh = load_obj("images/sphere.obj")
-            .unwrap_or_else(|_| {
-                // Fallback to generated sphere if OBJ loading fails
-                println!("Warning: Could not load sphere.obj, generating sphere instead");
-                Mesh::create_sphere(1.0, 32, 32)
-            });
-        
+    fn new(planet_type: PlanetType, mesh: Mesh) -> Self {
+        let star = StarShader { temperature: STAR_TEMPERATURE };
         let (shader, rotation_speed, has_rings, has_moon): (Box<dyn PlanetShader>, f32, bool, bool) = match planet_type {
             PlanetType::Rocky => (Box::new(RockyPlanetShader), 0.5, false, true),
             PlanetType::GasGiant => (Box::new(GasGiantShader), 1.2, true, false),
             PlanetType::Crystal => (Box::new(CrystalPlanetShader), 0.8, true, false),
             PlanetType::Lava => (Box::new(LavaPlanetShader), 1.5, false, false),
+            PlanetType::Star => (Box::new(star), 0.1, false, false),
+        };
+        let atmosphere = match planet_type {
+            PlanetType::Rocky => Some(Atmosphere::earth_like(BODY_RADIUS)),
+            PlanetType::GasGiant => Some(Atmosphere::gas_giant(BODY_RADIUS)),
+            _ => None,
+        };
+        // Nubes blancas que sombrean el terreno, o ceniza volcánica que deriva despacio
+        let mut clouds = match planet_type {
+            PlanetType::Rocky => Some(CloudLayer::new(
+                CloudShader { color: ShaderColor::WHITE, threshold: 0.5, opacity: 0.9 },
+                BODY_RADIUS * 1.07,
+                0.65,
+                true,
+            )),
+            PlanetType::Lava => Some(CloudLayer::new(
+                CloudShader { color: ShaderColor::from_rgb(70, 60, 55), threshold: 0.52, opacity: 0.8 },
+                BODY_RADIUS * 1.13,
+                1.2,
+                false,
+            )),
+            _ => None,
+        };
+        // El planeta rocoso se inclina como la Tierra; los que tienen anillos mantienen
+        // el ecuador en el plano de los anillos
+        let axial_tilt = match planet_type {
+            PlanetType::Rocky => 23.4f32.to_radians(),
+            _ => 0.0,
+        };
+        if let Some(clouds) = &mut clouds {
+            clouds.axial_tilt = axial_tilt;
+        }
+        let star = matches!(planet_type, PlanetType::Star).then_some(star);
+        // El umbral queda justo debajo de lo que cada shader emite por encima de 1
+        let bloom = match planet_type {
+            PlanetType::Rocky => Bloom { threshold: 1.2, intensity: 0.25, ..Bloom::default() },
+            PlanetType::GasGiant => Bloom { threshold: 0.9, intensity: 0.5, ..Bloom::default() },
+            PlanetType::Crystal => Bloom { threshold: 1.0, intensity: 0.6, ..Bloom::default() },
+            PlanetType::Lava => Bloom { threshold: 0.8, intensity: 0.8, ..Bloom::default() },
+            PlanetType::Star => Bloom { threshold: 1.5, intensity: 0.4, ..Bloom::default() },
+        };
+        
+        // Cinturón de rocas entre 1.5 y 3.6 radios, con velocidad kepleriana
+        let rings = if has_rings {
+            Some(RingSystem::new(7, 5000, 1.5, 3.6))
+        } else {
+            None
         };
         
         Planet {
@@ -55,16 +182,274 @@ impl Planet {
             shader,
             rotation: 0.0,
             rotation_speed,
-            has_rings,
+            axial_tilt,
+            rings,
             has_moon,
+            atmosphere,
+            clouds,
+            star,
+            bloom,
+            environment: None,
         }
     }
     
+    /// Orientación del planeta: el giro acumulado alrededor de su eje inclinado
+    fn orientation(&self) -> Quaternion {
+        Quaternion::tilted_spin(self.axial_tilt, self.rotation)
+    }
+
     fn update(&mut self, dt: f32) {
         self.rotation += self.rotation_speed * dt;
+        if let Some(clouds) = &mut self.clouds {
+            clouds.update(dt);
+        }
+    }
+}
+
+/// Dibuja el fondo (estrellas, nebulosa o skybox) con la orientación de la cámara,
+/// antes que el planeta; no escribe en el z-buffer
+fn render_background(framebuffer: &mut Framebuffer, background: &Background, camera: &Camera, time: f32) {
+    let aspect = framebuffer.width as f32 / framebuffer.height as f32;
+    let view_matrix = matrix::create_view_matrix(camera.eye, camera.target, camera.up);
+    let proj_matrix = matrix::create_projection_matrix(CAMERA_FOV_Y, aspect, CAMERA_NEAR, CAMERA_FAR);
+    background.render(framebuffer, &view_matrix, &proj_matrix, time);
+}
+
+/// Modo de rasterización del planeta
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RasterMode {
+    /// Triángulos agrupados por tiles de pantalla y sombreados en paralelo
+    Tiled,
+    /// Pipeline original, un triángulo tras otro en un solo hilo
+    SingleThreaded,
+    /// Deferred shading: se rasteriza la superficie al G-buffer y luego se ilumina
+    /// una vez por pixel (o se muestra un canal del G-buffer)
+    Deferred(GBufferChannel),
+}
+
+/// Órbita de la luna: radio, tamaño de la luna y velocidad angular en rad/s
+const MOON_ORBIT_RADIUS: f32 = 3.0;
+const MOON_RADIUS: f32 = 0.3;
+const MOON_ORBIT_SPEED: f32 = 0.8;
+/// Inclinación de la órbita respecto al plano XZ (radianes), suficiente para que
+/// la luna cruce la línea entre el planeta y la luz y haya eclipses
+const MOON_ORBIT_INCLINATION: f32 = 0.5;
+
+/// Centro de la luna en el tiempo `time`
+fn moon_position(time: f32) -> Vector3 {
+    let angle = time * MOON_ORBIT_SPEED;
+    let x = MOON_ORBIT_RADIUS * angle.cos();
+    let z = MOON_ORBIT_RADIUS * angle.sin();
+    Vector3::new(x * MOON_ORBIT_INCLINATION.cos(), x * MOON_ORBIT_INCLINATION.sin(), z)
+}
+
+/// Posición de la estrella que ilumina el sistema
+fn star_position() -> Vector3 {
+    Vector3::new(8.0, 8.0, 8.0)
+}
+
+/// Temperatura de la superficie de la estrella en Kelvin, da el color de su luz
+const STAR_TEMPERATURE: f32 = 5800.0;
+
+/// Radio de las mallas de los cuerpos (sphere.obj y la esfera generada)
+const BODY_RADIUS: f32 = 1.0;
+
+/// Campo de visión vertical de la proyección, en radianes (45°, igual que la
+/// cámara de raylib)
+const CAMERA_FOV_Y: f32 = PI / 4.0;
+
+/// Planos de recorte de la proyección (también los usa la profundidad de campo)
+const CAMERA_NEAR: f32 = 0.1;
+const CAMERA_FAR: f32 = 100.0;
+
+/// Semilla y cantidad de estrellas del fondo
+const STARFIELD_SEED: u64 = 1977;
+const STAR_COUNT: usize = 6000;
+/// Ancho del mapa de entorno equirectangular (el alto es la mitad)
+const ENVIRONMENT_MAP_WIDTH: usize = 512;
+
+/// Teclas que activan/desactivan cada efecto de post-proceso
+const POST_PROCESS_KEYS: [(KeyboardKey, &str); 7] = [
+    (KeyboardKey::KEY_B, "bloom"),
+    (KeyboardKey::KEY_K, "dof"),
+    (KeyboardKey::KEY_L, "lut"),
+    (KeyboardKey::KEY_C, "chromatic"),
+    (KeyboardKey::KEY_N, "vignette"),
+    (KeyboardKey::KEY_J, "grain"),
+    (KeyboardKey::KEY_X, "fxaa"),
+];
+
+/// Luces de la escena: la estrella, con intensidad ajustada para que en el planeta
+/// llegue irradiancia 1, y el brillo azulado y débil de una estrella compañera tan
+/// lejana que se trata como luz direccional. Si el cuerpo es la estrella, ella misma
+/// es la única luz, en su centro.
+fn scene_lights(planet: &Planet) -> Vec<Light> {
+    let star_color = blackbody_color(STAR_TEMPERATURE);
+    if planet.star.is_some() {
+        return vec![Light::point(Vector3::zero(), star_color, 1.0)];
+    }
+    let star = star_position();
+    vec![
+        Light::point(star, star_color, star.dot(&star)),
+        Light::directional(Vector3::new(-1.4, -0.4, 1.0), ShaderColor::new(0.55, 0.65, 1.0, 1.0), 0.15),
+    ]
+}
+
+/// Matrices de transformación compartidas por todos los triángulos de un frame
+struct FrameMatrices {
+    model: matrix::Matrix,
+    view: matrix::Matrix,
+    projection: matrix::Matrix,
+    viewport: matrix::Matrix,
+    /// viewport * projection * view * model, para transformar en un solo paso
+    mvp: matrix::Matrix,
+}
+
+impl FrameMatrices {
+    fn new(model: matrix::Matrix, view: matrix::Matrix, projection: matrix::Matrix, viewport: matrix::Matrix) -> Self {
+        let mvp = matrix::create_mvp_matrix(&model, &view, &projection, &viewport);
+        FrameMatrices { model, view, projection, viewport, mvp }
     }
 }
 
+/// Pasos 3 y 4 del pipeline para un triángulo: vertex shader, transformación
+/// a pantalla y fragment shader evaluado en cada vértice
+fn shade_triangle(
+    planet: &Planet,
+    first_index: usize,
+    matrices: &FrameMatrices,
+    uniforms: &ShaderUniforms,
+) -> ScreenTriangle {
+    [0, 1, 2].map(|corner| {
+        let vertex = &planet.mesh.vertices[planet.mesh.indices[first_index + corner] as usize];
+        
+        // PASO 3: Vertex Shader - Aplicar transformaciones al vértice
+        let (position, normal) = planet.shader.vertex_shader(vertex.position, vertex.normal, vertex.uv, uniforms);
+        
+        // Aplicar rotación del planeta (modelo matrix)
+        let world_position = matrices.model.transform_vector(&position);
+        let world_normal = matrices.model.transform_direction(&normal).normalize();
+        
+        // Multiplicación de matrices: Model * View * Projection
+        let screen_position = matrices.viewport.transform_vector(
+            &matrices.projection.transform_vector(&matrices.view.transform_vector(&world_position)),
+        );
+        
+        // PASO 4: Fragment Shader - Calcular color por vértice
+        let color = planet.shader.fragment_shader(world_position, world_normal, vertex.uv, uniforms);
+        
+        TransformedVertex {
+            screen_position,
+            world_position,
+            normal: world_normal,
+            color,
+            uv: vertex.uv,
+        }
+    })
+}
+
+/// Salida del vertex shader para toda la malla, en espacio del objeto
+struct DisplacedMesh {
+    positions: Vector3Soa,
+    normals: Vector3Soa,
+}
+
+/// PASO 3: Vertex Shader - Desplazamiento en espacio del objeto, una vez por vértice
+/// (no por esquina de triángulo). Lo comparten el mapa de sombras y la cámara.
+fn displace_vertices(planet: &Planet, uniforms: &ShaderUniforms) -> DisplacedMesh {
+    let displaced: Vec<(Vector3, Vector3)> = planet
+        .mesh
+        .vertices
+        .par_iter()
+        .map(|vertex| planet.shader.vertex_shader(vertex.position, vertex.normal, vertex.uv, uniforms))
+        .collect();
+    DisplacedMesh {
+        positions: Vector3Soa::from_vectors(displaced.iter().map(|(position, _)| *position)),
+        normals: Vector3Soa::from_vectors(displaced.iter().map(|(_, normal)| *normal)),
+    }
+}
+
+/// Pasos 2 a 4 del pipeline para toda la malla a la vez: las posiciones ya desplazadas
+/// por el vertex shader se transforman en lotes SoA
+/// con SIMD usando la matriz MVP pre-multiplicada, los triángulos ocultos según el
+/// z-buffer jerárquico se descartan y `shade` (el fragment shader, o el surface shader
+/// en modo deferred) solo se evalúa en los vértices de los triángulos que quedan.
+/// `shade` devuelve el color del vértice y datos extra que se agrupan por triángulo.
+fn shade_visible_triangles<T, S>(
+    planet: &Planet,
+    displaced: &DisplacedMesh,
+    matrices: &FrameMatrices,
+    framebuffer: &Framebuffer,
+    shade: S,
+) -> (Vec<ScreenTriangle>, Vec<[T; 3]>)
+where
+    T: Clone + Send + Sync,
+    S: Fn(Vector3, Vector3, (f32, f32)) -> (ShaderColor, T) + Sync,
+{
+    // Transformación por lotes: mundo (modelo), normales y pantalla (MVP)
+    let mut world_positions = Vector3Soa::default();
+    let mut world_normals = Vector3Soa::default();
+    let mut screen_positions = Vector3Soa::default();
+    simd::transform_points(&matrices.model, &displaced.positions, &mut world_positions);
+    simd::transform_normals(&matrices.model, &displaced.normals, &mut world_normals);
+    simd::transform_points(&matrices.mvp, &displaced.positions, &mut screen_positions);
+    
+    // PASO 2: Primitive Assembly - Descartar triángulos ocultos antes de sombrear
+    let faces: Vec<&[u32]> = planet
+        .mesh
+        .indices
+        .chunks_exact(3)
+        .filter(|face| {
+            let corners = [0, 1, 2].map(|corner| screen_positions.get(face[corner] as usize));
+            !is_triangle_hidden(framebuffer, corners)
+        })
+        .collect();
+    let mut needed = vec![false; planet.mesh.vertices.len()];
+    for face in &faces {
+        for &index in face.iter() {
+            needed[index as usize] = true;
+        }
+    }
+    
+    // PASO 4: Fragment Shader - Calcular color solo en los vértices visibles
+    let vertices: Vec<Option<(TransformedVertex, T)>> = planet
+        .mesh
+        .vertices
+        .par_iter()
+        .enumerate()
+        .map(|(i, vertex)| {
+            if !needed[i] {
+                return None;
+            }
+            let world_position = world_positions.get(i);
+            let normal = world_normals.get(i);
+            let (color, extra) = shade(world_position, normal, vertex.uv);
+            let transformed = TransformedVertex {
+                screen_position: screen_positions.get(i),
+                world_position,
+                normal,
+                color,
+                uv: vertex.uv,
+            };
+            Some((transformed, extra))
+        })
+        .collect();
+    
+    faces
+        .par_iter()
+        .map(|face| {
+            let corners = [0, 1, 2].map(|corner| {
+                vertices[face[corner] as usize]
+                    .clone()
+                    .expect("los vértices de triángulos visibles están sombreados")
+            });
+            let [(v1, e1), (v2, e2), (v3, e3)] = corners;
+            ([v1, v2, v3], [e1, e2, e3])
+        })
+        .unzip()
+}
+
+
 /// Función de renderizado usando framebuffer personalizado (implementación académica)
 /// Esta función demuestra el pipeline completo de renderizado 3D:
 /// 1. Vertex Shader - Transformación de vértices
@@ -79,259 +464,589 @@ fn render_planet_software(
     time: f32,
     width: i32,
     height: i32,
+    mode: RasterMode,
 ) {
-    use matrix;
-    
     // PASO 1: Construir matrices de transformación (multiplicación de matrices)
-    let view_matrix = matrix::create_view_matrix(camera.eye, camera.target, camera.up);
-    let proj_matrix = matrix::create_projection_matrix(45.0, width as f32 / height as f32, 0.1, 100.0);
-    let viewport_matrix = matrix::create_viewport_matrix(0.0, 0.0, width as f32, height as f32);
+    let matrices = FrameMatrices::new(
+        planet.orientation().to_matrix(),
+        matrix::create_view_matrix(camera.eye, camera.target, camera.up),
+        matrix::create_projection_matrix(CAMERA_FOV_Y, width as f32 / height as f32, CAMERA_NEAR, CAMERA_FAR),
+        matrix::create_viewport_matrix(0.0, 0.0, width as f32, height as f32),
+    );
+    let view_matrix = matrices.view;
+    let proj_matrix = matrices.projection;
+    let viewport_matrix = matrices.viewport;
+    // Lo que necesitan las capas que se dibujan después del planeta
+    let screen = ScreenTransform { view: view_matrix, projection: proj_matrix, viewport: viewport_matrix, width, height };
     
     // Configurar uniformes del shader
-    let uniforms = ShaderUniforms {
+    let planet: &Planet = planet;
+    let mut uniforms = ShaderUniforms {
         time,
         camera_position: camera.eye,
-        light_direction: Vector3::new(1.0, 1.0, 1.0).normalize(),
+        lights: scene_lights(planet),
+        shadow_map: None,
+        cloud_shadow: planet.clouds.as_ref().and_then(CloudLayer::shadow_caster),
+        environment: planet.environment.clone(),
+        occluder: None,
     };
     
-    // PASO 2: Primitive Assembly - Procesar cada triángulo
-    for i in (0..planet.mesh.indices.len()).step_by(3) {
-        let idx1 = planet.mesh.indices[i] as usize;
-        let idx2 = planet.mesh.indices[i + 1] as usize;
-        let idx3 = planet.mesh.indices[i + 2] as usize;
-        
-        let v1 = &planet.mesh.vertices[idx1];
-        let v2 = &planet.mesh.vertices[idx2];
-        let v3 = &planet.mesh.vertices[idx3];
-        
-        // PASO 3: Vertex Shader - Aplicar transformaciones a cada vértice
-        let (pos1, norm1) = planet.shader.vertex_shader(v1.position, v1.normal, v1.uv, &uniforms);
-        let (pos2, norm2) = planet.shader.vertex_shader(v2.position, v2.normal, v2.uv, &uniforms);
-        let (pos3, norm3) = planet.shader.vertex_shader(v3.position, v3.normal, v3.uv, &uniforms);
-        
-        // Aplicar rotación del planeta (modelo matrix)
-        let rot_matrix = matrix::create_rotation_y(planet.rotation);
-        let world_pos1 = rot_matrix.transform_vector(&pos1);
-        let world_pos2 = rot_matrix.transform_vector(&pos2);
-        let world_pos3 = rot_matrix.transform_vector(&pos3);
-        let world_norm1 = rot_matrix.transform_vector(&norm1).normalize();
-        let world_norm2 = rot_matrix.transform_vector(&norm2).normalize();
-        let world_norm3 = rot_matrix.transform_vector(&norm3).normalize();
-        
-        // Multiplicación de matrices: Model * View * Projection
-        let screen1 = viewport_matrix.transform_vector(&proj_matrix.transform_vector(&view_matrix.transform_vector(&world_pos1)));
-        let screen2 = viewport_matrix.transform_vector(&proj_matrix.transform_vector(&view_matrix.transform_vector(&world_pos2)));
-        let screen3 = viewport_matrix.transform_vector(&proj_matrix.transform_vector(&view_matrix.transform_vector(&world_pos3)));
-        
-        // PASO 4: Fragment Shader - Calcular color por vértice
-        let color1 = planet.shader.fragment_shader(world_pos1, world_norm1, v1.uv, &uniforms);
-        let color2 = planet.shader.fragment_shader(world_pos2, world_norm2, v2.uv, &uniforms);
-        let color3 = planet.shader.fragment_shader(world_pos3, world_norm3, v3.uv, &uniforms);
-        
-        // Crear vértices transformados para rasterización
-        let tv1 = TransformedVertex {
-            screen_position: screen1,
-            world_position: world_pos1,
-            normal: world_norm1,
-            color: color1,
-            uv: v1.uv,
-        };
-        
-        let tv2 = TransformedVertex {
-            screen_position: screen2,
-            world_position: world_pos2,
-            normal: world_norm2,
-            color: color2,
-            uv: v2.uv,
-        };
-        
-        let tv3 = TransformedVertex {
-            screen_position: screen3,
-            world_position: world_pos3,
-            normal: world_norm3,
-            color: color3,
-            uv: v3.uv,
-        };
-        
-        // PASO 5: Rasterization - Generar fragmentos usando coordenadas baricéntricas
-        let fragments = triangle(&tv1, &tv2, &tv3);
-        
-        // PASO 6: Framebuffer - Escribir fragmentos con depth testing
-        for fragment in fragments {
-            if fragment.position.x >= 0.0 && fragment.position.x < width as f32 &&
-               fragment.position.y >= 0.0 && fragment.position.y < height as f32 {
-                framebuffer.set_pixel_with_depth(
-                    fragment.position.x as u32,
-                    fragment.position.y as u32,
-                    fragment.color.to_raylib_color(),
-                    fragment.depth,
-                );
+    let displaced = displace_vertices(planet, &uniforms);
+    
+    // Mapa de sombras: profundidad vista desde la luz principal de todo lo que proyecta
+    // sombra. Una estrella es su propia luz y no tiene nada que sombrear.
+    if planet.star.is_none() {
+        uniforms.shadow_map = uniforms
+            .lights
+            .first()
+            .map(|light| render_shadow_map(planet, &displaced, &matrices.model, light, time));
+    }
+    
+    framebuffer.set_deferred(matches!(mode, RasterMode::Deferred(_)));
+    match mode {
+        RasterMode::Tiled => {
+            // PASOS 2 a 4: Vértices transformados por lotes, triángulos ocultos descartados
+            let (triangles, _) = shade_visible_triangles(planet, &displaced, &matrices, framebuffer, |position, normal, uv| {
+                (planet.shader.fragment_shader(position, normal, uv, &uniforms), ())
+            });
+            
+            // PASOS 5 y 6: Rasterización y depth testing por tiles en paralelo
+            rasterize_tiled(framebuffer, &triangles);
+        }
+        RasterMode::SingleThreaded => {
+            let screen = ClipRect { min_x: 0, min_y: 0, max_x: width - 1, max_y: height - 1 };
+            // PASO 2: Primitive Assembly - Procesar cada triángulo
+            for i in (0..planet.mesh.indices.len()).step_by(3) {
+                let [tv1, tv2, tv3] = shade_triangle(planet, i, &matrices, &uniforms);
+                
+                // PASOS 5 y 6: Rasterización con funciones de arista, escribiendo
+                // cada fragmento directamente en el framebuffer con depth testing
+                triangle(&tv1, &tv2, &tv3, screen, |sample| {
+                    let (x, y) = (sample.x as u32, sample.y as u32);
+                    if framebuffer.depth_test(x, y, sample.depth) {
+                        framebuffer.set_pixel_with_depth(x, y, sample.fragment().color, sample.depth);
+                    }
+                });
             }
         }
+        RasterMode::Deferred(_) => {
+            // PASOS 2 a 4: Igual que por tiles, pero los vértices guardan la superficie sin iluminar
+            let (triangles, surfaces) = shade_visible_triangles(planet, &displaced, &matrices, framebuffer, |position, normal, uv| {
+                let surface = planet.shader.surface_shader(position, normal, uv, &uniforms);
+                (surface.albedo, surface)
+            });
+            
+            // PASO 5: Rasterización al G-buffer con depth testing
+            rasterize_gbuffer(framebuffer, &triangles, &surfaces);
+        }
     }
     
+    // El planeta ya está en el z-buffer: actualizar la jerarquía para que anillos
+    // y luna puedan descartar partículas y puntos ocultos detrás de él
+    framebuffer.update_depth_hierarchy();
+    
     // Renderizar anillos si el planeta los tiene
-    if planet.has_rings {
-        render_rings(framebuffer, &view_matrix, &proj_matrix, &viewport_matrix, &uniforms, width, height);
+    if let Some(rings) = &planet.rings {
+        rings.render(framebuffer, &screen, &uniforms);
     }
     
     // Renderizar luna si el planeta la tiene
     if planet.has_moon {
-        render_moon(framebuffer, &view_matrix, &proj_matrix, &viewport_matrix, &uniforms, width, height);
+        render_moon(framebuffer, &screen, &uniforms);
+    }
+    
+    // PASO 6 en modo deferred: iluminar el G-buffer completo (o mostrar un canal)
+    if let RasterMode::Deferred(channel) = mode {
+        resolve_gbuffer(framebuffer, &uniforms, channel);
+    }
+    
+    // Nubes, atmósfera y corona se mezclan sobre la imagen ya iluminada (no en los
+    // canales del G-buffer)
+    let shows_lit_image = !matches!(mode, RasterMode::Deferred(channel) if channel != GBufferChannel::Lit);
+    if !shows_lit_image {
+        return;
+    }
+    if let Some(clouds) = &planet.clouds {
+        clouds.render(framebuffer, &screen, &uniforms);
+    }
+    if let Some(atmosphere) = &planet.atmosphere {
+        atmosphere.render(framebuffer, &screen, &uniforms);
+    }
+    if let Some(star) = &planet.star {
+        render_corona(framebuffer, star, &matrices, camera, time);
+    }
+}
+
+/// Renderizador de referencia: la misma escena que `render_planet_software`, pero
+/// lanzando un rayo por pixel contra la malla desplazada (en un BVH) y esferas
+/// exactas para la luna y las rocas de los anillos. Los shaders se evalúan en cada
+/// punto de impacto y las sombras se calculan con rayos hacia la luz.
+fn render_planet_raytraced(framebuffer: &mut Framebuffer, planet: &Planet, camera: &Camera, time: f32) {
+    let (width, height) = (framebuffer.width as f32, framebuffer.height as f32);
+    let matrices = FrameMatrices::new(
+        planet.orientation().to_matrix(),
+        matrix::create_view_matrix(camera.eye, camera.target, camera.up),
+        matrix::create_projection_matrix(CAMERA_FOV_Y, width / height, CAMERA_NEAR, CAMERA_FAR),
+        matrix::create_viewport_matrix(0.0, 0.0, width, height),
+    );
+    let mut uniforms = ShaderUniforms {
+        time,
+        camera_position: camera.eye,
+        lights: scene_lights(planet),
+        shadow_map: None,
+        cloud_shadow: planet.clouds.as_ref().and_then(CloudLayer::shadow_caster),
+        environment: planet.environment.clone(),
+        occluder: None,
+    };
+    
+    // Malla ya desplazada por el vertex shader y rotada al mundo
+    let displaced = displace_vertices(planet, &uniforms);
+    let vertices = planet
+        .mesh
+        .vertices
+        .iter()
+        .enumerate()
+        .map(|(i, vertex)| Vertex {
+            position: matrices.model.transform_vector(&displaced.positions.get(i)),
+            normal: matrices.model.transform_direction(&displaced.normals.get(i)).normalize(),
+            uv: vertex.uv,
+        })
+        .collect();
+    
+    let mut spheres = Vec::new();
+    if planet.has_moon {
+        spheres.push(Sphere { center: moon_position(time), radius: MOON_RADIUS, material: SphereMaterial::Moon });
+    }
+    if let Some(rings) = &planet.rings {
+        spheres.extend(rings.particles.iter().map(|particle| Sphere {
+            center: rings.particle_position(particle, time),
+            radius: particle.size,
+            material: SphereMaterial::Rock(particle.color),
+        }));
+    }
+    let scene = Arc::new(Scene::new(vertices, &planet.mesh.indices, spheres));
+    
+    // Una estrella es su propia luz y no tiene nada que sombrear
+    if planet.star.is_none() {
+        uniforms.occluder = Some(scene.clone());
+    }
+    
+    framebuffer.set_deferred(false);
+    let tracer = RayTracer {
+        scene: &scene,
+        shader: planet.shader.as_ref(),
+        clouds: planet.clouds.as_ref(),
+        atmosphere: planet.atmosphere.as_ref(),
+    };
+    tracer.render(framebuffer, &matrices.view, &matrices.projection, &uniforms);
+    
+    if let Some(star) = &planet.star {
+        render_corona(framebuffer, star, &matrices, camera, time);
     }
 }
 
-fn render_rings(
-    framebuffer: &mut Framebuffer,
-    view_matrix: &matrix::Matrix,
-    proj_matrix: &matrix::Matrix,
-    viewport_matrix: &matrix::Matrix,
-    uniforms: &ShaderUniforms,
-    width: i32,
-    height: i32,
-) {
-    // Generar anillos procedurales usando rasterización manual
-    let ring_segments = 64;
-    let rings = 8;
+/// Corona de la estrella en espacio de pantalla: un resplandor que se suma alrededor
+/// del disco proyectado, solo en los pixeles que quedan detrás del centro de la estrella
+fn render_corona(framebuffer: &mut Framebuffer, star: &StarShader, matrices: &FrameMatrices, camera: &Camera, time: f32) {
+    let to_screen = |point: Vector3| {
+        matrices.viewport.transform_vector(&matrices.projection.transform_vector(&matrices.view.transform_vector(&point)))
+    };
     
-    for ring in 0..rings {
-        let radius = 1.5 + ring as f32 * 0.3;
-        
-        for segment in 0..ring_segments {
-            let angle1 = (segment as f32 / ring_segments as f32) * 2.0 * PI;
-            let angle2 = ((segment + 1) as f32 / ring_segments as f32) * 2.0 * PI;
-            
-            // Crear vértices de anillo
-            let vertex1 = Vertex {
-                position: Vector3::new(radius * angle1.cos(), 0.0, radius * angle1.sin()),
-                normal: Vector3::new(0.0, 1.0, 0.0),
-                uv: (0.5, 0.5),
-            };
-            
-            let vertex2 = Vertex {
-                position: Vector3::new(radius * angle2.cos(), 0.0, radius * angle2.sin()),
-                normal: Vector3::new(0.0, 1.0, 0.0),
-                uv: (0.5, 0.5),
-            };
-            
-            // Aplicar shader de anillos
-            let (pos1, base_color1) = RingShader::vertex_shader(&vertex1, uniforms);
-            let (pos2, _) = RingShader::vertex_shader(&vertex2, uniforms);
-            
-            // Transformar a pantalla
-            let screen1 = viewport_matrix.transform_vector(&proj_matrix.transform_vector(&view_matrix.transform_vector(&pos1)));
-            let screen2 = viewport_matrix.transform_vector(&proj_matrix.transform_vector(&view_matrix.transform_vector(&pos2)));
-            
-            // Calcular color usando fragment shader
-            let color1 = RingShader::fragment_shader(pos1, vertex1.normal, base_color1, uniforms);
-            
-            // Dibujar línea de anillo en el framebuffer
-            draw_line_framebuffer(framebuffer, screen1, screen2, color1.to_raylib_color(), width, height);
+    // Radio aparente: distancia en pantalla hasta un punto del borde perpendicular
+    // a la línea de visión
+    let center = Vector3::zero();
+    let side = (center - camera.eye).cross(&camera.up).normalize();
+    let screen_center = to_screen(center);
+    let screen_edge = to_screen(center + side * BODY_RADIUS);
+    let radius = ((screen_edge.x - screen_center.x).powi(2) + (screen_edge.y - screen_center.y).powi(2)).sqrt();
+    if !(screen_center.x.is_finite() && screen_center.y.is_finite() && screen_center.z.is_finite() && radius > 0.5) {
+        return;
+    }
+    
+    // Más allá de 4 radios la corona ya no aporta nada visible
+    let extent = radius * 4.0;
+    let width = framebuffer.width as usize;
+    let min_x = (screen_center.x - extent).floor().max(0.0) as usize;
+    let max_x = ((screen_center.x + extent).ceil().max(0.0) as usize).min(width - 1);
+    let min_y = (screen_center.y - extent).floor().max(0.0) as usize;
+    let max_y = (screen_center.y + extent).ceil().max(0.0) as usize;
+    
+    let zbuffer = &framebuffer.zbuffer;
+    framebuffer
+        .hdr
+        .par_chunks_mut(width)
+        .enumerate()
+        .filter(|(y, _)| (min_y..=max_y).contains(y))
+        .for_each(|(y, row)| {
+            for x in min_x..=max_x {
+                if screen_center.z >= zbuffer[y * width + x] {
+                    continue;
+                }
+                let dx = x as f32 + 0.5 - screen_center.x;
+                let dy = y as f32 + 0.5 - screen_center.y;
+                let glow = star.corona((dx * dx + dy * dy).sqrt() / radius, dy.atan2(dx), time);
+                let pixel = &mut row[x];
+                *pixel = ShaderColor::new(pixel.r + glow.r, pixel.g + glow.g, pixel.b + glow.b, pixel.a);
+            }
+        });
+}
+
+/// Pasada de solo profundidad desde `light`: el planeta, la luna y las rocas de los
+/// anillos, que se dibujan como esferas. Una luz puntual se trata como direccional
+/// desde el centro del sistema, suficiente mientras esté lejos comparada con el sistema.
+fn render_shadow_map(planet: &Planet, displaced: &DisplacedMesh, model: &matrix::Matrix, light: &Light, time: f32) -> ShadowMap {
+    // Esfera que contiene todo lo que puede proyectar o recibir sombra
+    let mut scene_radius: f32 = 1.5;
+    if planet.has_moon {
+        scene_radius = scene_radius.max(MOON_ORBIT_RADIUS + MOON_RADIUS);
+    }
+    if let Some(rings) = &planet.rings {
+        scene_radius = scene_radius.max(rings.outer_radius + 0.1);
+    }
+    
+    let center = Vector3::zero();
+    let mut shadow_map = ShadowMap::new(light.direction_from(center), center, scene_radius, SHADOW_MAP_SIZE);
+    shadow_map.render_mesh(model, &displaced.positions, &planet.mesh.indices);
+    if planet.has_moon {
+        shadow_map.render_sphere(moon_position(time), MOON_RADIUS);
+    }
+    if let Some(rings) = &planet.rings {
+        for particle in &rings.particles {
+            shadow_map.render_sphere(rings.particle_position(particle, time), particle.size);
         }
     }
+    shadow_map
 }
 
-fn render_moon(
-    framebuffer: &mut Framebuffer,
-    view_matrix: &matrix::Matrix,
-    proj_matrix: &matrix::Matrix,
-    viewport_matrix: &matrix::Matrix,
-    uniforms: &ShaderUniforms,
-    width: i32,
-    height: i32,
-) {
-    // Posición orbital de la luna
-    let orbit_radius = 3.0;
-    let orbit_speed = uniforms.time * 0.8;
-    let moon_x = orbit_radius * orbit_speed.cos();
-    let moon_z = orbit_radius * orbit_speed.sin();
+fn render_moon(framebuffer: &mut Framebuffer, screen: &ScreenTransform, uniforms: &ShaderUniforms) {
+    // Posición orbital de la luna: la misma esfera que proyecta sombra en el mapa de sombras
+    let moon_center = moon_position(uniforms.time);
     
-    // Crear una esfera pequeña para la luna
-    let moon_scale = 0.3;
-    let segments = 16;
+    // Malla de latitud y longitud; la última columna repite la primera para cerrar la costura
+    let segments = 24;
     
-    for i in 0..segments {
-        for j in 0..segments {
+    let mut vertices = Vec::with_capacity((segments + 1) * (segments + 1));
+    for i in 0..=segments {
+        for j in 0..=segments {
             let phi = (i as f32 / segments as f32) * PI;
             let theta = (j as f32 / segments as f32) * 2.0 * PI;
             
-            let x = moon_scale * phi.sin() * theta.cos() + moon_x;
-            let y = moon_scale * phi.cos();
-            let z = moon_scale * phi.sin() * theta.sin() + moon_z;
-            
+            let offset = Vector3::new(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
             let vertex = Vertex {
-                position: Vector3::new(x, y, z),
-                normal: Vector3::new(x - moon_x, y, z - moon_z).normalize(),
+                position: moon_center + offset * MOON_RADIUS,
+                normal: offset,
                 uv: (j as f32 / segments as f32, i as f32 / segments as f32),
             };
             
-            // Aplicar shader de luna
+            // Aplicar shader de luna: cráteres y color base
             let (pos, base_color) = MoonShader::vertex_shader(&vertex, uniforms);
-            
-            // Transformar a pantalla
-            let screen = viewport_matrix.transform_vector(&proj_matrix.transform_vector(&view_matrix.transform_vector(&pos)));
-            
-            // Calcular color usando fragment shader
-            let color = MoonShader::fragment_shader(pos, vertex.normal, base_color, uniforms);
-            
-            // Dibujar punto de luna en el framebuffer
-            if screen.x >= 0.0 && screen.x < width as f32 && screen.y >= 0.0 && screen.y < height as f32 {
-                framebuffer.point_with_depth(
-                    screen.x as i32,
-                    screen.y as i32,
-                    color.to_raylib_color(),
-                    screen.z,
-                );
+            vertices.push(TransformedVertex {
+                screen_position: screen.project(&pos),
+                world_position: pos,
+                normal: offset,
+                color: base_color,
+                uv: vertex.uv,
+            });
+        }
+    }
+    
+    // Descartar la luna completa si está detrás del planeta (z-buffer jerárquico)
+    let (min_x, min_y, max_x, max_y, nearest) = vertices.iter().fold(
+        (f32::INFINITY, f32::INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY, f32::INFINITY),
+        |(min_x, min_y, max_x, max_y, nearest), vertex| {
+            let p = vertex.screen_position;
+            (min_x.min(p.x), min_y.min(p.y), max_x.max(p.x), max_y.max(p.y), nearest.min(p.z))
+        },
+    );
+    if framebuffer.is_hidden(min_x.floor() as i32, min_y.floor() as i32, max_x.ceil() as i32, max_y.ceil() as i32, nearest) {
+        return;
+    }
+    
+    let clip = ClipRect { min_x: 0, min_y: 0, max_x: screen.width - 1, max_y: screen.height - 1 };
+    let row = segments + 1;
+    for i in 0..segments {
+        for j in 0..segments {
+            let corner = i * row + j;
+            let quad = [corner, corner + 1, corner + row + 1, corner + row];
+            for [a, b, c] in [[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]] {
+                triangle(&vertices[a], &vertices[b], &vertices[c], clip, |sample| {
+                    let (x, y) = (sample.x as u32, sample.y as u32);
+                    // Early depth test: no sombrear pixeles ocultos
+                    if !framebuffer.depth_test(x, y, sample.depth) {
+                        return;
+                    }
+                    let fragment = sample.fragment();
+                    let normal = fragment.normal.normalize();
+                    if framebuffer.gbuffer.is_some() {
+                        let surface = MoonShader::surface_shader(fragment.world_position, fragment.color);
+                        framebuffer.set_surface_with_depth(x, y, sample.depth, &surface, normal, fragment.world_position);
+                    } else {
+                        let color = MoonShader::fragment_shader(fragment.world_position, normal, fragment.color, uniforms);
+                        framebuffer.set_pixel_with_depth(x, y, color, sample.depth);
+                    }
+                });
             }
         }
     }
 }
 
-// Función auxiliar para dibujar líneas en el framebuffer (algoritmo de Bresenham)
-fn draw_line_framebuffer(
-    framebuffer: &mut Framebuffer,
-    start: Vector3,
-    end: Vector3,
-    color: Color,
-    width: i32,
-    height: i32,
-) {
-    let x0 = start.x as i32;
-    let y0 = start.y as i32;
-    let x1 = end.x as i32;
-    let y1 = end.y as i32;
-    
-    let dx = (x1 - x0).abs();
-    let dy = -(y1 - y0).abs();
-    let sx = if x0 < x1 { 1 } else { -1 };
-    let sy = if y0 < y1 { 1 } else { -1 };
-    let mut err = dx + dy;
-    
-    let mut x = x0;
-    let mut y = y0;
-    
-    loop {
-        if x >= 0 && x < width && y >= 0 && y < height {
-            framebuffer.set_pixel_color(x as u32, y as u32, color);
+/// Fondo según la línea de comandos: `--skybox imagen` (equirectangular 2:1 o cubemap
+/// en cruz 4:3) o `--background nebula|stars|black` (por defecto estrellas con nebulosa)
+fn create_background(args: &[String]) -> Background {
+    match arg_value(args, "--skybox").map(|path| Skybox::load(&path)) {
+        Some(Ok(skybox)) => Background::Skybox(skybox),
+        skybox => {
+            if let Some(Err(e)) = skybox {
+                println!("Warning: {}, using the starfield", e);
+            }
+            let mut starfield = Starfield::new(STARFIELD_SEED, STAR_COUNT);
+            match arg_value(args, "--background").as_deref() {
+                Some("black") => Background::Solid(ShaderColor::BLACK),
+                Some("stars") => {
+                    starfield.nebula = None;
+                    Background::Starfield(starfield)
+                }
+                Some("nebula") | None => Background::Starfield(starfield),
+                Some(other) => {
+                    println!("Warning: unknown background {}, using the starfield", other);
+                    Background::Starfield(starfield)
+                }
+            }
         }
+    }
+}
+
+/// Valor que sigue a una opción de línea de comandos (`--opcion valor`)
+fn arg_value(args: &[String], name: &str) -> Option<String> {
+    args.iter().skip_while(|arg| *arg != name).nth(1).cloned()
+}
+
+/// Tipo de planeta elegido con `--planet 1-5` (rocoso por defecto)
+fn planet_type_arg(args: &[String]) -> PlanetType {
+    match arg_value(args, "--planet").as_deref() {
+        Some("2") => PlanetType::GasGiant,
+        Some("3") => PlanetType::Crystal,
+        Some("4") => PlanetType::Lava,
+        Some("5") => PlanetType::Star,
+        _ => PlanetType::Rocky,
+    }
+}
+
+/// Instante de la animación elegido con `--time t` (0 por defecto)
+fn time_arg(args: &[String]) -> f32 {
+    arg_value(args, "--time")
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(0.0)
+}
+
+/// Exporta la geometría desplazada por el vertex shader de un planeta a OBJ/PLY
+/// Uso: `--export terreno.ply [--planet 1-5] [--time t] [--colors]`
+fn run_export(args: &[String], output_path: &str, mesh: Mesh) {
+    let time = time_arg(args);
+    let with_colors = args.iter().any(|arg| arg == "--colors");
+
+    let planet = Planet::new(planet_type_arg(args), mesh);
+    let uniforms = ShaderUniforms {
+        time,
+        camera_position: Camera::new().eye,
+        lights: scene_lights(&planet),
+        shadow_map: None,
+        cloud_shadow: None,
+        environment: None,
+        occluder: None,
+    };
+
+    let baked = mesh_export::bake_shader(&planet.mesh, planet.shader.as_ref(), &uniforms, with_colors);
+    match mesh_export::export_mesh(&baked, output_path) {
+        Ok(()) => println!(
+            "Exported {} vertices, {} triangles to {}",
+            baked.mesh.vertices.len(),
+            baked.mesh.indices.len() / 3,
+            output_path
+        ),
+        Err(e) => eprintln!("Error exporting {}: {}", output_path, e),
+    }
+}
+
+/// Diferencia por canal (de 0 a 255) a partir de la cual un pixel cuenta como distinto
+/// al comparar el rasterizador con el trazador de rayos
+const COMPARE_TOLERANCE: u8 = 8;
+/// Diferencia de color (Delta E) a partir de la cual se nota a simple vista
+const COMPARE_DELTA_E: f32 = 2.3;
+
+/// Renderiza el mismo frame con el rasterizador y con el trazador de rayos de
+/// referencia y los compara pixel a pixel, después del tone mapping y sin post-proceso.
+/// Uso: `--compare prefijo [--planet 1-5] [--time t]`; escribe `prefijo_raster.png`,
+/// `prefijo_raytrace.png` y `prefijo_diff.png` (la diferencia, amplificada)
+fn run_compare(args: &[String], prefix: &str, mesh: Mesh) {
+    let (width, height) = (1024, 768);
+    let time = time_arg(args);
+    let camera = Camera::new();
+    let tone_mapping = ToneMapping::default();
+    let background = create_background(args);
+    
+    // Rotación y nubes como si la animación llevara `time` segundos
+    let mut planet = Planet::new(planet_type_arg(args), mesh);
+    planet.update(time);
+    planet.environment = Some(Arc::new(background.environment_map(ENVIRONMENT_MAP_WIDTH)));
+    
+    let mut raster = Framebuffer::new(width, height);
+    raster.clear(Color::BLACK);
+    render_background(&mut raster, &background, &camera, time);
+    let start = std::time::Instant::now();
+    render_planet_software(&mut raster, &mut planet, &camera, time, width as i32, height as i32, RasterMode::Tiled);
+    let raster_ms = start.elapsed().as_secs_f64() * 1000.0;
+    raster.resolve(&tone_mapping);
+    
+    let mut traced = Framebuffer::new(width, height);
+    traced.clear(Color::BLACK);
+    render_background(&mut traced, &background, &camera, time);
+    let start = std::time::Instant::now();
+    render_planet_raytraced(&mut traced, &planet, &camera, time);
+    let traced_ms = start.elapsed().as_secs_f64() * 1000.0;
+    traced.resolve(&tone_mapping);
+    
+    let diff = ImageDiff::compare(&raster.pixels, &traced.pixels, COMPARE_TOLERANCE);
+    let perceptual = PerceptualDiff::compare(&raster.pixels, &traced.pixels, COMPARE_DELTA_E);
+    image_compare::save_image(&format!("{}_raster.png", prefix), &raster.pixels, width, height);
+    image_compare::save_image(&format!("{}_raytrace.png", prefix), &traced.pixels, width, height);
+    let difference = image_compare::difference_image(&raster.pixels, &traced.pixels, 4.0);
+    image_compare::save_image(&format!("{}_diff.png", prefix), &difference, width, height);
+    
+    println!("Rasterizador: {:.1} ms, trazador de rayos: {:.1} ms", raster_ms, traced_ms);
+    println!("  Error medio:  {:.2} / 255", diff.mean_error);
+    println!("  RMSE:         {:.2} / 255 (PSNR {:.1} dB)", diff.rmse, diff.psnr());
+    println!("  Error máximo: {} / 255", diff.max_error);
+    println!(
+        "  Pixeles distintos (más de {}): {} ({:.2}%)",
+        COMPARE_TOLERANCE,
+        diff.differing_pixels,
+        diff.differing_fraction() * 100.0,
+    );
+    println!("  Delta E medio: {:.2}, máximo: {:.1}", perceptual.mean_delta_e, perceptual.max_delta_e);
+    println!(
+        "  Pixeles con diferencia visible (Delta E > {}): {} ({:.2}%)",
+        COMPARE_DELTA_E,
+        perceptual.failing_pixels,
+        perceptual.failing_fraction() * 100.0,
+    );
+}
+
+/// Mide el tiempo por frame a 1024x768 del rasterizador por tiles frente al pipeline
+/// original de un solo hilo. Uso: `--benchmark [frames]`
+fn run_benchmark(frame_count: usize, mesh: Mesh) {
+    let (width, height) = (1024, 768);
+    let mut framebuffer = Framebuffer::new(width as u32, height as u32);
+    let camera = Camera::new();
+    let planet_types = [
+        ("Rocoso", PlanetType::Rocky),
+        ("Gaseoso", PlanetType::GasGiant),
+        ("Cristal", PlanetType::Crystal),
+        ("Lava", PlanetType::Lava),
+        ("Estrella", PlanetType::Star),
+    ];
+    
+    println!("Benchmark {}x{}, {} frames por planeta, {} hilos", width, height, frame_count, rayon::current_num_threads());
+    run_vertex_benchmark(&mesh, &camera, width, height);
+    for (name, planet_type) in planet_types {
+        let mut planet = Planet::new(planet_type, mesh.clone());
+        let mut frame_times = [0.0f64; 2];
         
-        if x == x1 && y == y1 {
-            break;
+        for (slot, mode) in [RasterMode::SingleThreaded, RasterMode::Tiled].into_iter().enumerate() {
+            for frame in 0..frame_count {
+                let time = frame as f32 / 60.0;
+                framebuffer.clear(Color::BLACK);
+                let start = std::time::Instant::now();
+                render_planet_software(&mut framebuffer, &mut planet, &camera, time, width, height, mode);
+                frame_times[slot] += start.elapsed().as_secs_f64() * 1000.0;
+            }
+            frame_times[slot] /= frame_count.max(1) as f64;
         }
         
-        let e2 = 2 * err;
-        if e2 >= dy {
-            err += dy;
-            x += sx;
-        }
-        if e2 <= dx {
-            err += dx;
-            y += sy;
+        println!(
+            "  {:<8} un hilo: {:7.2} ms   tiles: {:7.2} ms   ({:.1}x)",
+            name,
+            frame_times[0],
+            frame_times[1],
+            frame_times[0] / frame_times[1],
+        );
+    }
+}
+
+/// Mide vértices por segundo de la transformación a pantalla sobre la malla del planeta:
+/// cuatro matrices por vértice (pipeline original), MVP escalar y MVP por lotes SIMD
+fn run_vertex_benchmark(mesh: &Mesh, camera: &Camera, width: i32, height: i32) {
+    let matrices = FrameMatrices::new(
+        matrix::create_rotation_y(0.3),
+        matrix::create_view_matrix(camera.eye, camera.target, camera.up),
+        matrix::create_projection_matrix(CAMERA_FOV_Y, width as f32 / height as f32, CAMERA_NEAR, CAMERA_FAR),
+        matrix::create_viewport_matrix(0.0, 0.0, width as f32, height as f32),
+    );
+    let positions = Vector3Soa::from_vectors(mesh.vertices.iter().map(|v| v.position));
+    if positions.is_empty() {
+        return;
+    }
+    let mut output = Vector3Soa::default();
+    let repeats = (2_000_000 / positions.len()).max(1);
+    let total = (repeats * positions.len()) as f64;
+    let rate = |start: std::time::Instant| total / start.elapsed().as_secs_f64() / 1.0e6;
+    
+    let start = std::time::Instant::now();
+    for _ in 0..repeats {
+        for i in 0..positions.len() {
+            let world = matrices.model.transform_vector(&positions.get(i));
+            let screen = matrices.viewport.transform_vector(
+                &matrices.projection.transform_vector(&matrices.view.transform_vector(&world)),
+            );
+            std::hint::black_box(screen);
         }
     }
+    let chained = rate(start);
+    
+    let start = std::time::Instant::now();
+    for _ in 0..repeats {
+        simd::transform_points_scalar(&matrices.mvp, &positions, &mut output);
+        std::hint::black_box(&output);
+    }
+    let scalar = rate(start);
+    
+    let start = std::time::Instant::now();
+    for _ in 0..repeats {
+        simd::transform_points(&matrices.mvp, &positions, &mut output);
+        std::hint::black_box(&output);
+    }
+    let batched = rate(start);
+    
+    let (backend, lanes) = simd::simd_backend();
+    println!("Transformación de {} vértices:", positions.len());
+    println!("  4 matrices:  {:8.1} Mvértices/s", chained);
+    println!("  MVP escalar: {:8.1} Mvértices/s", scalar);
+    println!("  MVP {} x{}: {:8.1} Mvértices/s ({:.1}x)", backend, lanes, batched, batched / chained);
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let planet_mesh = load_planet_mesh(&args);
+
+    if let Some(output_path) = arg_value(&args, "--export") {
+        run_export(&args, &output_path, planet_mesh);
+        return;
+    }
+    
+    if let Some(prefix) = arg_value(&args, "--compare") {
+        run_compare(&args, &prefix, planet_mesh);
+        return;
+    }
+    
+    if args.iter().any(|arg| arg == "--benchmark") {
+        let frame_count = arg_value(&args, "--benchmark")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(30);
+        run_benchmark(frame_count, planet_mesh);
+        return;
+    }
+
     let (mut rl, thread) = raylib::init()
         .size(1024, 768)
         .title("Laboratorio de Planetas - Software Renderer")
@@ -345,14 +1060,80 @@ fn main() {
     
     let mut camera = Camera::new();
     let mut planets = vec![
-        Planet::new(PlanetType::Rocky),
-        Planet::new(PlanetType::GasGiant),
-        Planet::new(PlanetType::Crystal),
-        Planet::new(PlanetType::Lava),
+        Planet::new(PlanetType::Rocky, planet_mesh.clone()),
+        Planet::new(PlanetType::GasGiant, planet_mesh.clone()),
+        Planet::new(PlanetType::Crystal, planet_mesh.clone()),
+        Planet::new(PlanetType::Lava, planet_mesh.clone()),
+        Planet::new(PlanetType::Star, planet_mesh.clone()),
     ];
     
     let mut current_planet = 0;
     let mut time = 0.0f32;
+    
+    let background = create_background(&args);
+    
+    // El mismo fondo, como mapa de entorno para los reflejos
+    let environment = Arc::new(background.environment_map(ENVIRONMENT_MAP_WIDTH));
+    for planet in &mut planets {
+        planet.environment = Some(Arc::clone(&environment));
+    }
+    
+    // Anti-aliasing: `--ssaa N` renderiza N×N muestras por pixel (cada una con su
+    // profundidad) en un framebuffer aparte y las promedia; `--fxaa` suaviza los bordes
+    // en el post-proceso. Se pueden combinar.
+    let ssaa = arg_value(&args, "--ssaa").and_then(|n| n.parse::<u32>().ok()).unwrap_or(1).clamp(1, 4);
+    let mut render_target = (ssaa > 1).then(|| Framebuffer::new(width as u32 * ssaa, height as u32 * ssaa));
+    
+    // Modo deferred: `--deferred` o `--gbuffer-view <canal>` para empezar en él
+    let mut gbuffer_view = match arg_value(&args, "--gbuffer-view") {
+        Some(name) => GBufferChannel::from_name(&name).or_else(|| {
+            println!("Warning: unknown G-buffer channel {}, showing the lit image", name);
+            Some(GBufferChannel::Lit)
+        }),
+        None if args.iter().any(|arg| arg == "--deferred") => Some(GBufferChannel::Lit),
+        None => None,
+    };
+    
+    // Trazador de rayos de referencia en lugar del rasterizador: `--raytrace`
+    let mut ray_traced = args.iter().any(|arg| arg == "--raytrace") && gbuffer_view.is_none();
+    
+    // Tone mapping del buffer HDR: `--tonemap <curva>` y `--exposure <valor>`
+    let mut tone_mapping = ToneMapping::default();
+    if let Some(name) = arg_value(&args, "--tonemap") {
+        match ToneMapper::from_name(&name) {
+            Some(operator) => tone_mapping.operator = operator,
+            None => println!("Warning: unknown tone mapper {}, using {}", name, tone_mapping.operator.name()),
+        }
+    }
+    if let Some(exposure) = arg_value(&args, "--exposure").and_then(|value| value.parse::<f32>().ok()) {
+        tone_mapping.exposure = exposure.max(0.0);
+    }
+    
+    // Cadena de post-proceso: profundidad de campo y bloom sobre HDR; LUT, aberración
+    // cromática, viñeta y grano sobre la imagen ya con tone mapping.
+    // `--lut archivo.cube` activa el grading con esa LUT, `--post a,b` activa más efectos
+    let lut_path = arg_value(&args, "--lut");
+    let lut = match lut_path.as_deref().map(ColorLut::load_cube) {
+        Some(Ok(lut)) => lut,
+        Some(Err(e)) => {
+            println!("Warning: could not load LUT ({}), using the built-in grading", e);
+            ColorLut::cinematic()
+        }
+        None => ColorLut::cinematic(),
+    };
+    let mut post_process = PostProcessChain::new()
+        .with(DepthOfField::new(CAMERA_NEAR, CAMERA_FAR), false)
+        .with(planets[current_planet].bloom, !args.iter().any(|arg| arg == "--no-bloom"))
+        .with(ColorGrading { lut, strength: 1.0 }, lut_path.is_some())
+        .with(Fxaa::default(), args.iter().any(|arg| arg == "--fxaa"))
+        .with(ChromaticAberration::default(), false)
+        .with(Vignette::default(), true)
+        .with(FilmGrain::default(), false);
+    for name in arg_value(&args, "--post").iter().flat_map(|list| list.split(',')) {
+        if !post_process.set_enabled(name, true) {
+            println!("Warning: unknown post-process effect {}", name);
+        }
+    }
 
     rl.set_target_fps(60);
 
@@ -372,23 +1153,87 @@ fn main() {
             current_planet = 2;
         } else if rl.is_key_pressed(KeyboardKey::KEY_FOUR) {
             current_planet = 3;
+        } else if rl.is_key_pressed(KeyboardKey::KEY_FIVE) {
+            current_planet = 4;
+        }
+        
+        // G: alternar modo deferred, V: siguiente canal del G-buffer
+        if rl.is_key_pressed(KeyboardKey::KEY_G) {
+            gbuffer_view = match gbuffer_view {
+                Some(_) => None,
+                None => Some(GBufferChannel::Lit),
+            };
+            ray_traced = false;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_V) {
+            gbuffer_view = gbuffer_view.map(GBufferChannel::next);
+        }
+        
+        // Y: alternar el trazador de rayos (no tiene G-buffer, así que sale del modo deferred)
+        if rl.is_key_pressed(KeyboardKey::KEY_Y) {
+            ray_traced = !ray_traced;
+            gbuffer_view = None;
+        }
+        
+        for (key, effect) in POST_PROCESS_KEYS {
+            if rl.is_key_pressed(key) {
+                post_process.toggle(effect);
+            }
+        }
+        
+        // T: siguiente curva de tone mapping, -/+: exposición
+        if rl.is_key_pressed(KeyboardKey::KEY_T) {
+            tone_mapping.operator = tone_mapping.operator.next();
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_MINUS) {
+            tone_mapping.exposure /= 1.25;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_EQUAL) {
+            tone_mapping.exposure *= 1.25;
         }
         
         // Actualizar planeta actual
         planets[current_planet].update(dt);
         
-        // RENDERIZADO: Limpiar framebuffer antes de cada frame
-        framebuffer.clear(Color::BLACK);
+        // RENDERIZADO: Limpiar framebuffer antes de cada frame (el supersampleado si hay SSAA)
+        let target = render_target.as_mut().unwrap_or(&mut framebuffer);
+        target.clear(Color::BLACK);
+        let (target_width, target_height) = (target.width as i32, target.height as i32);
+        render_background(target, &background, &camera, time);
         
         // Renderizar usando nuestro software renderer con framebuffer personalizado
-        render_planet_software(
-            &mut framebuffer,
-            &mut planets[current_planet],
-            &camera,
-            time,
-            width as i32,
-            height as i32,
-        );
+        if ray_traced {
+            render_planet_raytraced(target, &planets[current_planet], &camera, time);
+        } else {
+            render_planet_software(
+                target,
+                &mut planets[current_planet],
+                &camera,
+                time,
+                target_width,
+                target_height,
+                gbuffer_view.map_or(RasterMode::Tiled, RasterMode::Deferred),
+            );
+        }
+        
+        // Promediar las muestras de cada pixel
+        if let Some(target) = &render_target {
+            framebuffer.resolve_samples(target);
+        }
+        
+        // Post-proceso y paso a 8 bits; los canales de depuración del G-buffer se muestran tal cual
+        match gbuffer_view {
+            Some(channel) if channel != GBufferChannel::Lit => framebuffer.resolve(&ToneMapping::passthrough()),
+            _ => {
+                // El bloom usa los ajustes del planeta actual y el enfoque cae en su superficie
+                post_process.replace(planets[current_planet].bloom);
+                post_process.replace(DepthOfField {
+                    focus_distance: Some((camera.eye - camera.target).length() - BODY_RADIUS),
+                    ..DepthOfField::new(CAMERA_NEAR, CAMERA_FAR)
+                });
+                post_process.run(&mut framebuffer, &tone_mapping, time);
+            }
+        }
         
         // Actualizar textura de Raylib con los datos del framebuffer
         framebuffer.swap_buffers(&mut rl, &thread);
@@ -406,22 +1251,27 @@ fn main() {
         d.draw_text("2 - Gigante Gaseoso (con Anillos)", 10, 80, 14, Color::WHITE);
         d.draw_text("3 - Planeta de Cristal (con Anillos)", 10, 100, 14, Color::WHITE);
         d.draw_text("4 - Planeta de Lava", 10, 120, 14, Color::WHITE);
-        d.draw_text("WASD: Rotar cámara", 10, 140, 14, Color::WHITE);
-        d.draw_text("Flechas: Zoom y paneo", 10, 160, 14, Color::WHITE);
-        d.draw_text("Q/E: Paneo horizontal, R/F: Paneo vertical", 10, 180, 14, Color::WHITE);
+        d.draw_text("5 - Estrella (con Corona)", 10, 140, 14, Color::WHITE);
+        d.draw_text("WASD: Rotar cámara", 10, 160, 14, Color::WHITE);
+        d.draw_text("Flechas: Zoom y paneo", 10, 180, 14, Color::WHITE);
+        d.draw_text("Q/E: Paneo horizontal, R/F: Paneo vertical", 10, 200, 14, Color::WHITE);
+        d.draw_text("G: Modo deferred, V: Canal del G-buffer, Y: Trazado de rayos", 10, 220, 14, Color::WHITE);
+        d.draw_text("T: Tone mapping, -/+: Exposición", 10, 240, 14, Color::WHITE);
+        d.draw_text("B/K/L/C/N/J/X: Bloom, enfoque, LUT, aberración, viñeta, grano, FXAA", 10, 260, 14, Color::WHITE);
         
-        let planet_names = ["Planeta Rocoso (Luna)", "Gigante Gaseoso (Anillos)", "Planeta de Cristal (Anillos)", "Planeta de Lava"];
+        let planet_names = ["Planeta Rocoso (Luna)", "Gigante Gaseoso (Anillos)", "Planeta de Cristal (Anillos)", "Planeta de Lava", "Estrella (Corona)"];
         let planet_features = [
             "4 capas: Montañas, cráteres, rugosidad, minerales",
             "4 capas: Bandas, turbulencia, vórtices, brillos",
             "4 capas: Cristales, refracción, especular, energía",
-            "4 capas: Volcanes, lava, emisión, resplandor"
+            "4 capas: Volcanes, lava, emisión, resplandor",
+            "4 capas: Granulación, manchas, temperatura, limbo + corona"
         ];
         
         d.draw_text(
             &format!("Planeta actual: {}", planet_names[current_planet]),
             10,
-            200,
+            280,
             16,
             raylib::prelude::Color::YELLOW,
         );
@@ -429,9 +1279,40 @@ fn main() {
         d.draw_text(
             &format!("Efectos: {}", planet_features[current_planet]),
             10,
-            220,
+            300,
             12,
             raylib::prelude::Color::LIGHTGRAY,
         );
+        
+        d.draw_text(
+            &format!("Tone mapping: {}, exposición {:.2}", tone_mapping.operator.name(), tone_mapping.exposure),
+            10,
+            320,
+            12,
+            raylib::prelude::Color::LIGHTGRAY,
+        );
+        
+        let effects = post_process.enabled_names();
+        d.draw_text(
+            &format!("Post-proceso: {}", if effects.is_empty() { "ninguno".to_string() } else { effects.join(", ") }),
+            10,
+            340,
+            12,
+            raylib::prelude::Color::LIGHTGRAY,
+        );
+        
+        if let Some(channel) = gbuffer_view {
+            d.draw_text(
+                &format!("Deferred - canal: {}", channel.name()),
+                10,
+                360,
+                12,
+                raylib::prelude::Color::LIGHTGRAY,
+            );
+        }
+        
+        if ray_traced {
+            d.draw_text("Trazado de rayos (referencia)", 10, 360, 12, raylib::prelude::Color::LIGHTGRAY);
+        }
     }
 }
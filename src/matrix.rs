@@ -1,4 +1,6 @@
-use crate::vector::Vector3;
+#![allow(dead_code)]
+
+use crate::vector::{Vector3, Vector4};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Matrix {
@@ -48,17 +50,80 @@ impl Matrix {
         result
     }
 
+    /// Transforms a point (w = 1) and divides by the resulting w.
+    /// A point on the eye plane of a perspective projection has w = 0 and no screen
+    /// position: its coordinates come out infinite or NaN, so the finiteness checks
+    /// of the callers discard it instead of taking the undivided values as valid.
     pub fn transform_vector(&self, v: &Vector3) -> Vector3 {
         let x = self.data[0][0] * v.x + self.data[0][1] * v.y + self.data[0][2] * v.z + self.data[0][3];
         let y = self.data[1][0] * v.x + self.data[1][1] * v.y + self.data[1][2] * v.z + self.data[1][3];
         let z = self.data[2][0] * v.x + self.data[2][1] * v.y + self.data[2][2] * v.z + self.data[2][3];
         let w = self.data[3][0] * v.x + self.data[3][1] * v.y + self.data[3][2] * v.z + self.data[3][3];
-        
-        if w != 0.0 {
-            Vector3::new(x / w, y / w, z / w)
-        } else {
-            Vector3::new(x, y, z)
+
+        Vector3::new(x / w, y / w, z / w)
+    }
+
+    /// Transforms a direction (w = 0): rotation and scale apply, translation does not.
+    /// Normals only stay perpendicular to the surface under rotations and uniform
+    /// scales; otherwise transform them with the inverse transpose.
+    pub fn transform_direction(&self, v: &Vector3) -> Vector3 {
+        Vector3::new(
+            self.data[0][0] * v.x + self.data[0][1] * v.y + self.data[0][2] * v.z,
+            self.data[1][0] * v.x + self.data[1][1] * v.y + self.data[1][2] * v.z,
+            self.data[2][0] * v.x + self.data[2][1] * v.y + self.data[2][2] * v.z,
+        )
+    }
+
+    /// Full homogeneous product, without the perspective divide
+    pub fn transform(&self, v: &Vector4) -> Vector4 {
+        let row = |i: usize| Vector4::new(self.data[i][0], self.data[i][1], self.data[i][2], self.data[i][3]).dot(v);
+        Vector4::new(row(0), row(1), row(2), row(3))
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut result = *self;
+        for i in 0..4 {
+            for j in 0..4 {
+                result.data[i][j] = self.data[j][i];
+            }
+        }
+        result
+    }
+
+    /// Inverse matrix by Gauss-Jordan elimination with partial pivoting,
+    /// or `None` if the matrix is singular
+    pub fn inverse(&self) -> Option<Matrix> {
+        let mut a = self.data;
+        let mut inverse = Matrix::identity().data;
+
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap_or(column);
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+            for k in 0..4 {
+                a[column][k] *= scale;
+                inverse[column][k] *= scale;
+            }
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = a[row][column];
+                for k in 0..4 {
+                    a[row][k] -= factor * a[column][k];
+                    inverse[row][k] -= factor * inverse[column][k];
+                }
+            }
         }
+
+        Some(Matrix { data: inverse })
     }
 }
 
@@ -126,6 +191,7 @@ pub fn create_view_matrix(eye: Vector3, target: Vector3, up: Vector3) -> Matrix
 /// near: Near clipping plane distance
 /// far: Far clipping plane distance
 pub fn create_projection_matrix(fov_y: f32, aspect: f32, near: f32, far: f32) -> Matrix {
+    debug_assert!(fov_y > 0.0 && fov_y < std::f32::consts::PI, "fov_y is in radians, got {}", fov_y);
     let tan_half_fov = (fov_y / 2.0).tan();
 
     new_matrix4(
@@ -136,6 +202,18 @@ pub fn create_projection_matrix(fov_y: f32, aspect: f32, near: f32, far: f32) ->
     )
 }
 
+/// Creates an orthographic projection matrix
+/// left, right, bottom, top: View volume bounds in view space
+/// near, far: Clipping plane distances along the view direction
+pub fn create_orthographic_matrix(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Matrix {
+    new_matrix4(
+        2.0 / (right - left), 0.0, 0.0, -(right + left) / (right - left),
+        0.0, 2.0 / (top - bottom), 0.0, -(top + bottom) / (top - bottom),
+        0.0, 0.0, -2.0 / (far - near), -(far + near) / (far - near),
+        0.0, 0.0, 0.0, 1.0,
+    )
+}
+
 /// Creates a viewport matrix to transform NDC coordinates to screen space
 /// x, y: Viewport position (typically 0, 0)
 /// width, height: Viewport dimensions in pixels
@@ -164,6 +242,58 @@ pub fn create_rotation_y(angle: f32) -> Matrix {
     )
 }
 
+/// Creates a rotation matrix around the X axis
+pub fn create_rotation_x(angle: f32) -> Matrix {
+    let cos_a = angle.cos();
+    let sin_a = angle.sin();
+
+    new_matrix4(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, cos_a, -sin_a, 0.0,
+        0.0, sin_a, cos_a, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    )
+}
+
+/// Creates a rotation matrix around the Z axis
+pub fn create_rotation_z(angle: f32) -> Matrix {
+    let cos_a = angle.cos();
+    let sin_a = angle.sin();
+
+    new_matrix4(
+        cos_a, -sin_a, 0.0, 0.0,
+        sin_a, cos_a, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    )
+}
+
+/// Creates a rotation matrix around an arbitrary axis (normalized here), counterclockwise
+/// looking down the axis towards the origin (Rodrigues' formula)
+pub fn create_rotation_axis(axis: Vector3, angle: f32) -> Matrix {
+    let Vector3 { x, y, z } = axis.normalize();
+    let cos_a = angle.cos();
+    let sin_a = angle.sin();
+    let t = 1.0 - cos_a;
+
+    new_matrix4(
+        t * x * x + cos_a, t * x * y - sin_a * z, t * x * z + sin_a * y, 0.0,
+        t * x * y + sin_a * z, t * y * y + cos_a, t * y * z - sin_a * x, 0.0,
+        t * x * z - sin_a * y, t * y * z + sin_a * x, t * z * z + cos_a, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    )
+}
+
+/// Creates a scale matrix
+pub fn create_scale(x: f32, y: f32, z: f32) -> Matrix {
+    new_matrix4(
+        x, 0.0, 0.0, 0.0,
+        0.0, y, 0.0, 0.0,
+        0.0, 0.0, z, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+    )
+}
+
 /// Creates a translation matrix
 pub fn create_translation(x: f32, y: f32, z: f32) -> Matrix {
     new_matrix4(
@@ -172,4 +302,339 @@ pub fn create_translation(x: f32, y: f32, z: f32) -> Matrix {
         0.0, 0.0, 1.0, z,
         0.0, 0.0, 0.0, 1.0,
     )
-}
\ No newline at end of file
+}
+/// Pre-multiplies the whole vertex transform into one matrix:
+/// viewport * projection * view * model. A single `transform_vector` with this
+/// matrix gives the same screen position as applying the four in sequence,
+/// since the view and viewport matrices are affine.
+pub fn create_mvp_matrix(model: &Matrix, view: &Matrix, projection: &Matrix, viewport: &Matrix) -> Matrix {
+    viewport.multiply(projection).multiply(view).multiply(model)
+}
+
+/// Camera transforms of a frame plus the size of the screen they map to: what the
+/// passes drawn after the planet (rings, clouds, atmosphere) need to reach pixels
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenTransform {
+    pub view: Matrix,
+    pub projection: Matrix,
+    pub viewport: Matrix,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl ScreenTransform {
+    /// viewport * projection * view, to go from world to screen in one step
+    pub fn world_to_screen(&self) -> Matrix {
+        self.viewport.multiply(&self.projection.multiply(&self.view))
+    }
+
+    /// Pixel coordinates and depth of a world point, one matrix at a time
+    pub fn project(&self, point: &Vector3) -> Vector3 {
+        self.viewport.transform_vector(&self.projection.transform_vector(&self.view.transform_vector(point)))
+    }
+}
+
+impl std::ops::Mul for Matrix {
+    type Output = Matrix;
+
+    fn mul(self, other: Matrix) -> Matrix {
+        self.multiply(&other)
+    }
+}
+
+impl std::ops::Mul<Vector4> for Matrix {
+    type Output = Vector4;
+
+    fn mul(self, v: Vector4) -> Vector4 {
+        self.transform(&v)
+    }
+}
+
+impl From<Matrix> for nalgebra::Matrix4<f32> {
+    fn from(m: Matrix) -> Self {
+        nalgebra::Matrix4::from_fn(|i, j| m.data[i][j])
+    }
+}
+
+impl From<nalgebra::Matrix4<f32>> for Matrix {
+    fn from(m: nalgebra::Matrix4<f32>) -> Self {
+        let mut result = Matrix::identity();
+        for (i, row) in result.data.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = m[(i, j)];
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::FRAC_PI_4;
+
+    const CASES: usize = 200;
+
+    fn random_vector(rng: &mut StdRng, range: f32) -> Vector3 {
+        Vector3::new(rng.gen_range(-range..range), rng.gen_range(-range..range), rng.gen_range(-range..range))
+    }
+
+    /// Rotation, scale and translation: well conditioned and always invertible
+    fn random_transform(rng: &mut StdRng) -> Matrix {
+        let scale = rng.gen_range(0.5..2.0);
+        let offset = random_vector(rng, 10.0);
+        let mut scaling = Matrix::identity();
+        for i in 0..3 {
+            scaling.data[i][i] = scale;
+        }
+        create_translation(offset.x, offset.y, offset.z)
+            .multiply(&create_rotation_y(rng.gen_range(-3.0..3.0)))
+            .multiply(&scaling)
+    }
+
+    fn random_matrix(rng: &mut StdRng) -> Matrix {
+        let mut m = Matrix::identity();
+        for row in m.data.iter_mut() {
+            for value in row.iter_mut() {
+                *value = rng.gen_range(-2.0..2.0);
+            }
+        }
+        m
+    }
+
+    fn assert_matrix_close(a: &Matrix, b: &Matrix, tolerance: f32) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!(
+                    (a.data[i][j] - b.data[i][j]).abs() <= tolerance,
+                    "element [{}][{}]: {} vs {}\n{:?}\n{:?}",
+                    i, j, a.data[i][j], b.data[i][j], a, b
+                );
+            }
+        }
+    }
+
+    fn assert_vector_close(a: Vector3, b: Vector3, tolerance: f32) {
+        assert!((a - b).length() <= tolerance, "{:?} vs {:?}", a, b);
+    }
+
+    #[test]
+    fn identity_is_neutral() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..CASES {
+            let m = random_matrix(&mut rng);
+            assert_matrix_close(&m.multiply(&Matrix::identity()), &m, 0.0);
+            assert_matrix_close(&Matrix::identity().multiply(&m), &m, 0.0);
+        }
+    }
+
+    #[test]
+    fn multiplication_is_associative() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..CASES {
+            let (a, b, c) = (random_matrix(&mut rng), random_matrix(&mut rng), random_matrix(&mut rng));
+            assert_matrix_close(&a.multiply(&b).multiply(&c), &a.multiply(&b.multiply(&c)), 1e-4);
+        }
+    }
+
+    #[test]
+    fn composition_applies_right_to_left() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..CASES {
+            let (a, b) = (random_transform(&mut rng), random_transform(&mut rng));
+            let p = random_vector(&mut rng, 5.0);
+            assert_vector_close(a.multiply(&b).transform_vector(&p), a.transform_vector(&b.transform_vector(&p)), 1e-3);
+        }
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..CASES {
+            let m = random_transform(&mut rng);
+            let inverse = m.inverse().expect("rigid transforms with scale are invertible");
+            assert_matrix_close(&m.multiply(&inverse), &Matrix::identity(), 1e-4);
+            assert_matrix_close(&inverse.multiply(&m), &Matrix::identity(), 1e-4);
+
+            let p = random_vector(&mut rng, 5.0);
+            assert_vector_close(inverse.transform_vector(&m.transform_vector(&p)), p, 1e-3);
+        }
+    }
+
+    #[test]
+    fn inverse_of_general_matrices() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut tested = 0;
+        while tested < CASES {
+            let m = random_matrix(&mut rng);
+            let Some(inverse) = m.inverse() else { continue };
+            // Badly conditioned matrices lose too much precision in f32 to compare
+            if inverse.data.iter().flatten().any(|value| value.abs() > 50.0) {
+                continue;
+            }
+            assert_matrix_close(&m.multiply(&inverse), &Matrix::identity(), 1e-3);
+            tested += 1;
+        }
+    }
+
+    #[test]
+    fn singular_matrix_has_no_inverse() {
+        let mut m = Matrix::identity();
+        m.data[2] = [0.0; 4];
+        assert!(m.inverse().is_none());
+
+        // Two equal rows
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut m = random_matrix(&mut rng);
+        m.data[3] = m.data[1];
+        assert!(m.inverse().is_none());
+    }
+
+    #[test]
+    fn view_matrix_is_orthonormal() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        for _ in 0..CASES {
+            let eye = random_vector(&mut rng, 20.0);
+            let target = random_vector(&mut rng, 20.0);
+            // lookAt is undefined looking straight along the up vector
+            let forward = (target - eye).normalize();
+            if (target - eye).length() < 0.1 || forward.cross(&up).length() < 0.05 {
+                continue;
+            }
+            let view = create_view_matrix(eye, target, up);
+
+            let rows: Vec<Vector3> = (0..3).map(|i| Vector3::new(view.data[i][0], view.data[i][1], view.data[i][2])).collect();
+            for i in 0..3 {
+                assert!((rows[i].length() - 1.0).abs() < 1e-5, "row {} is not unit: {:?}", i, rows[i]);
+                for j in i + 1..3 {
+                    assert!(rows[i].dot(&rows[j]).abs() < 1e-5, "rows {} and {} are not orthogonal", i, j);
+                }
+            }
+            // Right-handed: right x up = back
+            assert_vector_close(rows[0].cross(&rows[1]), rows[2], 1e-5);
+            assert_eq!(view.data[3], [0.0, 0.0, 0.0, 1.0]);
+
+            // The eye goes to the origin and the target straight ahead on -z
+            assert_vector_close(view.transform_vector(&eye), Vector3::zero(), 1e-3);
+            let distance = (target - eye).length();
+            assert_vector_close(view.transform_vector(&target), Vector3::new(0.0, 0.0, -distance), 1e-3);
+        }
+    }
+
+    #[test]
+    fn projection_maps_the_frustum_to_ndc() {
+        let (near, far, aspect) = (0.1, 100.0, 16.0 / 9.0);
+        let proj = create_projection_matrix(FRAC_PI_4, aspect, near, far);
+
+        // Near and far planes go to -1 and 1
+        assert!((proj.transform_vector(&Vector3::new(0.0, 0.0, -near)).z + 1.0).abs() < 1e-4);
+        assert!((proj.transform_vector(&Vector3::new(0.0, 0.0, -far)).z - 1.0).abs() < 1e-4);
+
+        // The edges of the field of view go to the edges of the screen
+        let depth = 10.0;
+        let half_height = depth * (FRAC_PI_4 / 2.0).tan();
+        let top = proj.transform_vector(&Vector3::new(0.0, half_height, -depth));
+        assert!((top.y - 1.0).abs() < 1e-5, "top edge at y = {}", top.y);
+        let right = proj.transform_vector(&Vector3::new(half_height * aspect, 0.0, -depth));
+        assert!((right.x - 1.0).abs() < 1e-5, "right edge at x = {}", right.x);
+
+        // Depth grows monotonically and stays in range inside the frustum
+        let mut rng = StdRng::seed_from_u64(8);
+        let mut samples: Vec<f32> = (0..CASES).map(|_| rng.gen_range(near..far)).collect();
+        samples.sort_by(f32::total_cmp);
+        let mut previous = -1.0;
+        for distance in samples {
+            let z = proj.transform_vector(&Vector3::new(0.0, 0.0, -distance)).z;
+            assert!((-1.0..=1.0).contains(&z), "depth {} at distance {}", z, distance);
+            assert!(z >= previous);
+            previous = z;
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "radians")]
+    fn projection_rejects_degrees() {
+        create_projection_matrix(45.0, 1.0, 0.1, 100.0);
+    }
+
+    #[test]
+    fn point_on_the_eye_plane_is_not_finite() {
+        let proj = create_projection_matrix(FRAC_PI_4, 1.0, 0.1, 100.0);
+        let p = proj.transform_vector(&Vector3::new(1.0, 2.0, 0.0));
+        assert!(!(p.x.is_finite() && p.y.is_finite() && p.z.is_finite()), "got {:?}", p);
+    }
+
+    #[test]
+    fn mvp_matches_the_four_steps() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let view = create_view_matrix(Vector3::new(0.0, 1.5, 4.5), Vector3::zero(), Vector3::new(0.0, 1.0, 0.0));
+        let proj = create_projection_matrix(FRAC_PI_4, 4.0 / 3.0, 0.1, 100.0);
+        let viewport = create_viewport_matrix(0.0, 0.0, 800.0, 600.0);
+        for _ in 0..CASES {
+            let model = random_transform(&mut rng);
+            let mvp = create_mvp_matrix(&model, &view, &proj, &viewport);
+            let p = random_vector(&mut rng, 1.0);
+            let world = model.transform_vector(&p);
+            if view.transform_vector(&world).z > -0.5 {
+                continue;
+            }
+            let expected = viewport.transform_vector(&proj.transform_vector(&view.transform_vector(&world)));
+            let got = mvp.transform_vector(&p);
+            assert!((got.x - expected.x).abs() < 0.05 && (got.y - expected.y).abs() < 0.05, "{:?} vs {:?}", got, expected);
+            assert!((got.z - expected.z).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn axis_rotations_are_orthonormal_and_right_handed() {
+        let angle = 0.9;
+        let (x, y, z) = (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        // A positive angle turns each axis into the next one around it
+        let cases = [
+            (create_rotation_x(angle), x, y, z),
+            (create_rotation_y(angle), y, z, x),
+            (create_rotation_z(angle), z, x, y),
+        ];
+        for (m, axis, from, towards) in cases {
+            assert_matrix_close(&m.multiply(&m.transpose()), &Matrix::identity(), 1e-6);
+            assert_matrix_close(&m, &create_rotation_axis(axis, angle), 1e-6);
+            assert_vector_close(m.transform_vector(&axis), axis, 1e-6);
+            let turned = m.transform_vector(&from);
+            assert!((turned.dot(&towards) - angle.sin()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn transpose_and_directions() {
+        let mut rng = StdRng::seed_from_u64(10);
+        for _ in 0..CASES {
+            let m = random_matrix(&mut rng);
+            assert_matrix_close(&m.transpose().transpose(), &m, 0.0);
+            let t = random_transform(&mut rng);
+            let (p, d) = (random_vector(&mut rng, 5.0), random_vector(&mut rng, 5.0));
+            // Directions ignore the translation: the difference of two transformed points
+            assert_vector_close(t.transform_direction(&d), t.transform_vector(&(p + d)) - t.transform_vector(&p), 1e-3);
+            assert_vector_close((t * p.extend(1.0)).to_cartesian(), t.transform_vector(&p), 1e-4);
+        }
+        let scale = create_scale(2.0, 3.0, -1.0);
+        assert_vector_close(scale.transform_vector(&Vector3::new(1.0, 1.0, 1.0)), Vector3::new(2.0, 3.0, -1.0), 0.0);
+    }
+
+    #[test]
+    fn nalgebra_round_trip() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for _ in 0..CASES {
+            let (a, b) = (random_matrix(&mut rng), random_matrix(&mut rng));
+            let (na_a, na_b): (nalgebra::Matrix4<f32>, nalgebra::Matrix4<f32>) = (a.into(), b.into());
+            assert_matrix_close(&Matrix::from(na_a), &a, 0.0);
+            assert_matrix_close(&Matrix::from(na_a * na_b), &a.multiply(&b), 1e-5);
+        }
+        let t = random_transform(&mut rng);
+        let na_inverse = nalgebra::Matrix4::from(t).try_inverse().unwrap();
+        assert_matrix_close(&Matrix::from(na_inverse), &t.inverse().unwrap(), 1e-4);
+    }
+}
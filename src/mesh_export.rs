@@ -0,0 +1,152 @@
+#![allow(dead_code)]
+
+use crate::shaders::{PlanetShader, ShaderColor, ShaderUniforms};
+use crate::sphere::{Mesh, Vertex};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// A mesh after running a shader over it, ready to be written to disk
+pub struct BakedMesh {
+    pub mesh: Mesh,
+    /// Per-vertex color from the fragment stage, if requested
+    pub colors: Option<Vec<ShaderColor>>,
+}
+
+/// Runs the shader's vertex stage over every vertex (use `uniforms.time` to pick the
+/// animation instant), recomputes normals from the displaced surface and optionally
+/// evaluates the fragment stage at each vertex to get a color.
+pub fn bake_shader(
+    mesh: &Mesh,
+    shader: &dyn PlanetShader,
+    uniforms: &ShaderUniforms,
+    with_colors: bool,
+) -> BakedMesh {
+    let vertices = mesh
+        .vertices
+        .iter()
+        .map(|v| {
+            let (position, normal) = shader.vertex_shader(v.position, v.normal, v.uv, uniforms);
+            Vertex {
+                position,
+                normal,
+                uv: v.uv,
+            }
+        })
+        .collect();
+
+    let mut displaced = Mesh {
+        vertices,
+        indices: mesh.indices.clone(),
+        tangents: Vec::new(),
+    };
+    // The shaders only move positions along the normal, so the original normals are stale
+    displaced.recompute_normals();
+
+    let colors = with_colors.then(|| {
+        displaced
+            .vertices
+            .iter()
+            .map(|v| shader.fragment_shader(v.position, v.normal, v.uv, uniforms))
+            .collect()
+    });
+
+    BakedMesh {
+        mesh: displaced,
+        colors,
+    }
+}
+
+/// Writes the mesh to `.obj` or `.ply` depending on the file extension
+pub fn export_mesh(baked: &BakedMesh, path: &str) -> io::Result<()> {
+    let is_ply = Path::new(path)
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("ply"))
+        .unwrap_or(false);
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    if is_ply {
+        write_ply(baked, &mut writer)?;
+    } else {
+        write_obj(baked, &mut writer)?;
+    }
+    writer.flush()
+}
+
+/// Wavefront OBJ. Vertex colors use the common `v x y z r g b` extension.
+pub fn write_obj<W: Write>(baked: &BakedMesh, out: &mut W) -> io::Result<()> {
+    let mesh = &baked.mesh;
+    writeln!(out, "# Exported by planetas")?;
+    writeln!(out, "# {} vertices, {} triangles", mesh.vertices.len(), mesh.indices.len() / 3)?;
+
+    for (i, v) in mesh.vertices.iter().enumerate() {
+        match &baked.colors {
+            Some(colors) => {
+                let c = clamp_color(colors[i]);
+                writeln!(out, "v {} {} {} {} {} {}", v.position.x, v.position.y, v.position.z, c.r, c.g, c.b)?;
+            }
+            None => writeln!(out, "v {} {} {}", v.position.x, v.position.y, v.position.z)?,
+        }
+    }
+    for v in &mesh.vertices {
+        writeln!(out, "vt {} {}", v.uv.0, v.uv.1)?;
+    }
+    for v in &mesh.vertices {
+        writeln!(out, "vn {} {} {}", v.normal.x, v.normal.y, v.normal.z)?;
+    }
+
+    // OBJ indices are 1-based; position, UV and normal share the same index
+    for face in mesh.indices.chunks_exact(3) {
+        let (a, b, c) = (face[0] + 1, face[1] + 1, face[2] + 1);
+        writeln!(out, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+    }
+
+    Ok(())
+}
+
+/// ASCII PLY with positions, normals, UVs and optional 8-bit vertex colors
+pub fn write_ply<W: Write>(baked: &BakedMesh, out: &mut W) -> io::Result<()> {
+    let mesh = &baked.mesh;
+
+    writeln!(out, "ply")?;
+    writeln!(out, "format ascii 1.0")?;
+    writeln!(out, "comment Exported by planetas")?;
+    writeln!(out, "element vertex {}", mesh.vertices.len())?;
+    for property in ["x", "y", "z", "nx", "ny", "nz", "s", "t"] {
+        writeln!(out, "property float {}", property)?;
+    }
+    if baked.colors.is_some() {
+        for property in ["red", "green", "blue", "alpha"] {
+            writeln!(out, "property uchar {}", property)?;
+        }
+    }
+    writeln!(out, "element face {}", mesh.indices.len() / 3)?;
+    writeln!(out, "property list uchar int vertex_indices")?;
+    writeln!(out, "end_header")?;
+
+    for (i, v) in mesh.vertices.iter().enumerate() {
+        write!(
+            out,
+            "{} {} {} {} {} {} {} {}",
+            v.position.x, v.position.y, v.position.z,
+            v.normal.x, v.normal.y, v.normal.z,
+            v.uv.0, v.uv.1,
+        )?;
+        if let Some(colors) = &baked.colors {
+            let c = clamp_color(colors[i]);
+            let to_byte = |channel: f32| (channel * 255.0).round() as u8;
+            write!(out, " {} {} {} {}", to_byte(c.r), to_byte(c.g), to_byte(c.b), to_byte(c.a))?;
+        }
+        writeln!(out)?;
+    }
+
+    for face in mesh.indices.chunks_exact(3) {
+        writeln!(out, "3 {} {} {}", face[0], face[1], face[2])?;
+    }
+
+    Ok(())
+}
+
+fn clamp_color(c: ShaderColor) -> ShaderColor {
+    ShaderColor::new(c.r.clamp(0.0, 1.0), c.g.clamp(0.0, 1.0), c.b.clamp(0.0, 1.0), c.a.clamp(0.0, 1.0))
+}
@@ -2,119 +2,191 @@
 
 use crate::vector::Vector3;
 use crate::sphere::{Mesh, Vertex};
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
-pub fn load_obj(file_path: &str) -> Result<Mesh, String> {
-    let file = File::open(file_path)
-        .map_err(|e| format!("Error opening file {}: {}", file_path, e))?;
+/// Which index list a face element refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    Position,
+    TexCoord,
+    Normal,
+}
+
+impl fmt::Display for IndexKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexKind::Position => write!(f, "position"),
+            IndexKind::TexCoord => write!(f, "texture coordinate"),
+            IndexKind::Normal => write!(f, "normal"),
+        }
+    }
+}
+
+/// Errors produced while loading an OBJ file.
+/// Line and column numbers are 1-based.
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    Parse {
+        line: usize,
+        column: usize,
+        token: String,
+        expected: &'static str,
+    },
+    IndexOutOfRange {
+        line: usize,
+        column: usize,
+        kind: IndexKind,
+        index: i64,
+        count: usize,
+    },
+    EmptyMesh,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "error reading {}: {}", path, source),
+            ObjError::Parse { line, column, token, expected } => {
+                if token.is_empty() {
+                    write!(f, "line {}, column {}: missing {}", line, column, expected)
+                } else {
+                    write!(f, "line {}, column {}: invalid {} `{}`", line, column, expected, token)
+                }
+            }
+            ObjError::IndexOutOfRange { line, column, kind, index, count } => write!(
+                f,
+                "line {}, column {}: {} index {} out of range ({} defined)",
+                line, column, kind, index, count
+            ),
+            ObjError::EmptyMesh => write!(f, "no faces found in OBJ file"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// How strictly malformed input is treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObjMode {
+    /// Skip faces with bad or out-of-range indices and short records
+    #[default]
+    Lenient,
+    /// Reject the file at the first malformed record
+    Strict,
+}
+
+pub fn load_obj(file_path: &str) -> Result<Mesh, ObjError> {
+    load_obj_with_mode(file_path, ObjMode::Lenient)
+}
+
+pub fn load_obj_strict(file_path: &str) -> Result<Mesh, ObjError> {
+    load_obj_with_mode(file_path, ObjMode::Strict)
+}
+
+pub fn load_obj_with_mode(file_path: &str, mode: ObjMode) -> Result<Mesh, ObjError> {
+    let io_error = |source| ObjError::Io { path: file_path.to_string(), source };
+
+    let file = File::open(file_path).map_err(io_error)?;
     let reader = BufReader::new(file);
-    
+
     let mut positions = Vec::new();
     let mut normals = Vec::new();
     let mut uvs = Vec::new();
     let mut vertices = Vec::new();
     let mut indices = Vec::new();
-    
-    for line in reader.lines() {
-        let line = line.map_err(|e| format!("Error reading line: {}", e))?;
-        let line = line.trim();
-        
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
-        
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.is_empty() {
+
+    for (line_index, line) in reader.lines().enumerate() {
+        let line = line.map_err(io_error)?;
+        let line_number = line_index + 1;
+
+        // Tokens keep their 1-based column so errors can point at them
+        let parts = tokenize(&line);
+        if parts.is_empty() || parts[0].1.starts_with('#') {
             continue;
         }
-        
-        match parts[0] {
+
+        match parts[0].1 {
             "v" => {
                 // Vertex position
-                if parts.len() >= 4 {
-                    let x: f32 = parts[1].parse().map_err(|_| "Invalid vertex x coordinate")?;
-                    let y: f32 = parts[2].parse().map_err(|_| "Invalid vertex y coordinate")?;
-                    let z: f32 = parts[3].parse().map_err(|_| "Invalid vertex z coordinate")?;
+                if let Some([x, y, z]) = parse_floats(&parts, line_number, &line, mode, ["vertex x coordinate", "vertex y coordinate", "vertex z coordinate"])? {
                     positions.push(Vector3::new(x, y, z));
                 }
             }
             "vn" => {
                 // Vertex normal
-                if parts.len() >= 4 {
-                    let x: f32 = parts[1].parse().map_err(|_| "Invalid normal x coordinate")?;
-                    let y: f32 = parts[2].parse().map_err(|_| "Invalid normal y coordinate")?;
-                    let z: f32 = parts[3].parse().map_err(|_| "Invalid normal z coordinate")?;
+                if let Some([x, y, z]) = parse_floats(&parts, line_number, &line, mode, ["normal x coordinate", "normal y coordinate", "normal z coordinate"])? {
                     normals.push(Vector3::new(x, y, z));
                 }
             }
             "vt" => {
                 // Texture coordinate
-                if parts.len() >= 3 {
-                    let u: f32 = parts[1].parse().map_err(|_| "Invalid texture u coordinate")?;
-                    let v: f32 = parts[2].parse().map_err(|_| "Invalid texture v coordinate")?;
+                if let Some([u, v]) = parse_floats(&parts, line_number, &line, mode, ["texture u coordinate", "texture v coordinate"])? {
                     uvs.push((u, v));
                 }
             }
             "f" => {
-                // Face (triangle)
-                if parts.len() >= 4 {
-                    // Parse face indices (assuming triangulated mesh)
-                    for i in 1..4 {
-                        let face_data: Vec<&str> = parts[i].split('/').collect();
-                        
-                        // Position index (1-based in OBJ, convert to 0-based)
-                        let pos_idx: usize = face_data[0].parse::<usize>()
-                            .map_err(|_| "Invalid face position index")? - 1;
-                        
-                        // UV index (optional)
-                        let uv_idx = if face_data.len() > 1 && !face_data[1].is_empty() {
-                            face_data[1].parse::<usize>().ok().map(|idx| idx - 1)
-                        } else {
-                            None
-                        };
-                        
-                        // Normal index (optional)
-                        let normal_idx = if face_data.len() > 2 && !face_data[2].is_empty() {
-                            face_data[2].parse::<usize>().ok().map(|idx| idx - 1)
-                        } else {
-                            None
-                        };
-                        
-                        if pos_idx < positions.len() {
-                            let position = positions[pos_idx];
-                            
-                            // Use provided normal or calculate from position (for sphere)
-                            let normal = if let Some(idx) = normal_idx {
-                                if idx < normals.len() {
-                                    normals[idx]
-                                } else {
-                                    position.normalize() // Fallback for sphere
-                                }
-                            } else {
-                                position.normalize() // Calculate normal for sphere
-                            };
-                            
-                            // Use provided UV or calculate spherical UV
-                            let uv = if let Some(idx) = uv_idx {
-                                if idx < uvs.len() {
-                                    uvs[idx]
-                                } else {
-                                    calculate_spherical_uv(position)
-                                }
-                            } else {
-                                calculate_spherical_uv(position)
-                            };
-                            
-                            vertices.push(Vertex {
-                                position,
-                                normal,
-                                uv,
-                            });
-                            
-                            indices.push((vertices.len() - 1) as u32);
-                        }
+                // Face (polygons are triangulated as a fan around the first corner)
+                if parts.len() < 4 {
+                    if mode == ObjMode::Strict {
+                        return Err(ObjError::Parse {
+                            line: line_number,
+                            column: line.len() + 1,
+                            token: String::new(),
+                            expected: "face corner (at least three per face)",
+                        });
                     }
+                    continue;
+                }
+
+                let mut corners = Vec::with_capacity(parts.len() - 1);
+                for &(column, token) in &parts[1..] {
+                    match parse_face_corner(token, column, line_number, positions.len(), uvs.len(), normals.len()) {
+                        Ok(corner) => corners.push(corner),
+                        Err(error) if mode == ObjMode::Strict => return Err(error),
+                        Err(_) => break,
+                    }
+                }
+
+                // In lenient mode a single bad corner drops the whole face,
+                // so the remaining triangles keep their winding intact
+                if corners.len() != parts.len() - 1 {
+                    continue;
+                }
+
+                let base = vertices.len() as u32;
+                for (pos_idx, uv_idx, normal_idx) in corners.iter().copied() {
+                    let position = positions[pos_idx];
+
+                    // Use provided normal or calculate from position (for sphere)
+                    let normal = normal_idx.map(|idx| normals[idx]).unwrap_or_else(|| position.normalize());
+
+                    // Use provided UV or calculate spherical UV
+                    let uv = uv_idx.map(|idx| uvs[idx]).unwrap_or_else(|| calculate_spherical_uv(position));
+
+                    vertices.push(Vertex {
+                        position,
+                        normal,
+                        uv,
+                    });
+                }
+
+                for i in 1..corners.len() as u32 - 1 {
+                    indices.push(base);
+                    indices.push(base + i);
+                    indices.push(base + i + 1);
                 }
             }
             _ => {
@@ -122,22 +194,209 @@ pub fn load_obj(file_path: &str) -> Result<Mesh, String> {
             }
         }
     }
-    
-    if vertices.is_empty() {
-        return Err("No vertices found in OBJ file".to_string());
+
+    if indices.is_empty() {
+        return Err(ObjError::EmptyMesh);
     }
-    
+
     println!("Loaded OBJ: {} vertices, {} indices", vertices.len(), indices.len());
-    
+
     Ok(Mesh {
         vertices,
         indices,
+        tangents: Vec::new(),
     })
 }
 
-fn calculate_spherical_uv(position: Vector3) -> (f32, f32) {
+/// Splits a line on whitespace, keeping the 1-based column of each token
+fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+
+    for (offset, ch) in line.char_indices() {
+        if ch.is_whitespace() {
+            if let Some(begin) = start.take() {
+                tokens.push((begin + 1, &line[begin..offset]));
+            }
+        } else if start.is_none() {
+            start = Some(offset);
+        }
+    }
+    if let Some(begin) = start {
+        tokens.push((begin + 1, &line[begin..]));
+    }
+
+    tokens
+}
+
+/// Parses the N floats following the keyword of a `v`/`vn`/`vt` record.
+/// Returns `Ok(None)` when a lenient load should skip a short record.
+fn parse_floats<const N: usize>(
+    parts: &[(usize, &str)],
+    line_number: usize,
+    line: &str,
+    mode: ObjMode,
+    names: [&'static str; N],
+) -> Result<Option<[f32; N]>, ObjError> {
+    let mut values = [0.0; N];
+
+    for (i, name) in names.iter().enumerate() {
+        let Some(&(column, token)) = parts.get(i + 1) else {
+            if mode == ObjMode::Strict {
+                return Err(ObjError::Parse {
+                    line: line_number,
+                    column: line.len() + 1,
+                    token: String::new(),
+                    expected: name,
+                });
+            }
+            return Ok(None);
+        };
+
+        values[i] = token.parse().map_err(|_| ObjError::Parse {
+            line: line_number,
+            column,
+            token: token.to_string(),
+            expected: name,
+        })?;
+    }
+
+    Ok(Some(values))
+}
+
+/// Parses a `v`, `v/vt`, `v//vn` or `v/vt/vn` face corner into 0-based indices
+fn parse_face_corner(
+    token: &str,
+    column: usize,
+    line_number: usize,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<(usize, Option<usize>, Option<usize>), ObjError> {
+    let mut offset = 0;
+    let mut resolved = [None; 3];
+    let lists = [
+        (IndexKind::Position, position_count, "face position index"),
+        (IndexKind::TexCoord, uv_count, "face texture coordinate index"),
+        (IndexKind::Normal, normal_count, "face normal index"),
+    ];
+
+    for (slot, field) in token.split('/').enumerate() {
+        let field_column = column + offset;
+        offset += field.len() + 1;
+
+        let Some(&(kind, count, expected)) = lists.get(slot) else {
+            return Err(ObjError::Parse {
+                line: line_number,
+                column: field_column,
+                token: token.to_string(),
+                expected: "face corner",
+            });
+        };
+
+        // Only the position index is mandatory
+        if field.is_empty() && slot > 0 {
+            continue;
+        }
+
+        let raw: i64 = field.parse().map_err(|_| ObjError::Parse {
+            line: line_number,
+            column: field_column,
+            token: field.to_string(),
+            expected,
+        })?;
+
+        resolved[slot] = Some(resolve_index(raw, count).ok_or(ObjError::IndexOutOfRange {
+            line: line_number,
+            column: field_column,
+            kind,
+            index: raw,
+            count,
+        })?);
+    }
+
+    match resolved {
+        [Some(position), uv, normal] => Ok((position, uv, normal)),
+        _ => unreachable!("position index is parsed or rejected above"),
+    }
+}
+
+/// OBJ indices are 1-based; negative values count back from the last element
+fn resolve_index(raw: i64, count: usize) -> Option<usize> {
+    let index = if raw > 0 {
+        raw - 1
+    } else if raw < 0 {
+        count as i64 + raw
+    } else {
+        return None;
+    };
+
+    if index >= 0 && (index as usize) < count {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+pub fn calculate_spherical_uv(position: Vector3) -> (f32, f32) {
     let normalized = position.normalize();
     let u = 0.5 + (normalized.z.atan2(normalized.x)) / (2.0 * std::f32::consts::PI);
     let v = 0.5 - (normalized.y.asin()) / std::f32::consts::PI;
     (u, v)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+    /// Writes `source` to a temporary file and loads it
+    fn load_source(name: &str, source: &str, mode: ObjMode) -> Result<Mesh, ObjError> {
+        let path = std::env::temp_dir().join(format!("planetas-{}-{}.obj", std::process::id(), name));
+        std::fs::write(&path, source).unwrap();
+        let result = load_obj_with_mode(path.to_str().unwrap(), mode);
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn malformed_vertex_reports_line_and_column() {
+        let source = "# header\nv 1.0 abc 3.0\n";
+        for mode in [ObjMode::Lenient, ObjMode::Strict] {
+            match load_source("malformed", source, mode) {
+                Err(ObjError::Parse { line, column, token, expected }) => {
+                    assert_eq!((line, column), (2, 7));
+                    assert_eq!(token, "abc");
+                    assert_eq!(expected, "vertex y coordinate");
+                }
+                other => panic!("expected a parse error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_face_index_depends_on_mode() {
+        let source = format!("{}f 1 2 3\nf 1 2 9\n", TRIANGLE);
+
+        match load_source("range-strict", &source, ObjMode::Strict) {
+            Err(ObjError::IndexOutOfRange { line, column, kind, index, count }) => {
+                assert_eq!((line, column), (5, 7));
+                assert_eq!((kind, index, count), (IndexKind::Position, 9, 3));
+            }
+            other => panic!("expected an index error, got {:?}", other),
+        }
+
+        // Lenient drops the bad face and keeps the good one
+        let mesh = load_source("range-lenient", &source, ObjMode::Lenient).unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.vertices.len(), 3);
+    }
+
+    #[test]
+    fn empty_file_has_no_mesh() {
+        for mode in [ObjMode::Lenient, ObjMode::Strict] {
+            assert!(matches!(load_source("empty", "", mode), Err(ObjError::EmptyMesh)));
+        }
+    }
+}
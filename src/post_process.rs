@@ -0,0 +1,644 @@
+use crate::framebuffer::Framebuffer;
+use crate::shaders::ShaderColor;
+use crate::tone_mapping::ToneMapping;
+use rayon::prelude::*;
+use std::fs;
+
+/// Where in the chain an effect runs relative to tone mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostStage {
+    /// On linear HDR radiance (bloom, depth of field)
+    Hdr,
+    /// On the tone-mapped, display-encoded image in [0, 1] (grading, lens and film effects)
+    Display,
+}
+
+/// The finished frame as an effect sees it: color to rewrite and the depth it was rendered with
+pub struct PostBuffers<'a> {
+    pub color: &'a mut [ShaderColor],
+    /// NDC depth per pixel, `f32::INFINITY` where nothing was drawn
+    pub depth: &'a [f32],
+    pub width: usize,
+    pub height: usize,
+    pub time: f32,
+}
+
+/// Bilinear read of a color buffer at pixel coordinates (centers at i + 0.5)
+fn sample_bilinear(source: &[ShaderColor], width: usize, height: usize, x: f32, y: f32) -> ShaderColor {
+    let fx = (x - 0.5).clamp(0.0, (width - 1) as f32);
+    let fy = (y - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+
+    let top = mix(source[y0 * width + x0], source[y0 * width + x1], tx);
+    let bottom = mix(source[y1 * width + x0], source[y1 * width + x1], tx);
+    mix(top, bottom, ty)
+}
+
+/// One step of the post-processing chain
+pub trait PostEffect: Send + Sync {
+    /// Short lowercase name used to toggle the effect
+    fn name(&self) -> &'static str;
+    fn stage(&self) -> PostStage;
+    fn apply(&self, buffers: &mut PostBuffers);
+}
+
+struct ChainEntry {
+    effect: Box<dyn PostEffect>,
+    enabled: bool,
+}
+
+/// Ordered list of effects between rendering and the 8-bit image: the HDR effects
+/// run first, then tone mapping, then the display effects, each in insertion order.
+#[derive(Default)]
+pub struct PostProcessChain {
+    entries: Vec<ChainEntry>,
+}
+
+impl PostProcessChain {
+    pub fn new() -> Self {
+        PostProcessChain::default()
+    }
+
+    pub fn with<E: PostEffect + 'static>(mut self, effect: E, enabled: bool) -> Self {
+        self.push(effect, enabled);
+        self
+    }
+
+    pub fn push<E: PostEffect + 'static>(&mut self, effect: E, enabled: bool) {
+        self.entries.push(ChainEntry { effect: Box::new(effect), enabled });
+    }
+
+    /// Swaps the effect with the same name for `effect`, keeping its place and its
+    /// enabled state; appends it disabled if there was none
+    pub fn replace<E: PostEffect + 'static>(&mut self, effect: E) {
+        match self.entries.iter_mut().find(|entry| entry.effect.name() == effect.name()) {
+            Some(entry) => entry.effect = Box::new(effect),
+            None => self.push(effect, false),
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.entries.iter().any(|entry| entry.enabled && entry.effect.name() == name)
+    }
+
+    /// Enables or disables the effects called `name`; false if there is none
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let mut found = false;
+        for entry in self.entries.iter_mut().filter(|entry| entry.effect.name() == name) {
+            entry.enabled = enabled;
+            found = true;
+        }
+        found
+    }
+
+    pub fn toggle(&mut self, name: &str) {
+        let enabled = self.is_enabled(name);
+        self.set_enabled(name, !enabled);
+    }
+
+    /// Names of the enabled effects, in the order they run
+    pub fn enabled_names(&self) -> Vec<&'static str> {
+        let stage_order = [PostStage::Hdr, PostStage::Display];
+        stage_order
+            .iter()
+            .flat_map(|&stage| self.entries.iter().filter(move |entry| entry.enabled && entry.effect.stage() == stage))
+            .map(|entry| entry.effect.name())
+            .collect()
+    }
+
+    /// Runs the chain on the framebuffer's HDR color and writes the final 8-bit pixels
+    pub fn run(&self, framebuffer: &mut Framebuffer, tone_mapping: &ToneMapping, time: f32) {
+        let mut buffers = PostBuffers {
+            color: &mut framebuffer.hdr,
+            depth: &framebuffer.zbuffer,
+            width: framebuffer.width as usize,
+            height: framebuffer.height as usize,
+            time,
+        };
+
+        self.apply_stage(PostStage::Hdr, &mut buffers);
+        buffers.color.par_iter_mut().for_each(|color| *color = tone_mapping.apply(*color));
+        self.apply_stage(PostStage::Display, &mut buffers);
+
+        // The color is already display-encoded, only the 8-bit conversion is left
+        framebuffer.resolve(&ToneMapping::passthrough());
+    }
+
+    fn apply_stage(&self, stage: PostStage, buffers: &mut PostBuffers) {
+        for entry in self.entries.iter().filter(|entry| entry.enabled && entry.effect.stage() == stage) {
+            entry.effect.apply(buffers);
+        }
+    }
+}
+
+/// Darkens the image towards the corners
+#[derive(Debug, Clone, Copy)]
+pub struct Vignette {
+    /// Darkening at the corners, 0 = none, 1 = black
+    pub strength: f32,
+    /// Distance from the center (1 = corner) where the darkening starts
+    pub radius: f32,
+    /// Width of the transition from the radius to full strength
+    pub softness: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Vignette { strength: 0.45, radius: 0.55, softness: 0.5 }
+    }
+}
+
+impl PostEffect for Vignette {
+    fn name(&self) -> &'static str {
+        "vignette"
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Display
+    }
+
+    fn apply(&self, buffers: &mut PostBuffers) {
+        let (width, height) = (buffers.width, buffers.height);
+        let (center_x, center_y) = (width as f32 * 0.5, height as f32 * 0.5);
+        let half_diagonal = (center_x * center_x + center_y * center_y).sqrt();
+
+        buffers.color.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            let dy = y as f32 + 0.5 - center_y;
+            for (x, color) in row.iter_mut().enumerate() {
+                let dx = x as f32 + 0.5 - center_x;
+                let distance = (dx * dx + dy * dy).sqrt() / half_diagonal;
+                let falloff = smoothstep(self.radius, self.radius + self.softness, distance);
+                *color = scale(*color, 1.0 - self.strength * falloff);
+            }
+        });
+    }
+}
+
+/// Splits red and blue radially, like a cheap lens that does not focus every
+/// wavelength on the same spot
+#[derive(Debug, Clone, Copy)]
+pub struct ChromaticAberration {
+    /// Shift of red and blue at the corners, as a fraction of the distance to the center
+    pub strength: f32,
+}
+
+impl Default for ChromaticAberration {
+    fn default() -> Self {
+        ChromaticAberration { strength: 0.006 }
+    }
+}
+
+impl PostEffect for ChromaticAberration {
+    fn name(&self) -> &'static str {
+        "chromatic"
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Display
+    }
+
+    fn apply(&self, buffers: &mut PostBuffers) {
+        let (width, height) = (buffers.width, buffers.height);
+        let source = buffers.color.to_vec();
+        let (center_x, center_y) = (width as f32 * 0.5, height as f32 * 0.5);
+        let half_diagonal = (center_x * center_x + center_y * center_y).sqrt();
+
+        buffers.color.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            let py = y as f32 + 0.5;
+            for (x, color) in row.iter_mut().enumerate() {
+                let px = x as f32 + 0.5;
+                let (dx, dy) = (px - center_x, py - center_y);
+                // Grows with the distance: none in the center, strongest at the corners
+                let shift = self.strength * (dx * dx + dy * dy).sqrt() / half_diagonal;
+                let red = sample_bilinear(&source, width, height, px + dx * shift, py + dy * shift);
+                let blue = sample_bilinear(&source, width, height, px - dx * shift, py - dy * shift);
+                color.r = red.r;
+                color.b = blue.b;
+            }
+        });
+    }
+}
+
+/// Animated monochrome noise, stronger in the midtones like film grain
+#[derive(Debug, Clone, Copy)]
+pub struct FilmGrain {
+    pub intensity: f32,
+    /// New grain pattern this many times per second
+    pub frame_rate: f32,
+}
+
+impl Default for FilmGrain {
+    fn default() -> Self {
+        FilmGrain { intensity: 0.06, frame_rate: 24.0 }
+    }
+}
+
+impl PostEffect for FilmGrain {
+    fn name(&self) -> &'static str {
+        "grain"
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Display
+    }
+
+    fn apply(&self, buffers: &mut PostBuffers) {
+        let width = buffers.width;
+        let frame = (buffers.time * self.frame_rate) as u32;
+
+        buffers.color.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, color) in row.iter_mut().enumerate() {
+                let noise = hash(x as u32, y as u32, frame) * 2.0 - 1.0;
+                let luma = luminance(*color).clamp(0.0, 1.0);
+                let amount = noise * self.intensity * 4.0 * luma * (1.0 - luma);
+                color.r = (color.r + amount).max(0.0);
+                color.g = (color.g + amount).max(0.0);
+                color.b = (color.b + amount).max(0.0);
+            }
+        });
+    }
+}
+
+/// 3D color lookup table: display color in, graded color out
+#[derive(Debug, Clone)]
+pub struct ColorLut {
+    size: usize,
+    /// `size`³ entries, red varying fastest (the .cube layout)
+    data: Vec<[f32; 3]>,
+}
+
+impl ColorLut {
+    /// Table with `size` entries per axis filled by `grade`
+    pub fn from_fn<F: Fn([f32; 3]) -> [f32; 3]>(size: usize, grade: F) -> Self {
+        let size = size.max(2);
+        let step = 1.0 / (size - 1) as f32;
+        let mut data = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.push(grade([r as f32 * step, g as f32 * step, b as f32 * step]));
+                }
+            }
+        }
+        ColorLut { size, data }
+    }
+
+    /// Built-in look: cool teal shadows, warm highlights and a little extra contrast
+    pub fn cinematic() -> Self {
+        ColorLut::from_fn(17, |[r, g, b]| {
+            let luma = r * 0.2126 + g * 0.7152 + b * 0.0722;
+            let contrast = |value: f32| (value + (smoothstep(0.0, 1.0, value) - value) * 0.35).clamp(0.0, 1.0);
+            let shadows = (1.0 - luma) * 0.06;
+            let highlights = luma * 0.06;
+            [
+                contrast(r - shadows + highlights),
+                contrast(g + shadows * 0.3),
+                contrast(b + shadows - highlights),
+            ]
+        })
+    }
+
+    /// Loads a 3D LUT in the Adobe/Resolve .cube format
+    pub fn load_cube(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read LUT file: {}", e))?;
+        let mut size = None;
+        let mut data = Vec::new();
+
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let keyword = parts.next().unwrap_or_default();
+            match keyword {
+                "LUT_3D_SIZE" => {
+                    let value = parts.next().and_then(|n| n.parse::<usize>().ok());
+                    size = Some(value.filter(|&n| n >= 2).ok_or_else(|| format!("Invalid LUT_3D_SIZE at line {}", line_number + 1))?);
+                }
+                "LUT_1D_SIZE" => return Err("1D LUTs are not supported".to_string()),
+                "TITLE" | "DOMAIN_MIN" | "DOMAIN_MAX" => {}
+                _ => {
+                    let values: Vec<f32> = line.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+                    if values.len() != 3 {
+                        return Err(format!("Invalid LUT entry at line {}", line_number + 1));
+                    }
+                    data.push([values[0], values[1], values[2]]);
+                }
+            }
+        }
+
+        let size = size.ok_or("Missing LUT_3D_SIZE")?;
+        if data.len() != size * size * size {
+            return Err(format!("Expected {} LUT entries, found {}", size * size * size, data.len()));
+        }
+        Ok(ColorLut { size, data })
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.data[(b * self.size + g) * self.size + r]
+    }
+
+    /// Trilinear lookup of a color in [0, 1]
+    pub fn sample(&self, color: [f32; 3]) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        let scaled = color.map(|channel| channel.clamp(0.0, 1.0) * last);
+        let low = scaled.map(|channel| (channel.floor() as usize).min(self.size - 2));
+        let t = [scaled[0] - low[0] as f32, scaled[1] - low[1] as f32, scaled[2] - low[2] as f32];
+
+        let mut result = [0.0; 3];
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let weight = (0..3)
+                .map(|axis| if offset[axis] == 1 { t[axis] } else { 1.0 - t[axis] })
+                .product::<f32>();
+            let value = self.entry(low[0] + offset[0], low[1] + offset[1], low[2] + offset[2]);
+            result[0] += value[0] * weight;
+            result[1] += value[1] * weight;
+            result[2] += value[2] * weight;
+        }
+        result
+    }
+}
+
+/// Fast approximate anti-aliasing (Lottes' FXAA 3.11 quality preset): finds edges
+/// from the luma contrast, walks along them to their ends and re-samples each pixel
+/// shifted towards the other side of the edge
+#[derive(Debug, Clone, Copy)]
+pub struct Fxaa {
+    /// Contrast below which a pixel is never treated as an edge (dark areas)
+    pub edge_threshold_min: f32,
+    /// Contrast, relative to the brightest neighbour, needed to be an edge
+    pub edge_threshold: f32,
+    /// How much single-pixel details (thin lines, sparkles) are softened
+    pub subpixel_quality: f32,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Fxaa { edge_threshold_min: 0.0312, edge_threshold: 0.125, subpixel_quality: 0.75 }
+    }
+}
+
+/// Step sizes in pixels of the search along an edge; it speeds up the longer the edge
+const FXAA_STEPS: [f32; 12] = [1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0];
+
+impl PostEffect for Fxaa {
+    fn name(&self) -> &'static str {
+        "fxaa"
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Display
+    }
+
+    fn apply(&self, buffers: &mut PostBuffers) {
+        let (width, height) = (buffers.width, buffers.height);
+        let source = buffers.color.to_vec();
+        let lumas: Vec<f32> = source.par_iter().map(|&color| luminance(color)).collect();
+        let luma = |x: isize, y: isize| {
+            lumas[y.clamp(0, height as isize - 1) as usize * width + x.clamp(0, width as isize - 1) as usize]
+        };
+
+        buffers.color.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, color) in row.iter_mut().enumerate() {
+                let (xi, yi) = (x as isize, y as isize);
+                let center = luma(xi, yi);
+                let (up, down, left, right) = (luma(xi, yi - 1), luma(xi, yi + 1), luma(xi - 1, yi), luma(xi + 1, yi));
+                let luma_min = center.min(up).min(down).min(left).min(right);
+                let luma_max = center.max(up).max(down).max(left).max(right);
+                let range = luma_max - luma_min;
+                if range < self.edge_threshold_min.max(luma_max * self.edge_threshold) {
+                    continue;
+                }
+
+                let (up_left, up_right) = (luma(xi - 1, yi - 1), luma(xi + 1, yi - 1));
+                let (down_left, down_right) = (luma(xi - 1, yi + 1), luma(xi + 1, yi + 1));
+                let vertical_pair = up + down;
+                let horizontal_pair = left + right;
+                let left_corners = up_left + down_left;
+                let right_corners = up_right + down_right;
+                let up_corners = up_left + up_right;
+                let down_corners = down_left + down_right;
+
+                // The edge runs along the direction with the smaller second derivative
+                let horizontal_change = (-2.0 * left + left_corners).abs()
+                    + 2.0 * (-2.0 * center + vertical_pair).abs()
+                    + (-2.0 * right + right_corners).abs();
+                let vertical_change = (-2.0 * up + up_corners).abs()
+                    + 2.0 * (-2.0 * center + horizontal_pair).abs()
+                    + (-2.0 * down + down_corners).abs();
+                let horizontal = horizontal_change >= vertical_change;
+
+                // Side of the edge with the steepest gradient: -1 = up/left, +1 = down/right
+                let (luma_before, luma_after) = if horizontal { (up, down) } else { (left, right) };
+                let (gradient_before, gradient_after) = (luma_before - center, luma_after - center);
+                let before_steepest = gradient_before.abs() >= gradient_after.abs();
+                let gradient_scaled = 0.25 * gradient_before.abs().max(gradient_after.abs());
+                let (step, local_average) = if before_steepest {
+                    (-1.0, 0.5 * (luma_before + center))
+                } else {
+                    (1.0, 0.5 * (luma_after + center))
+                };
+
+                // Walk both ways along the edge, half a pixel towards its other side
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let (start_x, start_y) = if horizontal { (px, py + step * 0.5) } else { (px + step * 0.5, py) };
+                let (offset_x, offset_y) = if horizontal { (1.0, 0.0) } else { (0.0, 1.0) };
+                let luma_at = |position: (f32, f32)| sample_luma(&lumas, width, height, position.0, position.1) - local_average;
+
+                let mut position_before = (start_x - offset_x, start_y - offset_y);
+                let mut position_after = (start_x + offset_x, start_y + offset_y);
+                let mut end_before = luma_at(position_before);
+                let mut end_after = luma_at(position_after);
+                for &step_size in &FXAA_STEPS[1..] {
+                    let reached_before = end_before.abs() >= gradient_scaled;
+                    let reached_after = end_after.abs() >= gradient_scaled;
+                    if reached_before && reached_after {
+                        break;
+                    }
+                    if !reached_before {
+                        position_before = (position_before.0 - offset_x * step_size, position_before.1 - offset_y * step_size);
+                        end_before = luma_at(position_before);
+                    }
+                    if !reached_after {
+                        position_after = (position_after.0 + offset_x * step_size, position_after.1 + offset_y * step_size);
+                        end_after = luma_at(position_after);
+                    }
+                }
+
+                let distance_before = if horizontal { px - position_before.0 } else { py - position_before.1 };
+                let distance_after = if horizontal { position_after.0 - px } else { position_after.1 - py };
+                let closer_before = distance_before < distance_after;
+                let edge_length = distance_before + distance_after;
+                let pixel_offset = -distance_before.min(distance_after) / edge_length + 0.5;
+
+                // Only move if the closest end agrees with which side of the edge the center is on
+                let center_smaller = center < local_average;
+                let end = if closer_before { end_before } else { end_after };
+                let edge_offset = if (end < 0.0) != center_smaller { pixel_offset } else { 0.0 };
+
+                let neighbours_average = (2.0 * (vertical_pair + horizontal_pair) + left_corners + right_corners) / 12.0;
+                let subpixel = ((neighbours_average - center).abs() / range).clamp(0.0, 1.0);
+                let subpixel = (-2.0 * subpixel + 3.0) * subpixel * subpixel;
+                let offset = edge_offset.max(subpixel * subpixel * self.subpixel_quality);
+
+                let (sample_x, sample_y) = if horizontal { (px, py + offset * step) } else { (px + offset * step, py) };
+                *color = sample_bilinear(&source, width, height, sample_x, sample_y);
+            }
+        });
+    }
+}
+
+/// Bilinear read of a luma buffer, like `sample_bilinear`
+fn sample_luma(source: &[f32], width: usize, height: usize, x: f32, y: f32) -> f32 {
+    let fx = (x - 0.5).clamp(0.0, (width - 1) as f32);
+    let fy = (y - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+
+    let top = source[y0 * width + x0] + (source[y0 * width + x1] - source[y0 * width + x0]) * tx;
+    let bottom = source[y1 * width + x0] + (source[y1 * width + x1] - source[y1 * width + x0]) * tx;
+    top + (bottom - top) * ty
+}
+
+/// Color grading through a 3D LUT
+#[derive(Debug, Clone)]
+pub struct ColorGrading {
+    pub lut: ColorLut,
+    /// Blend between the original (0) and the graded color (1)
+    pub strength: f32,
+}
+
+impl PostEffect for ColorGrading {
+    fn name(&self) -> &'static str {
+        "lut"
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Display
+    }
+
+    fn apply(&self, buffers: &mut PostBuffers) {
+        buffers.color.par_iter_mut().for_each(|color| {
+            let graded = self.lut.sample([color.r, color.g, color.b]);
+            let graded = ShaderColor::new(graded[0], graded[1], graded[2], color.a);
+            *color = mix(*color, graded, self.strength);
+        });
+    }
+}
+
+/// Blurs what is away from the focus distance, reading the distance from the z-buffer
+#[derive(Debug, Clone, Copy)]
+pub struct DepthOfField {
+    /// Distance from the camera kept sharp; `None` focuses on whatever is at the center of the screen
+    pub focus_distance: Option<f32>,
+    /// Blur radius in pixels per unit of |1/focus - 1/distance| (a thin lens' circle of confusion)
+    pub aperture: f32,
+    /// Largest blur radius in pixels
+    pub max_radius: f32,
+    /// Clip planes of the projection, to turn NDC depth back into distance
+    pub near: f32,
+    pub far: f32,
+}
+
+/// Taps of the gather kernel, spread over the disk along a golden-angle spiral
+const DOF_SAMPLES: usize = 24;
+
+impl DepthOfField {
+    pub fn new(near: f32, far: f32) -> Self {
+        DepthOfField { focus_distance: None, aperture: 30.0, max_radius: 8.0, near, far }
+    }
+
+    fn linear_depth(&self, depth: f32) -> f32 {
+        if !depth.is_finite() {
+            return self.far;
+        }
+        let (n, f) = (self.near, self.far);
+        (2.0 * n * f / (f + n - depth.clamp(-1.0, 1.0) * (f - n))).clamp(n, f)
+    }
+
+    fn blur_radius(&self, distance: f32, focus: f32) -> f32 {
+        ((1.0 / focus - 1.0 / distance).abs() * self.aperture).min(self.max_radius)
+    }
+}
+
+impl PostEffect for DepthOfField {
+    fn name(&self) -> &'static str {
+        "dof"
+    }
+
+    fn stage(&self) -> PostStage {
+        PostStage::Hdr
+    }
+
+    fn apply(&self, buffers: &mut PostBuffers) {
+        let (width, height) = (buffers.width, buffers.height);
+        let focus = self.focus_distance.unwrap_or_else(|| {
+            self.linear_depth(buffers.depth[(height / 2) * width + width / 2])
+        });
+        let radii: Vec<f32> = buffers
+            .depth
+            .par_iter()
+            .map(|&depth| self.blur_radius(self.linear_depth(depth), focus))
+            .collect();
+        let source = buffers.color.to_vec();
+        let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+
+        buffers.color.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, color) in row.iter_mut().enumerate() {
+                let radius = radii[y * width + x];
+                if radius < 0.5 {
+                    continue;
+                }
+                let (mut sum, mut total) = (source[y * width + x], 1.0);
+                for i in 1..=DOF_SAMPLES {
+                    let distance = radius * (i as f32 / DOF_SAMPLES as f32).sqrt();
+                    let angle = i as f32 * golden_angle;
+                    let sx = (x as f32 + angle.cos() * distance).round().clamp(0.0, (width - 1) as f32) as usize;
+                    let sy = (y as f32 + angle.sin() * distance).round().clamp(0.0, (height - 1) as f32) as usize;
+                    // A sharp neighbour does not spread over this pixel, so in-focus
+                    // edges do not bleed into the blurred background
+                    if radii[sy * width + sx] < distance {
+                        continue;
+                    }
+                    let sample = source[sy * width + sx];
+                    sum.r += sample.r;
+                    sum.g += sample.g;
+                    sum.b += sample.b;
+                    total += 1.0;
+                }
+                *color = ShaderColor::new(sum.r / total, sum.g / total, sum.b / total, color.a);
+            }
+        });
+    }
+}
+
+fn mix(a: ShaderColor, b: ShaderColor, t: f32) -> ShaderColor {
+    ShaderColor::new(a.r + (b.r - a.r) * t, a.g + (b.g - a.g) * t, a.b + (b.b - a.b) * t, a.a + (b.a - a.a) * t)
+}
+
+fn scale(color: ShaderColor, factor: f32) -> ShaderColor {
+    ShaderColor::new(color.r * factor, color.g * factor, color.b * factor, color.a)
+}
+
+fn luminance(color: ShaderColor) -> f32 {
+    color.r * 0.2126 + color.g * 0.7152 + color.b * 0.0722
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Integer hash of a pixel and a frame number to [0, 1)
+fn hash(x: u32, y: u32, frame: u32) -> f32 {
+    let mut h = x.wrapping_mul(0x8da6_b343) ^ y.wrapping_mul(0xd816_3841) ^ frame.wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0x5bd1_e995);
+    h ^= h >> 15;
+    (h >> 8) as f32 / (1u32 << 24) as f32
+}
@@ -0,0 +1,231 @@
+#![allow(dead_code)]
+
+use crate::matrix::{new_matrix4, Matrix};
+use crate::vector::Vector3;
+
+/// Rotation as a unit quaternion x i + y j + z k + w. Unlike a rotation matrix it
+/// composes without drifting away from a rotation and interpolates smoothly (slerp).
+/// The bodies do not store one: each frame they compose their orientation from the
+/// axial tilt and the spin angle with `tilted_spin`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quaternion {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quaternion {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Quaternion { x, y, z, w }
+    }
+
+    pub fn identity() -> Self {
+        Quaternion::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// Rotation by `angle` radians around `axis` (normalized here), with the same
+    /// sense as `matrix::create_rotation_axis`
+    pub fn from_axis_angle(axis: Vector3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (sin_half, cos_half) = (angle * 0.5).sin_cos();
+        Quaternion::new(axis.x * sin_half, axis.y * sin_half, axis.z * sin_half, cos_half)
+    }
+
+    /// Orientation of a body that has turned `spin` radians around its own axis,
+    /// where the axis is tilted `axial_tilt` radians from +y (leaning around z)
+    pub fn tilted_spin(axial_tilt: f32, spin: f32) -> Self {
+        Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), axial_tilt)
+            * Quaternion::from_axis_angle(Vector3::new(0.0, 1.0, 0.0), spin)
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let len = self.length();
+        if len > 0.0 {
+            Quaternion::new(self.x / len, self.y / len, self.z / len, self.w / len)
+        } else {
+            Quaternion::identity()
+        }
+    }
+
+    /// The opposite rotation (the inverse, for unit quaternions)
+    pub fn conjugate(&self) -> Self {
+        Quaternion::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    /// Rotates a vector: q v q*, expanded so it costs two cross products
+    pub fn rotate(&self, v: &Vector3) -> Vector3 {
+        let axis = Vector3::new(self.x, self.y, self.z);
+        let t = axis.cross(v) * 2.0;
+        *v + t * self.w + axis.cross(&t)
+    }
+
+    /// Equivalent rotation matrix (the quaternion must be unit)
+    pub fn to_matrix(self) -> Matrix {
+        let Quaternion { x, y, z, w } = self;
+        new_matrix4(
+            1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - z * w), 2.0 * (x * z + y * w), 0.0,
+            2.0 * (x * y + z * w), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - x * w), 0.0,
+            2.0 * (x * z - y * w), 2.0 * (y * z + x * w), 1.0 - 2.0 * (x * x + y * y), 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        )
+    }
+
+    /// Spherical interpolation at constant angular speed: `self` at t = 0, `other`
+    /// at t = 1, always along the shorter way around
+    pub fn slerp(&self, other: &Quaternion, t: f32) -> Quaternion {
+        let mut cos_theta = self.dot(other);
+        let mut other = *other;
+        if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            other = Quaternion::new(-other.x, -other.y, -other.z, -other.w);
+        }
+
+        // Nearly the same rotation: sin(theta) would vanish, interpolate linearly
+        let (a, b) = if cos_theta > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos_theta.acos();
+            let sin_theta = theta.sin();
+            (((1.0 - t) * theta).sin() / sin_theta, (t * theta).sin() / sin_theta)
+        };
+        Quaternion::new(
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+            self.w * a + other.w * b,
+        )
+        .normalize()
+    }
+}
+
+/// Composition: `a * b` rotates by `b` first, then by `a`, like matrices
+impl std::ops::Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, other: Quaternion) -> Quaternion {
+        Quaternion::new(
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+        )
+    }
+}
+
+impl From<Quaternion> for nalgebra::UnitQuaternion<f32> {
+    fn from(q: Quaternion) -> Self {
+        nalgebra::UnitQuaternion::from_quaternion(nalgebra::Quaternion::new(q.w, q.x, q.y, q.z))
+    }
+}
+
+impl From<nalgebra::UnitQuaternion<f32>> for Quaternion {
+    fn from(q: nalgebra::UnitQuaternion<f32>) -> Self {
+        Quaternion::new(q.i, q.j, q.k, q.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::FRAC_PI_2;
+
+    fn random_vector(rng: &mut StdRng) -> Vector3 {
+        Vector3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0))
+    }
+
+    fn random_rotation(rng: &mut StdRng) -> (Vector3, f32) {
+        loop {
+            let axis = random_vector(rng);
+            if axis.length() > 0.1 {
+                return (axis, rng.gen_range(-3.0..3.0));
+            }
+        }
+    }
+
+    fn assert_vector_close(a: Vector3, b: Vector3, tolerance: f32) {
+        assert!((a - b).length() <= tolerance, "{:?} vs {:?}", a, b);
+    }
+
+    #[test]
+    fn rotation_matches_the_axis_matrix() {
+        let mut rng = StdRng::seed_from_u64(41);
+        for _ in 0..200 {
+            let (axis, angle) = random_rotation(&mut rng);
+            let q = Quaternion::from_axis_angle(axis, angle);
+            let m = matrix::create_rotation_axis(axis, angle);
+            let v = random_vector(&mut rng);
+            assert_vector_close(q.rotate(&v), m.transform_vector(&v), 1e-5);
+            assert_vector_close(q.to_matrix().transform_vector(&v), m.transform_vector(&v), 1e-5);
+        }
+    }
+
+    #[test]
+    fn axis_rotations_agree_with_the_matrix_helpers() {
+        let v = Vector3::new(0.3, -0.7, 0.5);
+        let cases = [
+            (Vector3::new(1.0, 0.0, 0.0), matrix::create_rotation_x(0.8)),
+            (Vector3::new(0.0, 1.0, 0.0), matrix::create_rotation_y(0.8)),
+            (Vector3::new(0.0, 0.0, 1.0), matrix::create_rotation_z(0.8)),
+        ];
+        for (axis, m) in cases {
+            assert_vector_close(Quaternion::from_axis_angle(axis, 0.8).rotate(&v), m.transform_vector(&v), 1e-6);
+        }
+        // Right-handed: a quarter turn around z takes x to y
+        let turned = Quaternion::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), FRAC_PI_2).rotate(&Vector3::new(1.0, 0.0, 0.0));
+        assert_vector_close(turned, Vector3::new(0.0, 1.0, 0.0), 1e-6);
+    }
+
+    #[test]
+    fn product_composes_like_matrices() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..200 {
+            let (axis_a, angle_a) = random_rotation(&mut rng);
+            let (axis_b, angle_b) = random_rotation(&mut rng);
+            let (a, b) = (Quaternion::from_axis_angle(axis_a, angle_a), Quaternion::from_axis_angle(axis_b, angle_b));
+            let v = random_vector(&mut rng);
+            assert_vector_close((a * b).rotate(&v), a.rotate(&b.rotate(&v)), 1e-5);
+            assert_vector_close((a * b).rotate(&v), (a.to_matrix() * b.to_matrix()).transform_vector(&v), 1e-5);
+            assert_vector_close((a * a.conjugate()).rotate(&v), v, 1e-5);
+        }
+    }
+
+    #[test]
+    fn slerp_keeps_unit_length_and_constant_speed() {
+        let a = Quaternion::from_axis_angle(Vector3::new(0.0, 1.0, 0.0), 0.2);
+        let b = Quaternion::from_axis_angle(Vector3::new(0.0, 1.0, 0.0), 1.4);
+        let v = Vector3::new(1.0, 0.0, 0.0);
+        for step in 0..=10 {
+            let t = step as f32 / 10.0;
+            let q = a.slerp(&b, t);
+            assert!((q.length() - 1.0).abs() < 1e-5);
+            let expected = Quaternion::from_axis_angle(Vector3::new(0.0, 1.0, 0.0), 0.2 + 1.2 * t);
+            assert_vector_close(q.rotate(&v), expected.rotate(&v), 1e-5);
+        }
+        // q and -q are the same rotation: still the short way
+        let flipped = Quaternion::new(-b.x, -b.y, -b.z, -b.w);
+        assert_vector_close(a.slerp(&flipped, 0.5).rotate(&v), a.slerp(&b, 0.5).rotate(&v), 1e-5);
+    }
+
+    #[test]
+    fn nalgebra_round_trip() {
+        let q = Quaternion::from_axis_angle(Vector3::new(1.0, 2.0, -0.5), 1.1);
+        let v = Vector3::new(0.4, -0.2, 0.9);
+        let converted: nalgebra::UnitQuaternion<f32> = q.into();
+        let rotated: Vector3 = (converted * nalgebra::Vector3::from(v)).into();
+        assert_vector_close(rotated, q.rotate(&v), 1e-6);
+        let back = Quaternion::from(converted);
+        assert!((back.dot(&q) - 1.0).abs() < 1e-6);
+    }
+}
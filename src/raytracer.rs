@@ -0,0 +1,239 @@
+use crate::atmosphere::{ray_sphere, Atmosphere};
+use crate::background::ViewRays;
+use crate::bvh::{Aabb, Bvh, Ray};
+use crate::clouds::CloudLayer;
+use crate::framebuffer::Framebuffer;
+use crate::light::{Light, LightSource};
+use crate::matrix::Matrix;
+use crate::shaders::{MoonShader, Occluder, PlanetShader, RingParticleShader, ShaderColor, ShaderUniforms};
+use crate::sphere::Vertex;
+use crate::vector::Vector3;
+use rayon::prelude::*;
+use std::f32::consts::PI;
+
+/// Distance shadow rays start away from the surface, so they do not hit it again
+const SHADOW_BIAS: f32 = 1e-2;
+
+/// What an analytic sphere of the scene is, which picks its shader
+#[derive(Debug, Clone, Copy)]
+pub enum SphereMaterial {
+    Moon,
+    /// A rock of the rings, with its own base color
+    Rock(ShaderColor),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sphere {
+    pub center: Vector3,
+    pub radius: f32,
+    pub material: SphereMaterial,
+}
+
+/// Where a ray hit the scene
+#[derive(Debug, Clone, Copy)]
+pub struct Hit {
+    pub distance: f32,
+    pub position: Vector3,
+    pub normal: Vector3,
+    pub surface: HitSurface,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum HitSurface {
+    /// The body's mesh, with the interpolated texture coordinates
+    Mesh { uv: (f32, f32) },
+    Sphere(SphereMaterial),
+}
+
+/// Opaque geometry of a frame in world space: the body's mesh as triangles (already
+/// displaced by its vertex shader and rotated) plus the moon and the ring rocks as
+/// exact spheres, all in one BVH
+pub struct Scene {
+    vertices: Vec<Vertex>,
+    triangles: Vec<[usize; 3]>,
+    spheres: Vec<Sphere>,
+    /// Primitives `0..triangles.len()` are triangles, the rest spheres
+    bvh: Bvh,
+}
+
+impl Scene {
+    pub fn new(vertices: Vec<Vertex>, indices: &[u32], spheres: Vec<Sphere>) -> Self {
+        let triangles: Vec<[usize; 3]> = indices
+            .chunks_exact(3)
+            .map(|face| [face[0] as usize, face[1] as usize, face[2] as usize])
+            .collect();
+        let bounds: Vec<Aabb> = triangles
+            .iter()
+            .map(|triangle| Aabb::from_points(&triangle.map(|i| vertices[i].position)))
+            .chain(spheres.iter().map(|sphere| {
+                let extent = Vector3::new(sphere.radius, sphere.radius, sphere.radius);
+                Aabb { min: sphere.center - extent, max: sphere.center + extent }
+            }))
+            .collect();
+        let bvh = Bvh::build(&bounds);
+        Scene { vertices, triangles, spheres, bvh }
+    }
+
+    /// Closest surface along the ray (unit direction) before `t_max`
+    pub fn intersect(&self, origin: Vector3, direction: Vector3, t_max: f32) -> Option<Hit> {
+        let ray = Ray::new(origin, direction);
+        let (primitive, distance) = self.bvh.closest(&ray, t_max, |primitive| self.distance_to(primitive, &ray))?;
+        let position = ray.at(distance);
+
+        if let Some(sphere) = self.sphere(primitive) {
+            let normal = (position - sphere.center).normalize();
+            return Some(Hit { distance, position, normal, surface: HitSurface::Sphere(sphere.material) });
+        }
+        // Smooth shading: normals and texture coordinates interpolated like the rasterizer does
+        let [a, b, c] = self.triangles[primitive].map(|i| &self.vertices[i]);
+        let (_, u, v) = intersect_triangle(&ray, a.position, b.position, c.position)?;
+        let w = 1.0 - u - v;
+        let normal = (a.normal * w + b.normal * u + c.normal * v).normalize();
+        let uv = (a.uv.0 * w + b.uv.0 * u + c.uv.0 * v, a.uv.1 * w + b.uv.1 * u + c.uv.1 * v);
+        Some(Hit { distance, position, normal, surface: HitSurface::Mesh { uv } })
+    }
+
+    /// Whether anything blocks the ray before `t_max`
+    pub fn occluded(&self, origin: Vector3, direction: Vector3, t_max: f32) -> bool {
+        let ray = Ray::new(origin, direction);
+        self.bvh.any_hit(&ray, t_max, |primitive| self.distance_to(primitive, &ray))
+    }
+
+    fn sphere(&self, primitive: usize) -> Option<&Sphere> {
+        primitive.checked_sub(self.triangles.len()).map(|i| &self.spheres[i])
+    }
+
+    fn distance_to(&self, primitive: usize, ray: &Ray) -> Option<f32> {
+        if let Some(sphere) = self.sphere(primitive) {
+            let (near, far) = ray_sphere(ray.origin - sphere.center, ray.direction, sphere.radius)?;
+            return [near, far].into_iter().find(|&distance| distance > 0.0);
+        }
+        let [a, b, c] = self.triangles[primitive].map(|i| self.vertices[i].position);
+        intersect_triangle(ray, a, b, c).map(|(distance, _, _)| distance)
+    }
+}
+
+/// Hard shadows: one ray from the point towards the light
+impl Occluder for Scene {
+    fn visibility(&self, position: Vector3, normal: Vector3, light: &Light) -> f32 {
+        let direction = light.direction_from(position);
+        let distance = match light.source {
+            LightSource::Directional(_) => f32::INFINITY,
+            LightSource::Point(light_position) => (light_position - position).length(),
+        };
+        if self.occluded(position + normal * SHADOW_BIAS, direction, distance) {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Distance along the ray to the triangle and the barycentric coordinates (u, v) of
+/// the hit, if it is in front of the origin (Möller-Trumbore; both faces count)
+fn intersect_triangle(ray: &Ray, a: Vector3, b: Vector3, c: Vector3) -> Option<(f32, f32, f32)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.direction.cross(&edge2);
+    let determinant = edge1.dot(&p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let s = ray.origin - a;
+    let u = s.dot(&p) * inverse;
+    let q = s.cross(&edge1);
+    let v = ray.direction.dot(&q) * inverse;
+    let distance = edge2.dot(&q) * inverse;
+    (u >= 0.0 && v >= 0.0 && u + v <= 1.0 && distance > 0.0).then_some((distance, u, v))
+}
+
+/// Reference renderer: one ray per pixel center through the same camera as the
+/// rasterizer, shaded per hit with the same shader functions, with ray-traced
+/// shadows and the clouds and atmosphere blended along the ray. Its images are the
+/// ground truth the rasterizer is compared against.
+pub struct RayTracer<'a> {
+    pub scene: &'a Scene,
+    /// Shader of the body's mesh
+    pub shader: &'a dyn PlanetShader,
+    pub clouds: Option<&'a CloudLayer>,
+    pub atmosphere: Option<&'a Atmosphere>,
+}
+
+impl RayTracer<'_> {
+    /// Draws over the background already in the framebuffer and fills the z-buffer
+    /// with the same depth the rasterizer would write, so post effects keep working
+    pub fn render(&self, framebuffer: &mut Framebuffer, view_matrix: &Matrix, proj_matrix: &Matrix, uniforms: &ShaderUniforms) {
+        let width = framebuffer.width as usize;
+        let rays = ViewRays::new(view_matrix, proj_matrix, width, framebuffer.height as usize);
+        let to_clip = proj_matrix.multiply(view_matrix);
+        let eye = uniforms.camera_position;
+
+        framebuffer
+            .hdr
+            .par_chunks_mut(width)
+            .zip(framebuffer.zbuffer.par_chunks_mut(width))
+            .enumerate()
+            .for_each(|(y, (colors, depths))| {
+                for (x, (color, depth)) in colors.iter_mut().zip(depths.iter_mut()).enumerate() {
+                    let direction = rays.direction(x as f32 + 0.5, y as f32 + 0.5);
+                    let mut distance = f32::INFINITY;
+                    if let Some(hit) = self.scene.intersect(eye, direction, f32::INFINITY) {
+                        *color = self.shade(&hit, uniforms);
+                        *depth = to_clip.transform_vector(&hit.position).z;
+                        distance = hit.distance;
+                    }
+                    self.blend_layers(color, eye, direction, distance, uniforms);
+                }
+            });
+    }
+
+    fn shade(&self, hit: &Hit, uniforms: &ShaderUniforms) -> ShaderColor {
+        match hit.surface {
+            HitSurface::Mesh { uv } => self.shader.fragment_shader(hit.position, hit.normal, uv, uniforms),
+            HitSurface::Sphere(SphereMaterial::Rock(color)) => {
+                RingParticleShader::fragment_shader(hit.position, hit.normal, color, uniforms)
+            }
+            HitSurface::Sphere(SphereMaterial::Moon) => {
+                // Same texture coordinates as the rasterized moon's latitude/longitude grid
+                let normal = hit.normal;
+                let uv = (normal.z.atan2(normal.x).rem_euclid(2.0 * PI) / (2.0 * PI), normal.y.clamp(-1.0, 1.0).acos() / PI);
+                let vertex = Vertex { position: hit.position, normal, uv };
+                let (_, base_color) = MoonShader::vertex_shader(&vertex, uniforms);
+                MoonShader::fragment_shader(hit.position, normal, base_color, uniforms)
+            }
+        }
+    }
+
+    /// Clouds, then atmosphere, over whatever the ray hit (or the background), as long
+    /// as the front of their shell is closer than the hit
+    fn blend_layers(&self, color: &mut ShaderColor, eye: Vector3, direction: Vector3, distance: f32, uniforms: &ShaderUniforms) {
+        let in_front = |radius: f32| {
+            ray_sphere(eye, direction, radius)
+                .map(|(near, _)| near)
+                .filter(|&near| near > 0.0 && near < distance)
+        };
+        if let Some(clouds) = self.clouds {
+            if let Some(cloud) = in_front(clouds.radius()).and_then(|near| clouds.shade(eye + direction * near, uniforms)) {
+                *color = over(*color, cloud);
+            }
+        }
+        if let Some(atmosphere) = self.atmosphere {
+            if in_front(atmosphere.radius()).is_some() {
+                *color = over(*color, atmosphere.scatter(eye, direction, uniforms));
+            }
+        }
+    }
+}
+
+/// Same "over" operator as `Framebuffer::blend_pixel`
+fn over(destination: ShaderColor, source: ShaderColor) -> ShaderColor {
+    let alpha = source.a.clamp(0.0, 1.0);
+    let blend = |dst: f32, src: f32| dst * (1.0 - alpha) + src.max(0.0);
+    ShaderColor::new(
+        blend(destination.r, source.r),
+        blend(destination.g, source.g),
+        blend(destination.b, source.b),
+        destination.a,
+    )
+}
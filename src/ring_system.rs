@@ -0,0 +1,250 @@
+#![allow(dead_code)]
+
+use crate::asteroid::{generate_asteroid, AsteroidParams};
+use crate::fragment::{triangle, ClipRect, TransformedVertex};
+use crate::framebuffer::Framebuffer;
+use crate::matrix::{self, ScreenTransform};
+use crate::shaders::{MaterialId, RingParticleShader, ShaderColor, ShaderUniforms, SurfaceLighting, SurfaceSample};
+use crate::sphere::Mesh;
+use crate::vector::Vector3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::TAU;
+
+/// A single rock of the ring
+#[derive(Debug, Clone)]
+pub struct RingParticle {
+    pub radius: f32,
+    /// Orbital angle at time 0
+    pub phase: f32,
+    /// Offset above/below the ring plane
+    pub height: f32,
+    /// World-space radius of the rock
+    pub size: f32,
+    /// Rotation speed around its own Y axis
+    pub spin: f32,
+    /// Which rock mesh is used when drawn as geometry
+    pub variant: usize,
+    pub color: ShaderColor,
+    /// Shapes the irregular outline of the billboard
+    pub shape_seed: f32,
+}
+
+/// Planetary ring / asteroid belt made of many small rocks orbiting in an annulus.
+/// Each rock orbits with Keplerian angular velocity (inner rocks move faster).
+/// Rocks close to the camera are drawn as instanced asteroid meshes, the rest as
+/// depth-tested sphere impostor billboards.
+pub struct RingSystem {
+    pub particles: Vec<RingParticle>,
+    pub inner_radius: f32,
+    pub outer_radius: f32,
+    /// Angular velocity at the inner edge in rad/s
+    pub inner_angular_speed: f32,
+    /// Rocks whose projected radius exceeds this many pixels are drawn as meshes
+    pub mesh_lod_pixels: f32,
+    rock_meshes: Vec<Mesh>,
+}
+
+impl RingSystem {
+    pub fn new(seed: u64, count: usize, inner_radius: f32, outer_radius: f32) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        // A few low-poly rock shapes shared by every particle
+        let rock_meshes: Vec<Mesh> = (0..4)
+            .map(|i| {
+                generate_asteroid(&AsteroidParams {
+                    seed: seed.wrapping_add(i),
+                    subdivisions: 1,
+                    crater_count: 3,
+                    ..AsteroidParams::default()
+                })
+            })
+            .collect();
+
+        let palette = [
+            ShaderColor::new(0.8, 0.7, 0.5, 1.0),   // Dorado
+            ShaderColor::new(0.6, 0.5, 0.4, 1.0),   // Marrón
+            ShaderColor::new(0.55, 0.53, 0.5, 1.0), // Gris
+            ShaderColor::new(0.85, 0.82, 0.75, 1.0), // Hielo sucio
+        ];
+
+        let span = outer_radius - inner_radius;
+        let mut particles = Vec::with_capacity(count);
+        while particles.len() < count {
+            // Uniform over the annulus area
+            let t: f32 = rng.gen();
+            let radius = (inner_radius * inner_radius + t * (outer_radius * outer_radius - inner_radius * inner_radius)).sqrt();
+
+            // Thin out a few bands to get visible gaps like the Cassini division
+            let band = (radius - inner_radius) / span;
+            let density = if (0.58..0.64).contains(&band) { 0.05 } else { 0.6 + 0.4 * (band * 37.0).sin().abs() };
+            if rng.gen::<f32>() > density {
+                continue;
+            }
+
+            let base = palette[rng.gen_range(0..palette.len())];
+            let shade = rng.gen_range(0.85..1.1);
+
+            particles.push(RingParticle {
+                radius,
+                phase: rng.gen_range(0.0..TAU),
+                height: rng.gen_range(-1.0f32..1.0).powi(3) * 0.04,
+                size: rng.gen_range(0.004f32..0.016),
+                spin: rng.gen_range(-2.0..2.0),
+                variant: rng.gen_range(0..rock_meshes.len()),
+                color: ShaderColor::new(base.r * shade, base.g * shade, base.b * shade, 1.0),
+                shape_seed: rng.gen_range(0.0..TAU),
+            });
+        }
+
+        RingSystem {
+            particles,
+            inner_radius,
+            outer_radius,
+            inner_angular_speed: 0.8,
+            mesh_lod_pixels: 6.0,
+            rock_meshes,
+        }
+    }
+
+    /// Keplerian angular velocity: omega ~ r^(-3/2)
+    pub fn angular_speed(&self, radius: f32) -> f32 {
+        self.inner_angular_speed * (self.inner_radius / radius).powf(1.5)
+    }
+
+    pub fn particle_position(&self, particle: &RingParticle, time: f32) -> Vector3 {
+        let angle = particle.phase + self.angular_speed(particle.radius) * time;
+        Vector3::new(particle.radius * angle.cos(), particle.height, particle.radius * angle.sin())
+    }
+
+    pub fn render(&self, framebuffer: &mut Framebuffer, screen: &ScreenTransform, uniforms: &ShaderUniforms) {
+        let (view_matrix, proj_matrix) = (&screen.view, &screen.projection);
+        let (width, height) = (screen.width, screen.height);
+        // Camera basis in world space, taken from the rows of the view matrix
+        let right = Vector3::new(view_matrix.data[0][0], view_matrix.data[0][1], view_matrix.data[0][2]);
+        let up = Vector3::new(view_matrix.data[1][0], view_matrix.data[1][1], view_matrix.data[1][2]);
+        let back = Vector3::new(view_matrix.data[2][0], view_matrix.data[2][1], view_matrix.data[2][2]);
+        let focal_pixels = proj_matrix.data[1][1] * height as f32 / 2.0;
+
+        for particle in &self.particles {
+            let center = self.particle_position(particle, uniforms.time);
+
+            let distance = -view_matrix.transform_vector(&center).z;
+            if distance < 0.1 {
+                continue; // Behind the camera or too close to the near plane
+            }
+
+            let pixel_radius = particle.size * focal_pixels / distance;
+            if pixel_radius > self.mesh_lod_pixels {
+                self.render_rock_mesh(framebuffer, particle, center, screen, uniforms);
+                continue;
+            }
+
+            let projected = screen.project(&center);
+            let reach = (pixel_radius * 1.3).max(0.5);
+            let min_x = ((projected.x - reach).floor() as i32).max(0);
+            let max_x = ((projected.x + reach).ceil() as i32).min(width - 1);
+            let min_y = ((projected.y - reach).floor() as i32).max(0);
+            let max_y = ((projected.y + reach).ceil() as i32).min(height - 1);
+
+            // The impostor is drawn at a constant depth, so if the hierarchical z-buffer
+            // has something closer over its whole rectangle it is skipped before shading
+            if framebuffer.is_hidden(min_x, min_y, max_x, max_y, projected.z) {
+                continue;
+            }
+
+            for y in min_y..=max_y {
+                for x in min_x..=max_x {
+                    if !framebuffer.depth_test(x as u32, y as u32, projected.z) {
+                        continue;
+                    }
+
+                    let dx = (x as f32 + 0.5 - projected.x) / pixel_radius.max(0.5);
+                    let dy = (y as f32 + 0.5 - projected.y) / pixel_radius.max(0.5);
+
+                    // Lumpy outline so the impostors read as rocks rather than discs
+                    let angle = dy.atan2(dx);
+                    let edge = 1.0 + 0.15 * (3.0 * angle + particle.shape_seed).sin() + 0.1 * (5.0 * angle + 2.0 * particle.shape_seed).sin();
+                    let r2 = (dx * dx + dy * dy) / (edge * edge);
+                    if r2 > 1.0 {
+                        continue;
+                    }
+
+                    // Sphere impostor normal (screen y grows downwards)
+                    let nz = (1.0 - r2).sqrt();
+                    let normal = (right * (dx / edge) - up * (dy / edge) + back * nz).normalize();
+                    let world_pos = center + normal * particle.size;
+
+                    if framebuffer.gbuffer.is_some() {
+                        let surface = RingParticleShader::surface_shader(world_pos, particle.color);
+                        framebuffer.set_surface_with_depth(x as u32, y as u32, projected.z, &surface, normal, world_pos);
+                        continue;
+                    }
+
+                    let color = RingParticleShader::fragment_shader(world_pos, normal, particle.color, uniforms);
+                    framebuffer.point_with_depth(x, y, color, projected.z);
+                }
+            }
+        }
+    }
+
+    /// Draws a close rock as real geometry, one instance of a shared rock mesh
+    fn render_rock_mesh(
+        &self,
+        framebuffer: &mut Framebuffer,
+        particle: &RingParticle,
+        center: Vector3,
+        screen: &ScreenTransform,
+        uniforms: &ShaderUniforms,
+    ) {
+        let mesh = &self.rock_meshes[particle.variant];
+        let rotation = matrix::create_rotation_y(particle.shape_seed + particle.spin * uniforms.time);
+        // In deferred mode the vertex color carries the unlit albedo instead
+        let deferred = framebuffer.gbuffer.is_some();
+
+        let transformed: Vec<TransformedVertex> = mesh
+            .vertices
+            .iter()
+            .map(|v| {
+                let world_position = rotation.transform_vector(&(v.position * particle.size)) + center;
+                let normal = rotation.transform_direction(&v.normal).normalize();
+                let screen_position = screen.project(&world_position);
+                TransformedVertex {
+                    screen_position,
+                    world_position,
+                    normal,
+                    color: if deferred {
+                        RingParticleShader::surface_shader(world_position, particle.color).albedo
+                    } else {
+                        RingParticleShader::fragment_shader(world_position, normal, particle.color, uniforms)
+                    },
+                    uv: v.uv,
+                }
+            })
+            .collect();
+
+        let clip = ClipRect { min_x: 0, min_y: 0, max_x: screen.width - 1, max_y: screen.height - 1 };
+        for face in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [face[0], face[1], face[2]].map(|i| &transformed[i as usize]);
+            triangle(a, b, c, clip, |sample| {
+                let (x, y) = (sample.x as u32, sample.y as u32);
+                if !framebuffer.depth_test(x, y, sample.depth) {
+                    return;
+                }
+                let fragment = sample.fragment();
+                if deferred {
+                    let surface = SurfaceSample {
+                        albedo: fragment.color,
+                        emission: ShaderColor::new(0.0, 0.0, 0.0, 0.0),
+                        specular: 0.0,
+                        material: MaterialId::RingParticle,
+                        lighting: SurfaceLighting::MATTE,
+                    };
+                    framebuffer.set_surface_with_depth(x, y, sample.depth, &surface, fragment.normal, fragment.world_position);
+                } else {
+                    framebuffer.set_pixel_with_depth(x, y, fragment.color, sample.depth);
+                }
+            });
+        }
+    }
+}
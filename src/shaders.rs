@@ -1,5 +1,11 @@
 use crate::vector::Vector3;
 use crate::sphere::Vertex;
+use crate::shadow_map::ShadowMap;
+use crate::light::Light;
+use crate::clouds::CloudShadow;
+use crate::background::EnvironmentMap;
+use crate::tone_mapping::srgb_decode;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Copy)]
 pub struct ShaderColor {
@@ -14,38 +20,174 @@ impl ShaderColor {
         ShaderColor { r, g, b, a }
     }
 
+    /// Color de 8 bits en sRGB, como las paletas de los shaders, pasado a lineal para
+    /// que al codificarlo de nuevo en la salida se vea igual
     pub fn from_rgb(r: u8, g: u8, b: u8) -> Self {
         ShaderColor {
-            r: r as f32 / 255.0,
-            g: g as f32 / 255.0,
-            b: b as f32 / 255.0,
+            r: srgb_decode(r as f32 / 255.0),
+            g: srgb_decode(g as f32 / 255.0),
+            b: srgb_decode(b as f32 / 255.0),
             a: 1.0,
         }
     }
 
-    pub fn to_raylib_color(&self) -> raylib::prelude::Color {
-        raylib::prelude::Color {
-            r: (self.r * 255.0) as u8,
-            g: (self.g * 255.0) as u8,
-            b: (self.b * 255.0) as u8,
-            a: (self.a * 255.0) as u8,
-        }
-    }
-
     pub const WHITE: ShaderColor = ShaderColor { r: 1.0, g: 1.0, b: 1.0, a: 1.0 };
     pub const BLACK: ShaderColor = ShaderColor { r: 0.0, g: 0.0, b: 0.0, a: 1.0 };
     pub const YELLOW: ShaderColor = ShaderColor { r: 1.0, g: 1.0, b: 0.0, a: 1.0 };
 }
 
+/// Identificador del material de cada pixel en el G-buffer, elige el modelo de
+/// iluminación en el pase de luces del modo deferred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MaterialId {
+    None = 0,
+    Rocky,
+    GasGiant,
+    Crystal,
+    Lava,
+    RingParticle,
+    Moon,
+    Star,
+}
+
+/// Salida de un shader antes de iluminar: lo que se guarda en el G-buffer
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceSample {
+    /// Color de la superficie (incluye oclusión y variaciones del patrón)
+    pub albedo: ShaderColor,
+    /// Luz propia, se suma después de iluminar
+    pub emission: ShaderColor,
+    /// Intensidad del brillo especular (0 = mate)
+    pub specular: f32,
+    pub material: MaterialId,
+    /// Cómo responde el material a la luz
+    pub lighting: SurfaceLighting,
+}
+
+/// Términos de iluminación de un material. Cada shader devuelve los suyos desde
+/// `surface_shader` y `shade_surface` los evalúa igual en los modos forward y deferred
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceLighting {
+    pub ambient: f32,
+    pub diffuse: f32,
+    /// Peso del brillo especular, escalado en cada punto por `SurfaceSample::specular`
+    pub specular: f32,
+    pub shininess: f32,
+    /// Luz de borde, más fuerte donde la superficie se ve de canto
+    pub rim: f32,
+    pub rim_power: f32,
+    /// Tope de la intensidad antes de sumar la emisión
+    pub max_intensity: f32,
+}
+
+impl SurfaceLighting {
+    /// Difuso con algo de ambiente, sin brillo ni borde
+    pub const MATTE: SurfaceLighting = SurfaceLighting {
+        ambient: 0.15,
+        diffuse: 0.85,
+        specular: 0.0,
+        shininess: 1.0,
+        rim: 0.0,
+        rim_power: 1.0,
+        max_intensity: f32::INFINITY,
+    };
+}
+
 pub struct ShaderUniforms {
     pub time: f32,
-    pub light_direction: Vector3,
+    /// Luces de la escena; el mapa de sombras se genera desde la primera
+    pub lights: Vec<Light>,
     pub camera_position: Vector3,
+    /// Profundidad vista desde la primera luz; sin mapa no hay sombras
+    pub shadow_map: Option<ShadowMap>,
+    /// Capa de nubes que tapa la primera luz sobre la superficie
+    pub cloud_shadow: Option<CloudShadow>,
+    /// Fondo de la escena como mapa de entorno, para reflejarlo y refractarlo
+    pub environment: Option<Arc<EnvironmentMap>>,
+    /// Escena que responde las sombras lanzando rayos; si está, reemplaza al mapa de sombras
+    pub occluder: Option<Arc<dyn Occluder>>,
+}
+
+/// Algo que sabe si un punto ve una luz, como la escena del trazador de rayos
+pub trait Occluder: Send + Sync {
+    /// Fracción de la luz de `light` que llega a `position` (1 = sin sombra)
+    fn visibility(&self, position: Vector3, normal: Vector3, light: &Light) -> f32;
+}
+
+impl ShaderUniforms {
+    /// Fracción de la luz principal que llega a un punto (1 = sin sombra)
+    pub fn shadow(&self, position: Vector3, normal: Vector3) -> f32 {
+        if let (Some(occluder), Some(light)) = (&self.occluder, self.lights.first()) {
+            return occluder.visibility(position, normal, light);
+        }
+        self.shadow_map.as_ref().map_or(1.0, |map| map.visibility(position, normal))
+    }
+
+    /// Luz que refleja un punto sumando todas las luces de la escena. `response` recibe
+    /// la dirección hacia cada luz, calculada desde el punto, y la sombra de esa luz
+    /// (1 = sin sombra), y devuelve cuánto refleja la superficie; eso se escala por el
+    /// color e intensidad que llega de la luz (ya atenuada por la distancia).
+    pub fn light_sum<F: Fn(Vector3, f32) -> f32>(&self, position: Vector3, normal: Vector3, response: F) -> ShaderColor {
+        let mut total = ShaderColor::new(0.0, 0.0, 0.0, 1.0);
+        for (index, light) in self.lights.iter().enumerate() {
+            let (light_dir, irradiance) = light.illuminate(position);
+            let shadow = if index == 0 {
+                let clouds = self.cloud_shadow.as_ref().map_or(1.0, |clouds| clouds.transmittance(position, light_dir, self.time));
+                self.shadow(position, normal) * clouds
+            } else {
+                1.0
+            };
+            let amount = response(light_dir, shadow);
+            total.r += irradiance.r * amount;
+            total.g += irradiance.g * amount;
+            total.b += irradiance.b * amount;
+        }
+        total
+    }
 }
 
-pub trait PlanetShader {
+pub trait PlanetShader: Send + Sync {
     fn vertex_shader(&self, position: Vector3, normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> (Vector3, Vector3);
-    fn fragment_shader(&self, position: Vector3, normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> ShaderColor;
+    /// Superficie sin iluminar: se guarda en el G-buffer del modo deferred o se ilumina
+    /// enseguida en el modo forward
+    fn surface_shader(&self, position: Vector3, normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> SurfaceSample;
+
+    fn fragment_shader(&self, position: Vector3, normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> ShaderColor {
+        shade_surface(&self.surface_shader(position, normal, uv, uniforms), position, normal, uniforms)
+    }
+}
+
+/// Ilumina una superficie con todas las luces de la escena: ambiente, difuso, brillo
+/// especular, borde y su propia emisión. Es la misma cuenta en el modo forward y en el
+/// pase de luces del deferred
+pub fn shade_surface(surface: &SurfaceSample, position: Vector3, normal: Vector3, uniforms: &ShaderUniforms) -> ShaderColor {
+    let lighting = surface.lighting;
+    let view_dir = (uniforms.camera_position - position).normalize();
+    let facing = view_dir.dot(&normal);
+
+    let direct = uniforms.light_sum(position, normal, |light_dir, shadow| {
+        let diffuse = normal.dot(&light_dir).max(0.0) * shadow;
+        let reflect_dir = (-light_dir).reflect(&normal);
+        let specular = view_dir.dot(&reflect_dir).max(0.0).powf(lighting.shininess) * shadow;
+        lighting.diffuse * diffuse + lighting.specular * specular * surface.specular
+    });
+    let base = lighting.ambient + lighting.rim * (1.0 - facing).max(0.0).powf(lighting.rim_power);
+    let intensity = |light: f32| (base + light).min(lighting.max_intensity);
+
+    let albedo = surface.albedo;
+    let emission = surface.emission;
+    // El cristal además deja ver el fondo, reflejado y a través de él
+    let environment = (surface.material == MaterialId::Crystal)
+        .then(|| crystal_environment(position, normal, albedo, uniforms))
+        .flatten()
+        .unwrap_or(ShaderColor::new(0.0, 0.0, 0.0, 0.0));
+    ShaderColor::new(
+        (albedo.r * intensity(direct.r) + emission.r + environment.r).max(0.0),
+        (albedo.g * intensity(direct.g) + emission.g + environment.g).max(0.0),
+        (albedo.b * intensity(direct.b) + emission.b + environment.b).max(0.0),
+        albedo.a.clamp(0.0, 1.0),
+    )
 }
 
 // Funciones de ruido mejoradas para efectos procedurales
@@ -106,6 +248,34 @@ fn ridge_noise(x: f32, y: f32, octaves: i32) -> f32 {
     value
 }
 
+// Ruido de valor 3D: valores aleatorios en una rejilla interpolados suavemente,
+// continuo en toda la esfera (sin costura en u = 0)
+fn value_noise_3d(p: Vector3) -> f32 {
+    let (ix, iy, iz) = (p.x.floor(), p.y.floor(), p.z.floor());
+    let fade = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (u, v, w) = (fade(p.x - ix), fade(p.y - iy), fade(p.z - iz));
+    let hash = |x: f32, y: f32, z: f32| simple_noise(ix + x + (iz + z) * 57.0, iy + y - (iz + z) * 113.0);
+    
+    let bottom = mix(mix(hash(0.0, 0.0, 0.0), hash(1.0, 0.0, 0.0), u), mix(hash(0.0, 1.0, 0.0), hash(1.0, 1.0, 0.0), u), v);
+    let top = mix(mix(hash(0.0, 0.0, 1.0), hash(1.0, 0.0, 1.0), u), mix(hash(0.0, 1.0, 1.0), hash(1.0, 1.0, 1.0), u), v);
+    mix(bottom, top, w)
+}
+
+pub fn fbm_3d(mut p: Vector3, octaves: i32) -> f32 {
+    let mut value = 0.0;
+    let mut amplitude = 0.5;
+    let mut total = 0.0;
+    
+    for _ in 0..octaves {
+        value += amplitude * value_noise_3d(p);
+        total += amplitude;
+        p *= 2.0;
+        amplitude *= 0.5;
+    }
+    
+    value / total
+}
+
 fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
     let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
     t * t * (3.0 - 2.0 * t)
@@ -124,6 +294,50 @@ fn mix_color(a: ShaderColor, b: ShaderColor, t: f32) -> ShaderColor {
     )
 }
 
+// Capas 1 a 3 del planeta rocoso: color del terreno y los ruidos que usa la iluminación
+// (elevación, rugosidad, minerales)
+fn rocky_terrain(uv: (f32, f32)) -> (ShaderColor, f32, f32, f32) {
+    // Capa 1: Colores base del terreno
+    let bedrock_color = ShaderColor::from_rgb(101, 67, 33);    // Roca base
+    let soil_color = ShaderColor::from_rgb(139, 69, 19);      // Tierra
+    let mountain_color = ShaderColor::from_rgb(105, 105, 105); // Montañas
+    let crater_color = ShaderColor::from_rgb(64, 64, 64);     // Cráteres
+    let mineral_color = ShaderColor::from_rgb(184, 134, 11);  // Minerales
+    
+    // Capa 2: Mapas de ruido para diferentes características
+    let elevation_noise = ridge_noise(uv.0 * 3.0, uv.1 * 3.0, 4);
+    let surface_noise = fbm(uv.0 * 8.0, uv.1 * 8.0, 4);
+    let crater_noise = voronoi_noise(uv.0 * 6.0, uv.1 * 6.0);
+    let mineral_noise = fbm(uv.0 * 20.0, uv.1 * 20.0, 2);
+    
+    // Capa 3: Selección de color basada en múltiples factores
+    let mut base_color = bedrock_color;
+    
+    // Montañas en elevaciones altas
+    if elevation_noise > 0.6 {
+        base_color = mix_color(base_color, mountain_color, smoothstep(0.6, 0.8, elevation_noise));
+    }
+    
+    // Suelo en áreas medias
+    if surface_noise > 0.3 && elevation_noise < 0.7 {
+        base_color = mix_color(base_color, soil_color, smoothstep(0.3, 0.6, surface_noise));
+    }
+    
+    // Cráteres en áreas específicas
+    if crater_noise < 0.3 {
+        let crater_factor = smoothstep(0.0, 0.3, crater_noise);
+        base_color = mix_color(crater_color, base_color, crater_factor);
+    }
+    
+    // Vetas minerales
+    if mineral_noise > 0.7 {
+        let mineral_factor = smoothstep(0.7, 0.9, mineral_noise) * 0.4;
+        base_color = mix_color(base_color, mineral_color, mineral_factor);
+    }
+    
+    (base_color, elevation_noise, surface_noise, mineral_noise)
+}
+
 // Shader para planeta rocoso mejorado con múltiples capas
 pub struct RockyPlanetShader;
 
@@ -147,76 +361,99 @@ impl PlanetShader for RockyPlanetShader {
         (new_position, normal)
     }
 
-    fn fragment_shader(&self, position: Vector3, normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> ShaderColor {
-        // Capa 1: Colores base del terreno
-        let bedrock_color = ShaderColor::from_rgb(101, 67, 33);    // Roca base
-        let soil_color = ShaderColor::from_rgb(139, 69, 19);      // Tierra
-        let mountain_color = ShaderColor::from_rgb(105, 105, 105); // Montañas
-        let crater_color = ShaderColor::from_rgb(64, 64, 64);     // Cráteres
-        let mineral_color = ShaderColor::from_rgb(184, 134, 11);  // Minerales
-        
-        // Capa 2: Mapas de ruido para diferentes características
-        let elevation_noise = ridge_noise(uv.0 * 3.0, uv.1 * 3.0, 4);
-        let surface_noise = fbm(uv.0 * 8.0, uv.1 * 8.0, 4);
-        let crater_noise = voronoi_noise(uv.0 * 6.0, uv.1 * 6.0);
-        let mineral_noise = fbm(uv.0 * 20.0, uv.1 * 20.0, 2);
-        
-        // Capa 3: Selección de color basada en múltiples factores
-        let mut base_color = bedrock_color;
-        
-        // Montañas en elevaciones altas
-        if elevation_noise > 0.6 {
-            base_color = mix_color(base_color, mountain_color, smoothstep(0.6, 0.8, elevation_noise));
-        }
-        
-        // Suelo en áreas medias
-        if surface_noise > 0.3 && elevation_noise < 0.7 {
-            base_color = mix_color(base_color, soil_color, smoothstep(0.3, 0.6, surface_noise));
-        }
-        
-        // Cráteres en áreas específicas
-        if crater_noise < 0.3 {
-            let crater_factor = smoothstep(0.0, 0.3, crater_noise);
-            base_color = mix_color(crater_color, base_color, crater_factor);
-        }
-        
-        // Vetas minerales
-        if mineral_noise > 0.7 {
-            let mineral_factor = smoothstep(0.7, 0.9, mineral_noise) * 0.4;
-            base_color = mix_color(base_color, mineral_color, mineral_factor);
-        }
-        
-        // Capa 4: Iluminación avanzada con múltiples componentes
-        let light_dir = uniforms.light_direction.normalize();
-        let view_dir = (uniforms.camera_position - position).normalize();
-        
-        // Iluminación difusa
-        let diffuse = normal.dot(&light_dir).max(0.0);
+    fn surface_shader(&self, position: Vector3, _normal: Vector3, uv: (f32, f32), _uniforms: &ShaderUniforms) -> SurfaceSample {
+        let (base_color, elevation_noise, surface_noise, mineral_noise) = rocky_terrain(uv);
         
-        // Iluminación especular para minerales
-        let reflect_dir = normal * (2.0 * normal.dot(&light_dir)) - light_dir;
-        let specular = view_dir.dot(&reflect_dir).max(0.0).powf(16.0) * mineral_noise.max(0.0);
-        
-        // Oclusión ambiental basada en rugosidad
+        // Los factores que no dependen de la luz se guardan ya aplicados en el albedo
         let ao = 1.0 - (surface_noise * 0.3).clamp(0.0, 0.4);
-        
-        // Iluminación de borde (rim lighting)
-        let rim = (1.0 - view_dir.dot(&normal)).powf(2.0) * 0.2;
-        
-        let ambient = 0.15;
-        let final_intensity = (ambient + diffuse * 0.7 + specular * 0.3 + rim) * ao;
-        
-        // Variación de color por altura y temperatura simulada
         let altitude_factor = (elevation_noise * 0.2 + 0.8).clamp(0.6, 1.0);
         let temperature_variation = (position.y * 0.1).sin() * 0.1 + 1.0;
-        
-        ShaderColor::new(
-            (base_color.r * final_intensity * altitude_factor * temperature_variation).clamp(0.0, 1.0),
-            (base_color.g * final_intensity * altitude_factor * temperature_variation).clamp(0.0, 1.0),
-            (base_color.b * final_intensity * altitude_factor * temperature_variation).clamp(0.0, 1.0),
-            1.0,
-        )
+        let shade = ao * altitude_factor * temperature_variation;
+        
+        SurfaceSample {
+            albedo: ShaderColor::new(base_color.r * shade, base_color.g * shade, base_color.b * shade, 1.0),
+            emission: ShaderColor::new(0.0, 0.0, 0.0, 0.0),
+            specular: mineral_noise.max(0.0),
+            material: MaterialId::Rocky,
+            lighting: SurfaceLighting { diffuse: 0.7, specular: 0.3, shininess: 16.0, rim: 0.2, rim_power: 2.0, ..SurfaceLighting::MATTE },
+        }
+    }
+}
+
+// Capas 1 a 5 del gigante gaseoso: color de bandas, tormentas, nubes y rayos, junto con
+// la intensidad de la tormenta y la turbulencia que usa la iluminación
+fn gas_giant_bands(uv: (f32, f32), time: f32) -> (ShaderColor, f32, f32) {
+    // Capa 1: Colores base de las diferentes capas atmosféricas
+    let deep_atmosphere = ShaderColor::from_rgb(139, 69, 19);    // Marrón profundo
+    let mid_atmosphere = ShaderColor::from_rgb(255, 140, 0);     // Naranja
+    let high_atmosphere = ShaderColor::from_rgb(255, 215, 0);    // Dorado
+    let storm_color = ShaderColor::from_rgb(255, 69, 0);         // Rojo tormenta
+    let cloud_color = ShaderColor::from_rgb(255, 248, 220);      // Nubes claras
+    let lightning_color = ShaderColor::from_rgb(173, 216, 230);  // Azul eléctrico
+    
+    // Capa 2: Bandas atmosféricas con múltiples frecuencias
+    let band_frequency1 = 6.0;
+    let band_frequency2 = 12.0;
+    let band_position1 = (uv.1 * band_frequency1 + time * 0.08).sin();
+    let band_position2 = (uv.1 * band_frequency2 + time * 0.05).sin();
+    
+    // Capa 3: Turbulencia y remolinos complejos
+    let turbulence1 = fbm(uv.0 * 8.0 + time * 0.03, uv.1 * 6.0, 4) * 0.4;
+    let turbulence2 = fbm(uv.0 * 12.0 - time * 0.02, uv.1 * 8.0, 3) * 0.3;
+    let combined_turbulence = turbulence1 + turbulence2;
+    
+    // Capa 4: Grandes tormentas circulares (Great Red Spot style)
+    let storm_center_x = 0.3 + (time * 0.01).sin() * 0.1;
+    let storm_center_y = 0.6 + (time * 0.015).cos() * 0.05;
+    let storm_dist = ((uv.0 - storm_center_x).powi(2) + (uv.1 - storm_center_y).powi(2)).sqrt();
+    let storm_intensity = smoothstep(0.3, 0.1, storm_dist);
+    
+    // Remolino en la tormenta
+    let angle = (uv.1 - storm_center_y).atan2(uv.0 - storm_center_x);
+    let spiral = (angle * 3.0 + storm_dist * 10.0 + time * 2.0).sin();
+    let storm_swirl = storm_intensity * spiral * 0.3;
+    
+    // Capa 5: Rayos y descargas eléctricas
+    let lightning_noise = fbm(uv.0 * 25.0 + time * 5.0, uv.1 * 25.0, 2);
+    let lightning_threshold = 0.85 + (time * 10.0).sin() * 0.1;
+    let lightning_intensity = if lightning_noise > lightning_threshold { 
+        (lightning_noise - lightning_threshold) * 10.0 
+    } else { 
+        0.0 
+    };
+    
+    // Selección de color base según las bandas distorsionadas
+    let distorted_band1 = band_position1 + combined_turbulence + storm_swirl;
+    let distorted_band2 = band_position2 + combined_turbulence * 0.5;
+    
+    let mut base_color = if distorted_band1 > 0.6 {
+        high_atmosphere
+    } else if distorted_band1 > 0.2 {
+        mid_atmosphere
+    } else if distorted_band2 > 0.0 {
+        mix_color(mid_atmosphere, deep_atmosphere, 0.6)
+    } else {
+        deep_atmosphere
+    };
+    
+    // Aplicar efectos de tormenta
+    if storm_intensity > 0.1 {
+        base_color = mix_color(base_color, storm_color, storm_intensity * 0.8);
+    }
+    
+    // Añadir nubes altas
+    let cloud_noise = fbm(uv.0 * 15.0 + time * 0.02, uv.1 * 10.0, 3);
+    if cloud_noise > 0.6 {
+        let cloud_factor = smoothstep(0.6, 0.8, cloud_noise) * 0.4;
+        base_color = mix_color(base_color, cloud_color, cloud_factor);
+    }
+    
+    // Aplicar rayos si están presentes
+    if lightning_intensity > 0.0 {
+        base_color = mix_color(base_color, lightning_color, lightning_intensity.min(0.8));
     }
+    
+    (base_color, storm_intensity, combined_turbulence)
 }
 
 // Shader para gigante gaseoso mejorado con múltiples capas atmosféricas
@@ -240,106 +477,126 @@ impl PlanetShader for GasGiantShader {
         (new_position, normal)
     }
 
-    fn fragment_shader(&self, position: Vector3, normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> ShaderColor {
-        // Capa 1: Colores base de las diferentes capas atmosféricas
-        let deep_atmosphere = ShaderColor::from_rgb(139, 69, 19);    // Marrón profundo
-        let mid_atmosphere = ShaderColor::from_rgb(255, 140, 0);     // Naranja
-        let high_atmosphere = ShaderColor::from_rgb(255, 215, 0);    // Dorado
-        let storm_color = ShaderColor::from_rgb(255, 69, 0);         // Rojo tormenta
-        let cloud_color = ShaderColor::from_rgb(255, 248, 220);      // Nubes claras
-        let lightning_color = ShaderColor::from_rgb(173, 216, 230);  // Azul eléctrico
-        
-        // Capa 2: Bandas atmosféricas con múltiples frecuencias
-        let band_frequency1 = 6.0;
-        let band_frequency2 = 12.0;
-        let band_position1 = (uv.1 * band_frequency1 + uniforms.time * 0.08).sin();
-        let band_position2 = (uv.1 * band_frequency2 + uniforms.time * 0.05).sin();
-        
-        // Capa 3: Turbulencia y remolinos complejos
-        let turbulence1 = fbm(uv.0 * 8.0 + uniforms.time * 0.03, uv.1 * 6.0, 4) * 0.4;
-        let turbulence2 = fbm(uv.0 * 12.0 - uniforms.time * 0.02, uv.1 * 8.0, 3) * 0.3;
-        let combined_turbulence = turbulence1 + turbulence2;
-        
-        // Capa 4: Grandes tormentas circulares (Great Red Spot style)
-        let storm_center_x = 0.3 + (uniforms.time * 0.01).sin() * 0.1;
-        let storm_center_y = 0.6 + (uniforms.time * 0.015).cos() * 0.05;
-        let storm_dist = ((uv.0 - storm_center_x).powi(2) + (uv.1 - storm_center_y).powi(2)).sqrt();
-        let storm_intensity = smoothstep(0.3, 0.1, storm_dist);
-        
-        // Remolino en la tormenta
-        let angle = (uv.1 - storm_center_y).atan2(uv.0 - storm_center_x);
-        let spiral = (angle * 3.0 + storm_dist * 10.0 + uniforms.time * 2.0).sin();
-        let storm_swirl = storm_intensity * spiral * 0.3;
-        
-        // Capa 5: Rayos y descargas eléctricas
-        let lightning_noise = fbm(uv.0 * 25.0 + uniforms.time * 5.0, uv.1 * 25.0, 2);
-        let lightning_threshold = 0.85 + (uniforms.time * 10.0).sin() * 0.1;
-        let lightning_intensity = if lightning_noise > lightning_threshold { 
-            (lightning_noise - lightning_threshold) * 10.0 
-        } else { 
-            0.0 
-        };
-        
-        // Selección de color base según las bandas distorsionadas
-        let distorted_band1 = band_position1 + combined_turbulence + storm_swirl;
-        let distorted_band2 = band_position2 + combined_turbulence * 0.5;
-        
-        let mut base_color = if distorted_band1 > 0.6 {
-            high_atmosphere
-        } else if distorted_band1 > 0.2 {
-            mid_atmosphere
-        } else if distorted_band2 > 0.0 {
-            mix_color(mid_atmosphere, deep_atmosphere, 0.6)
-        } else {
-            deep_atmosphere
-        };
-        
-        // Aplicar efectos de tormenta
-        if storm_intensity > 0.1 {
-            base_color = mix_color(base_color, storm_color, storm_intensity * 0.8);
-        }
-        
-        // Añadir nubes altas
-        let cloud_noise = fbm(uv.0 * 15.0 + uniforms.time * 0.02, uv.1 * 10.0, 3);
-        if cloud_noise > 0.6 {
-            let cloud_factor = smoothstep(0.6, 0.8, cloud_noise) * 0.4;
-            base_color = mix_color(base_color, cloud_color, cloud_factor);
-        }
-        
-        // Capa 6: Iluminación atmosférica compleja
-        let light_dir = uniforms.light_direction.normalize();
-        let view_dir = (uniforms.camera_position - position).normalize();
-        
-        // Iluminación difusa con scattering atmosférico
-        let diffuse = normal.dot(&light_dir).max(0.0);
-        let atmosphere_scattering = (1.0 - diffuse).powf(0.5) * 0.3;
-        
-        // Iluminación de borde para efecto atmosférico
-        let rim = (1.0 - view_dir.dot(&normal)).powf(1.5) * 0.4;
-        
-        // Iluminación interna de las tormentas
+    fn surface_shader(&self, _position: Vector3, _normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> SurfaceSample {
+        let (base_color, storm_intensity, combined_turbulence) = gas_giant_bands(uv, uniforms.time);
+        
+        let depth_variation = (uv.1 * std::f32::consts::PI).sin().abs() * 0.2 + 0.8;
+        let albedo = ShaderColor::new(
+            base_color.r * depth_variation,
+            base_color.g * depth_variation,
+            base_color.b * depth_variation,
+            0.95,
+        );
+        
+        // Las tormentas brillan por sí mismas
         let internal_glow = storm_intensity * 0.2 + combined_turbulence * 0.1;
         
-        let ambient = 0.25;
-        let final_intensity = (ambient + diffuse * 0.6 + atmosphere_scattering + rim + internal_glow).min(1.8);
-        
-        // Aplicar rayos si están presentes
-        if lightning_intensity > 0.0 {
-            base_color = mix_color(base_color, lightning_color, lightning_intensity.min(0.8));
+        SurfaceSample {
+            albedo,
+            emission: ShaderColor::new(albedo.r * internal_glow, albedo.g * internal_glow, albedo.b * internal_glow, 0.0),
+            specular: 0.0,
+            material: MaterialId::GasGiant,
+            // Sin luz de borde propia: el halo lo pone la capa de atmósfera
+            lighting: SurfaceLighting { ambient: 0.25, diffuse: 0.6, max_intensity: 1.8, ..SurfaceLighting::MATTE },
         }
-        
-        // Variación de profundidad atmosférica
-        let depth_variation = (uv.1 * 3.14159).sin().abs() * 0.2 + 0.8;
-        
-        ShaderColor::new(
-            (base_color.r * final_intensity * depth_variation).clamp(0.0, 1.0),
-            (base_color.g * final_intensity * depth_variation).clamp(0.0, 1.0),
-            (base_color.b * final_intensity * depth_variation).clamp(0.0, 1.0),
-            0.95, // Ligeramente transparente para efecto atmosférico
-        )
     }
 }
 
+// Capas 1 a 3 y 5 del planeta de cristal: color con su desplazamiento dinámico y
+// transparencia, más la energía interna que usa la iluminación
+fn crystal_pattern(position: Vector3, uv: (f32, f32), time: f32) -> (ShaderColor, f32, f32, f32) {
+    // Capa 1: Colores cristalinos base
+    let crystal_core = ShaderColor::from_rgb(240, 248, 255);      // Blanco cristalino
+    let crystal_blue = ShaderColor::from_rgb(173, 216, 230);      // Azul claro
+    let crystal_purple = ShaderColor::from_rgb(147, 112, 219);    // Púrpura
+    let crystal_cyan = ShaderColor::from_rgb(0, 255, 255);        // Cian brillante
+    let crystal_pink = ShaderColor::from_rgb(255, 182, 193);      // Rosa cristalino
+    let energy_core = ShaderColor::from_rgb(255, 255, 255);       // Energía pura
+    
+    // Capa 2: Patrones cristalinos complejos
+    let main_crystal_pattern = voronoi_noise(uv.0 * 8.0, uv.1 * 8.0);
+    let secondary_pattern = fbm(uv.0 * 16.0, uv.1 * 16.0, 4);
+    let fractal_pattern = fbm(uv.0 * 32.0, uv.1 * 32.0, 2);
+    
+    // Capa 3: Efectos de energía y pulsación
+    let time_factor = (time * 2.0).sin() * 0.5 + 0.5;
+    let energy_pulse = (time * 4.0 + position.length() * 3.0).sin().abs();
+    let energy_flow = fbm(uv.0 * 6.0 + time * 0.5, uv.1 * 6.0, 3);
+    
+    // Selección de color base según patrones cristalinos
+    let mut base_color = crystal_core;
+    
+    // Cristales principales
+    if main_crystal_pattern < 0.2 {
+        base_color = crystal_cyan;
+    } else if main_crystal_pattern < 0.4 {
+        base_color = crystal_blue;
+    } else if main_crystal_pattern < 0.6 {
+        base_color = crystal_purple;
+    } else if main_crystal_pattern < 0.8 {
+        base_color = crystal_pink;
+    }
+    
+    // Cristales secundarios superpuestos
+    if secondary_pattern > 0.7 {
+        let blend_factor = smoothstep(0.7, 0.9, secondary_pattern) * 0.6;
+        base_color = mix_color(base_color, crystal_cyan, blend_factor);
+    }
+    
+    // Vetas de energía
+    if fractal_pattern > 0.8 {
+        let energy_factor = smoothstep(0.8, 0.95, fractal_pattern) * energy_pulse;
+        base_color = mix_color(base_color, energy_core, energy_factor);
+    }
+    
+    // Capa 5: Efectos de color dinámicos
+    let color_shift = (time * 1.5 + position.x * 0.5).sin() * 0.1;
+    let final_color = mix_color(base_color, 
+                               ShaderColor::new(base_color.b, base_color.r, base_color.g, base_color.a), 
+                               color_shift.abs());
+    
+    // Variación de transparencia basada en el patrón
+    let alpha_variation = (main_crystal_pattern * 0.2 + 0.7).clamp(0.6, 0.95);
+    
+    (final_color, alpha_variation, energy_flow, energy_pulse)
+}
+
+/// Índice de refracción del cristal (cuarzo)
+const CRYSTAL_IOR: f32 = 1.45;
+
+/// Lo que se ve del fondo en un punto del cristal: el reflejo y lo que atraviesa la
+/// esfera (refractado al entrar y al salir, teñido por `tint`), mezclados con el término
+/// de Fresnel de Schlick. `None` si la escena no tiene mapa de entorno.
+pub fn crystal_environment(position: Vector3, normal: Vector3, tint: ShaderColor, uniforms: &ShaderUniforms) -> Option<ShaderColor> {
+    let environment = uniforms.environment.as_ref()?;
+    let incident = (position - uniforms.camera_position).normalize();
+    let cos_i = (-incident.dot(&normal)).clamp(0.0, 1.0);
+
+    let f0 = ((CRYSTAL_IOR - 1.0) / (CRYSTAL_IOR + 1.0)).powi(2);
+    let fresnel = f0 + (1.0 - f0) * (1.0 - cos_i).powi(5);
+    let reflected = environment.sample(incident.reflect(&normal));
+
+    // Recorrido dentro de la esfera hasta el otro lado y salida hacia el fondo
+    let transmitted = incident.refract(&normal, 1.0 / CRYSTAL_IOR).map(|inside| {
+        let exit = position + inside * (-2.0 * position.dot(&inside));
+        let exit_normal = exit.normalize();
+        let outgoing = inside.refract(&-exit_normal, CRYSTAL_IOR).unwrap_or_else(|| inside.reflect(&-exit_normal));
+        environment.sample(outgoing)
+    });
+    let transmission = 1.0 - fresnel;
+    let through = |channel: f32, tint: f32| channel * (0.4 + 0.6 * tint) * transmission;
+
+    Some(match transmitted {
+        Some(color) => ShaderColor::new(
+            reflected.r * fresnel + through(color.r, tint.r),
+            reflected.g * fresnel + through(color.g, tint.g),
+            reflected.b * fresnel + through(color.b, tint.b),
+            1.0,
+        ),
+        None => reflected,
+    })
+}
+
 // Shader para planeta de cristal mejorado con múltiples capas cristalinas
 pub struct CrystalPlanetShader;
 
@@ -362,94 +619,81 @@ impl PlanetShader for CrystalPlanetShader {
         (new_position, normal)
     }
 
-    fn fragment_shader(&self, position: Vector3, normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> ShaderColor {
-        // Capa 1: Colores cristalinos base
-        let crystal_core = ShaderColor::from_rgb(240, 248, 255);      // Blanco cristalino
-        let crystal_blue = ShaderColor::from_rgb(173, 216, 230);      // Azul claro
-        let crystal_purple = ShaderColor::from_rgb(147, 112, 219);    // Púrpura
-        let crystal_cyan = ShaderColor::from_rgb(0, 255, 255);        // Cian brillante
-        let crystal_pink = ShaderColor::from_rgb(255, 182, 193);      // Rosa cristalino
-        let energy_core = ShaderColor::from_rgb(255, 255, 255);       // Energía pura
-        
-        // Capa 2: Patrones cristalinos complejos
-        let main_crystal_pattern = voronoi_noise(uv.0 * 8.0, uv.1 * 8.0);
-        let secondary_pattern = fbm(uv.0 * 16.0, uv.1 * 16.0, 4);
-        let fractal_pattern = fbm(uv.0 * 32.0, uv.1 * 32.0, 2);
-        
-        // Capa 3: Efectos de energía y pulsación
-        let time_factor = (uniforms.time * 2.0).sin() * 0.5 + 0.5;
-        let energy_pulse = (uniforms.time * 4.0 + position.length() * 3.0).sin().abs();
-        let energy_flow = fbm(uv.0 * 6.0 + uniforms.time * 0.5, uv.1 * 6.0, 3);
-        
-        // Selección de color base según patrones cristalinos
-        let mut base_color = crystal_core;
-        
-        // Cristales principales
-        if main_crystal_pattern < 0.2 {
-            base_color = crystal_cyan;
-        } else if main_crystal_pattern < 0.4 {
-            base_color = crystal_blue;
-        } else if main_crystal_pattern < 0.6 {
-            base_color = crystal_purple;
-        } else if main_crystal_pattern < 0.8 {
-            base_color = crystal_pink;
-        }
-        
-        // Cristales secundarios superpuestos
-        if secondary_pattern > 0.7 {
-            let blend_factor = smoothstep(0.7, 0.9, secondary_pattern) * 0.6;
-            base_color = mix_color(base_color, crystal_cyan, blend_factor);
-        }
-        
-        // Vetas de energía
-        if fractal_pattern > 0.8 {
-            let energy_factor = smoothstep(0.8, 0.95, fractal_pattern) * energy_pulse;
-            base_color = mix_color(base_color, energy_core, energy_factor);
-        }
-        
-        // Capa 4: Iluminación cristalina avanzada
-        let light_dir = uniforms.light_direction.normalize();
-        let view_dir = (uniforms.camera_position - position).normalize();
-        
-        // Iluminación difusa suave
-        let diffuse = normal.dot(&light_dir).max(0.0) * 0.4;
+    fn surface_shader(&self, position: Vector3, _normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> SurfaceSample {
+        let (final_color, alpha_variation, energy_flow, energy_pulse) = crystal_pattern(position, uv, uniforms.time);
         
-        // Múltiples reflexiones especulares para efecto cristalino
-        let reflect_dir = normal * (2.0 * normal.dot(&light_dir)) - light_dir;
-        let specular1 = view_dir.dot(&reflect_dir).max(0.0).powf(64.0);
-        let specular2 = view_dir.dot(&reflect_dir).max(0.0).powf(16.0);
-        let specular3 = view_dir.dot(&reflect_dir).max(0.0).powf(4.0);
-        
-        // Refracción simulada
-        let refraction = (1.0 - view_dir.dot(&normal)).powf(3.0) * 0.3;
-        
-        // Iluminación interna (subsurface scattering simulado)
+        // Luz interna (subsurface scattering simulado)
         let internal_light = energy_flow * 0.2 + energy_pulse * 0.3;
         
-        // Iluminación de borde con múltiples capas
-        let rim1 = (1.0 - view_dir.dot(&normal)).powf(2.0) * 0.4;
-        let rim2 = (1.0 - view_dir.dot(&normal)).powf(4.0) * 0.6;
-        
-        let ambient = 0.3;
-        let final_intensity = (ambient + diffuse + specular1 * 0.8 + specular2 * 0.4 + 
-                              specular3 * 0.2 + refraction + internal_light + rim1 + rim2).min(2.5);
-        
-        // Capa 5: Efectos de color dinámicos
-        let color_shift = (uniforms.time * 1.5 + position.x * 0.5).sin() * 0.1;
-        let final_color = mix_color(base_color, 
-                                   ShaderColor::new(base_color.b, base_color.r, base_color.g, base_color.a), 
-                                   color_shift.abs());
-        
-        // Variación de transparencia basada en el patrón
-        let alpha_variation = (main_crystal_pattern * 0.2 + 0.7).clamp(0.6, 0.95);
-        
-        ShaderColor::new(
-            (final_color.r * final_intensity).clamp(0.0, 1.0),
-            (final_color.g * final_intensity).clamp(0.0, 1.0),
-            (final_color.b * final_intensity).clamp(0.0, 1.0),
-            alpha_variation,
-        )
+        SurfaceSample {
+            albedo: ShaderColor::new(final_color.r, final_color.g, final_color.b, alpha_variation),
+            emission: ShaderColor::new(
+                final_color.r * internal_light,
+                final_color.g * internal_light,
+                final_color.b * internal_light,
+                0.0,
+            ),
+            specular: 1.0,
+            material: MaterialId::Crystal,
+            lighting: SurfaceLighting {
+                ambient: 0.3,
+                diffuse: 0.4,
+                specular: 0.8,
+                shininess: 64.0,
+                rim: 0.4,
+                rim_power: 2.0,
+                max_intensity: 2.5,
+            },
+        }
+    }
+}
+
+// Capas 1 a 3 del planeta de lava: color según la temperatura, junto con el mapa de
+// temperatura y la intensidad del calor que usa la iluminación
+fn lava_temperature(position: Vector3, uv: (f32, f32), time: f32) -> (ShaderColor, f32, f32) {
+    // Capa 1: Colores volcánicos
+    let cooled_lava = ShaderColor::from_rgb(64, 64, 64);          // Lava enfriada
+    let warm_rock = ShaderColor::from_rgb(139, 69, 19);          // Roca caliente
+    let hot_lava = ShaderColor::from_rgb(255, 69, 0);            // Lava caliente
+    let molten_core = ShaderColor::from_rgb(255, 140, 0);        // Núcleo fundido
+    let white_hot = ShaderColor::from_rgb(255, 255, 200);        // Blanco caliente
+    let ember_glow = ShaderColor::from_rgb(255, 165, 0);         // Brasa
+    
+    // Capa 2: Patrones de flujo de lava
+    let lava_flow1 = fbm(uv.0 * 6.0 + time * 0.05, uv.1 * 4.0, 4);
+    let lava_flow2 = fbm(uv.0 * 12.0 - time * 0.03, uv.1 * 8.0, 3);
+    let volcanic_cracks = voronoi_noise(uv.0 * 15.0, uv.1 * 15.0);
+    
+    // Capa 3: Actividad volcánica y temperatura
+    let heat_intensity = (time * 3.0 + position.length() * 2.0).sin() * 0.5 + 0.5;
+    let volcanic_activity = fbm(uv.0 * 8.0 + time * 0.2, uv.1 * 8.0, 2);
+    let temperature_map = lava_flow1 * 0.6 + volcanic_activity * 0.4;
+    
+    // Selección de color basada en temperatura
+    let mut base_color = cooled_lava;
+    
+    if temperature_map > 0.8 {
+        // Lava muy caliente
+        base_color = mix_color(white_hot, molten_core, (temperature_map - 0.8) * 5.0);
+    } else if temperature_map > 0.6 {
+        // Lava caliente
+        base_color = mix_color(hot_lava, white_hot, (temperature_map - 0.6) * 5.0);
+    } else if temperature_map > 0.4 {
+        // Lava tibia
+        base_color = mix_color(molten_core, hot_lava, (temperature_map - 0.4) * 5.0);
+    } else if temperature_map > 0.2 {
+        // Roca caliente
+        base_color = mix_color(warm_rock, molten_core, (temperature_map - 0.2) * 5.0);
+    }
+    
+    // Grietas volcánicas brillantes
+    if volcanic_cracks < 0.1 {
+        let crack_intensity = smoothstep(0.0, 0.1, volcanic_cracks);
+        let crack_glow = mix_color(white_hot, ember_glow, heat_intensity);
+        base_color = mix_color(crack_glow, base_color, crack_intensity);
     }
+    
+    (base_color, temperature_map, heat_intensity)
 }
 
 // Shader para planeta de lava (cuarto planeta adicional)
@@ -474,163 +718,229 @@ impl PlanetShader for LavaPlanetShader {
         (new_position, normal)
     }
 
-    fn fragment_shader(&self, position: Vector3, normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> ShaderColor {
-        // Capa 1: Colores volcánicos
-        let cooled_lava = ShaderColor::from_rgb(64, 64, 64);          // Lava enfriada
-        let warm_rock = ShaderColor::from_rgb(139, 69, 19);          // Roca caliente
-        let hot_lava = ShaderColor::from_rgb(255, 69, 0);            // Lava caliente
-        let molten_core = ShaderColor::from_rgb(255, 140, 0);        // Núcleo fundido
-        let white_hot = ShaderColor::from_rgb(255, 255, 200);        // Blanco caliente
-        let ember_glow = ShaderColor::from_rgb(255, 165, 0);         // Brasa
-        
-        // Capa 2: Patrones de flujo de lava
-        let lava_flow1 = fbm(uv.0 * 6.0 + uniforms.time * 0.05, uv.1 * 4.0, 4);
-        let lava_flow2 = fbm(uv.0 * 12.0 - uniforms.time * 0.03, uv.1 * 8.0, 3);
-        let volcanic_cracks = voronoi_noise(uv.0 * 15.0, uv.1 * 15.0);
-        
-        // Capa 3: Actividad volcánica y temperatura
-        let heat_intensity = (uniforms.time * 3.0 + position.length() * 2.0).sin() * 0.5 + 0.5;
-        let volcanic_activity = fbm(uv.0 * 8.0 + uniforms.time * 0.2, uv.1 * 8.0, 2);
-        let temperature_map = lava_flow1 * 0.6 + volcanic_activity * 0.4;
-        
-        // Selección de color basada en temperatura
-        let mut base_color = cooled_lava;
-        
-        if temperature_map > 0.8 {
-            // Lava muy caliente
-            base_color = mix_color(white_hot, molten_core, (temperature_map - 0.8) * 5.0);
-        } else if temperature_map > 0.6 {
-            // Lava caliente
-            base_color = mix_color(hot_lava, white_hot, (temperature_map - 0.6) * 5.0);
-        } else if temperature_map > 0.4 {
-            // Lava tibia
-            base_color = mix_color(molten_core, hot_lava, (temperature_map - 0.4) * 5.0);
-        } else if temperature_map > 0.2 {
-            // Roca caliente
-            base_color = mix_color(warm_rock, molten_core, (temperature_map - 0.2) * 5.0);
-        }
-        
-        // Grietas volcánicas brillantes
-        if volcanic_cracks < 0.1 {
-            let crack_intensity = smoothstep(0.0, 0.1, volcanic_cracks);
-            let crack_glow = mix_color(white_hot, ember_glow, heat_intensity);
-            base_color = mix_color(crack_glow, base_color, crack_intensity);
-        }
-        
-        // Capa 4: Iluminación volcánica
-        let light_dir = uniforms.light_direction.normalize();
-        let view_dir = (uniforms.camera_position - position).normalize();
-        
-        // Iluminación difusa
-        let diffuse = normal.dot(&light_dir).max(0.0);
-        
-        // Emisión de calor (self-illumination)
-        let heat_emission = temperature_map * 0.8 + heat_intensity * 0.4;
-        
-        // Iluminación especular para lava fundida
-        let reflect_dir = normal * (2.0 * normal.dot(&light_dir)) - light_dir;
-        let specular = view_dir.dot(&reflect_dir).max(0.0).powf(8.0) * temperature_map;
-        
-        // Resplandor volcánico
-        let volcanic_glow = (1.0 - view_dir.dot(&normal)).powf(1.5) * heat_emission * 0.3;
-        
-        let ambient = 0.1; // Ambiente bajo para planeta volcánico
-        let final_intensity = (ambient + diffuse * 0.5 + heat_emission + specular * 0.4 + volcanic_glow).min(2.0);
+    fn surface_shader(&self, position: Vector3, _normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> SurfaceSample {
+        let (base_color, temperature_map, heat_intensity) = lava_temperature(position, uv, uniforms.time);
         
         // Parpadeo de la actividad volcánica
         let flicker = (uniforms.time * 15.0 + position.x * 10.0).sin() * 0.1 + 1.0;
         let final_flicker = if temperature_map > 0.6 { flicker } else { 1.0 };
         
+        // Emisión de calor (self-illumination)
+        let heat_emission = (temperature_map * 0.8 + heat_intensity * 0.4) * final_flicker;
+        
+        SurfaceSample {
+            albedo: ShaderColor::new(
+                base_color.r * final_flicker,
+                base_color.g * final_flicker,
+                base_color.b * final_flicker,
+                1.0,
+            ),
+            emission: ShaderColor::new(
+                base_color.r * heat_emission,
+                base_color.g * heat_emission,
+                base_color.b * heat_emission,
+                0.0,
+            ),
+            specular: temperature_map,
+            material: MaterialId::Lava,
+            lighting: SurfaceLighting { ambient: 0.1, diffuse: 0.5, specular: 0.4, shininess: 8.0, max_intensity: 2.0, ..SurfaceLighting::MATTE },
+        }
+    }
+}
+
+/// Color de un cuerpo negro a la temperatura dada (Kelvin), normalizado para que el
+/// canal más intenso valga 1. Aproximación de Tanner Helland, válida de 1000 K a 40000 K
+pub fn blackbody_color(kelvin: f32) -> ShaderColor {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let r = if t <= 66.0 { 255.0 } else { 329.698_73 * (t - 60.0).powf(-0.133_204_76) };
+    let g = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_17 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+    ShaderColor::new(
+        (r / 255.0).clamp(0.0, 1.0),
+        (g / 255.0).clamp(0.0, 1.0),
+        (b / 255.0).clamp(0.0, 1.0),
+        1.0,
+    )
+}
+
+// Capas 1 a 3 de la estrella: temperatura de cada punto de la fotosfera según la
+// granulación y las manchas solares
+fn star_temperature(uv: (f32, f32), time: f32, temperature: f32) -> f32 {
+    // Capa 1: Granulación - celdas de convección que hierven con el tiempo,
+    // centros calientes que suben y bordes más fríos que bajan
+    let cells = voronoi_noise(uv.0 * 24.0 + (time * 0.7).sin() * 0.3, uv.1 * 12.0 + time * 0.15);
+    let boiling = (time * 2.0 + cells * 9.0).sin() * 0.5 + 0.5;
+    let granulation = (1.0 - smoothstep(0.1, 0.6, cells)) * (0.7 + boiling * 0.3);
+    
+    // Capa 2: Manchas solares en bandas de latitud media, arrastradas por la rotación
+    let latitude = (uv.1 - 0.5).abs();
+    let band = smoothstep(0.05, 0.12, latitude) * (1.0 - smoothstep(0.2, 0.3, latitude));
+    let spots = voronoi_noise(uv.0 * 7.0 - time * 0.02, uv.1 * 5.0);
+    let umbra = (1.0 - smoothstep(0.05, 0.1, spots)) * band;
+    let penumbra = (1.0 - smoothstep(0.1, 0.2, spots)) * band;
+    
+    // Capa 3: Temperatura local (la umbra es ~35% más fría)
+    let surface = temperature * (0.92 + granulation * 0.12);
+    surface * (1.0 - penumbra * 0.15 - umbra * 0.2)
+}
+
+// Shader de estrella: emite su propia luz, no la recibe de la escena
+#[derive(Debug, Clone, Copy)]
+pub struct StarShader {
+    /// Temperatura efectiva de la superficie en Kelvin, define el color
+    pub temperature: f32,
+}
+
+impl StarShader {
+    /// Color que emite un punto de la superficie, con oscurecimiento del limbo
+    fn emission(&self, position: Vector3, normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> ShaderColor {
+        // Capas 1 a 3: Granulación y manchas solares
+        let local_temperature = star_temperature(uv, uniforms.time, self.temperature);
+        
+        // Capa 4: Oscurecimiento del limbo - en el borde se ven capas más altas y
+        // frías, más tenues y rojizas (ley lineal con u = 0.6)
+        let view_dir = (uniforms.camera_position - position).normalize();
+        let mu = view_dir.dot(&normal).max(0.0);
+        let limb = 1.0 - 0.6 * (1.0 - mu);
+        let limb_temperature = local_temperature * (0.8 + 0.2 * mu);
+        
+        // Brillo proporcional a T^4 (Stefan-Boltzmann) relativo a la temperatura efectiva,
+        // algo saturado en el centro del disco para que se vea deslumbrante
+        let brightness = (local_temperature / self.temperature).powi(4) * limb * 1.3;
+        let color = blackbody_color(limb_temperature);
         ShaderColor::new(
-            (base_color.r * final_intensity * final_flicker).clamp(0.0, 1.0),
-            (base_color.g * final_intensity * final_flicker).clamp(0.0, 1.0),
-            (base_color.b * final_intensity * final_flicker).clamp(0.0, 1.0),
+            (color.r * brightness).max(0.0),
+            (color.g * brightness).max(0.0),
+            (color.b * brightness).max(0.0),
             1.0,
         )
     }
+    
+    /// Luz de la corona a `distance` radios del centro (1 = borde del disco), en la
+    /// dirección `angle` de la pantalla: resplandor que cae rápido y rayos animados
+    pub fn corona(&self, distance: f32, angle: f32, time: f32) -> ShaderColor {
+        let height = (distance - 1.0).max(0.0);
+        let glow = (-height * 4.0).exp() * 0.8;
+        let streamers = ((angle * 6.0 + (angle * 3.0 + time * 0.4).sin() * 1.5).sin() * 0.5 + 0.5).powi(3);
+        let rays = streamers * (-height * 1.5).exp() * 0.35;
+        let intensity = glow + rays;
+        // La corona es más fría que la superficie, algo más anaranjada
+        let color = blackbody_color(self.temperature * 0.8);
+        ShaderColor::new(color.r * intensity, color.g * intensity, color.b * intensity, intensity.min(1.0))
+    }
 }
 
-// Estructura para anillos procedurales
-pub struct RingShader;
+impl PlanetShader for StarShader {
+    fn vertex_shader(&self, position: Vector3, normal: Vector3, _uv: (f32, f32), _uniforms: &ShaderUniforms) -> (Vector3, Vector3) {
+        // Sin relieve: la fotosfera es gas
+        (position, normal)
+    }
 
-impl RingShader {
-    pub fn vertex_shader(vertex: &Vertex, uniforms: &ShaderUniforms) -> (Vector3, ShaderColor) {
-        let mut pos = vertex.position;
-        
-        // Crear anillos procedurales usando coordenadas polares
-        let radius = (pos.x * pos.x + pos.z * pos.z).sqrt();
-        let angle = pos.z.atan2(pos.x);
-        
-        // Generar múltiples anillos con diferentes radios
-        let ring_count = 8.0;
-        let ring_spacing = 0.3;
-        let base_radius = 1.5;
-        
-        // Determinar en qué anillo estamos
-        let ring_index = (radius / ring_spacing).floor();
-        let ring_center = base_radius + ring_index * ring_spacing;
-        
-        // Crear variaciones en el anillo usando noise
-        let noise_scale = 10.0;
-        let ring_noise = simple_noise(angle * noise_scale + uniforms.time * 2.0, 0.0);
-        let radial_noise = simple_noise(radius * 15.0 + uniforms.time, 0.0);
-        
-        // Modular la altura del anillo
-        let ring_height = 0.02 + ring_noise * 0.01;
-        pos.y = ring_height * (1.0 + radial_noise * 0.5);
-        
-        // Crear gaps en los anillos
-        let gap_noise = simple_noise(angle * 20.0 + ring_index * 3.14159, 0.0);
-        if gap_noise > 0.7 {
-            pos.y *= 0.1; // Hacer el anillo muy delgado en los gaps
+    fn surface_shader(&self, position: Vector3, normal: Vector3, uv: (f32, f32), uniforms: &ShaderUniforms) -> SurfaceSample {
+        SurfaceSample {
+            albedo: ShaderColor::BLACK,
+            emission: self.emission(position, normal, uv, uniforms),
+            specular: 0.0,
+            material: MaterialId::Star,
+            // Solo emite, las luces de la escena no la iluminan
+            lighting: SurfaceLighting { ambient: 0.0, diffuse: 0.0, ..SurfaceLighting::MATTE },
         }
-        
-        // Rotación de los anillos
-        let rotation_speed = 0.5 + ring_index * 0.1;
-        let rotated_angle = angle + uniforms.time * rotation_speed;
-        pos.x = radius * rotated_angle.cos();
-        pos.z = radius * rotated_angle.sin();
-        
-        // Color base del anillo
-        let ring_color_variation = simple_noise(ring_index * 2.0, 0.0);
-        let base_color = if ring_color_variation > 0.0 {
-            ShaderColor { r: 0.8, g: 0.7, b: 0.5, a: 0.8 } // Dorado
-        } else {
-            ShaderColor { r: 0.6, g: 0.5, b: 0.4, a: 0.7 } // Marrón
-        };
-        
-        (pos, base_color)
+    }
+}
+
+// Shader de nubes: cobertura animada con ruido 3D, se dibuja con transparencia
+// sobre el planeta
+#[derive(Debug, Clone, Copy)]
+pub struct CloudShader {
+    pub color: ShaderColor,
+    /// Umbral del ruido: más alto, menos nubes
+    pub threshold: f32,
+    /// Alpha de una nube completamente cubierta
+    pub opacity: f32,
+}
+
+impl CloudShader {
+    /// Cobertura (0 = cielo despejado, 1 = nube densa) en un punto de la capa, en
+    /// coordenadas propias de la capa (ya sin su rotación)
+    pub fn coverage(&self, local_position: Vector3, time: f32) -> f32 {
+        let p = local_position.normalize();
+        
+        // Capa 1: Formas grandes que cambian lentamente con el tiempo
+        let drift = Vector3::new(time * 0.04, time * 0.01, -time * 0.03);
+        let shapes = fbm_3d(p * 2.5 + drift, 5);
+        
+        // Capa 2: Más nubes en latitudes medias y cerca de los polos
+        let latitude = p.y.abs();
+        let bands = 0.85 + 0.15 * (latitude * std::f32::consts::PI * 3.0).cos().abs();
+        
+        // Capa 3: Bordes suaves con detalle fino
+        let detail = fbm_3d(p * 12.0 - drift * 2.0, 2);
+        smoothstep(self.threshold, self.threshold + 0.2, shapes * bands + (detail - 0.5) * 0.08)
     }
     
+    /// Color premultiplicado por el alpha de la nube en `position`, iluminada por
+    /// las luces de la escena con algo de luz que la atraviesa en el terminador
+    pub fn fragment_shader(&self, position: Vector3, normal: Vector3, coverage: f32, uniforms: &ShaderUniforms) -> ShaderColor {
+        let direct = uniforms.light_sum(position, normal, |light_dir, shadow| {
+            ((normal.dot(&light_dir) + 0.3) / 1.3).max(0.0) * shadow
+        });
+        let ambient = 0.08;
+        let alpha = coverage * self.opacity;
+        ShaderColor::new(
+            self.color.r * (ambient + direct.r).min(1.2) * alpha,
+            self.color.g * (ambient + direct.g).min(1.2) * alpha,
+            self.color.b * (ambient + direct.b).min(1.2) * alpha,
+            alpha,
+        )
+    }
+}
+
+// Material rocoso simple para las partículas de los anillos
+pub struct RingParticleShader;
+
+impl RingParticleShader {
     pub fn fragment_shader(
-        _world_pos: Vector3,
-        _normal: Vector3,
+        world_pos: Vector3,
+        normal: Vector3,
         color: ShaderColor,
         uniforms: &ShaderUniforms,
     ) -> ShaderColor {
-        let radius = (_world_pos.x * _world_pos.x + _world_pos.z * _world_pos.z).sqrt();
+        shade_surface(&Self::surface_shader(world_pos, color), world_pos, normal, uniforms)
+    }
+    
+    pub fn surface_shader(world_pos: Vector3, color: ShaderColor) -> SurfaceSample {
+        let pattern = Self::surface_pattern(world_pos);
+        SurfaceSample {
+            albedo: ShaderColor::new(color.r * pattern, color.g * pattern, color.b * pattern, 1.0),
+            emission: ShaderColor::new(0.0, 0.0, 0.0, 0.0),
+            specular: 0.0,
+            material: MaterialId::RingParticle,
+            lighting: SurfaceLighting::MATTE,
+        }
+    }
+    
+    // Bandas de color según el radio orbital y rugosidad de la roca
+    fn surface_pattern(world_pos: Vector3) -> f32 {
+        let radius = (world_pos.x * world_pos.x + world_pos.z * world_pos.z).sqrt();
         
-        // Crear bandas de color en los anillos
+        // Bandas de color según el radio orbital
         let band_frequency = 25.0;
         let band_pattern = (radius * band_frequency).sin() * 0.5 + 0.5;
         
-        // Variaciones de densidad
-        let density_noise = fbm(_world_pos.x * 30.0, _world_pos.z * 30.0, 3);
-        let density = 0.3 + density_noise * 0.4;
+        // Rugosidad de la superficie de la roca
+        let roughness = fbm(world_pos.x * 40.0, world_pos.y * 40.0 + world_pos.z * 40.0, 2);
         
-        // Partículas brillantes ocasionales
-        let sparkle_noise = simple_noise(_world_pos.x * 100.0 + _world_pos.z * 100.0 + uniforms.time * 5.0, 0.0);
-        let sparkle = if sparkle_noise > 0.95 { 0.5 } else { 0.0 };
-        
-        // Combinar efectos
-        let final_color = ShaderColor {
-            r: color.r * (0.7 + band_pattern * 0.3) + sparkle,
-            g: color.g * (0.7 + band_pattern * 0.3) + sparkle * 0.8,
-            b: color.b * (0.7 + band_pattern * 0.3) + sparkle * 0.6,
-            a: color.a * density,
-        };
-        
-        final_color
+        (0.7 + band_pattern * 0.3) * (0.85 + roughness * 0.3)
     }
 }
 
@@ -638,55 +948,30 @@ impl RingShader {
 pub struct MoonShader;
 
 impl MoonShader {
-    pub fn vertex_shader(vertex: &Vertex, uniforms: &ShaderUniforms) -> (Vector3, ShaderColor) {
+    /// Hunde los cráteres a lo largo de la normal y da el color base. La posición y el
+    /// tamaño de la luna ya vienen en el vértice; el ruido se toma sobre la normal para
+    /// que los cráteres no se muevan al recorrer la órbita
+    pub fn vertex_shader(vertex: &Vertex, _uniforms: &ShaderUniforms) -> (Vector3, ShaderColor) {
         let mut pos = vertex.position;
-        
-        // Escalar la luna para que sea más pequeña
-        let moon_scale = 0.3;
-        pos = pos * moon_scale;
-        
-        // Órbita de la luna alrededor del planeta
-        let orbit_radius = 3.0;
-        let orbit_speed = 0.8;
-        let orbit_angle = uniforms.time * orbit_speed;
-        
-        // Posición orbital
-        let orbit_x = orbit_radius * orbit_angle.cos();
-        let orbit_z = orbit_radius * orbit_angle.sin();
-        
-        // Agregar la posición orbital
-        pos.x += orbit_x;
-        pos.z += orbit_z;
-        
-        // Rotación propia de la luna
-        let moon_rotation = uniforms.time * 0.3;
-        let cos_rot = moon_rotation.cos();
-        let sin_rot = moon_rotation.sin();
-        
-        // Aplicar rotación en Y
-        let rotated_x = pos.x * cos_rot - pos.z * sin_rot;
-        let rotated_z = pos.x * sin_rot + pos.z * cos_rot;
-        pos.x = rotated_x;
-        pos.z = rotated_z;
+        let local = vertex.normal;
         
         // Crear cráteres usando noise
-        let crater_noise1 = simple_noise(pos.x * 15.0, pos.y * 15.0 + pos.z * 15.0);
-        let crater_noise2 = simple_noise(pos.x * 25.0 + 100.0, pos.y * 25.0 + pos.z * 25.0 + 100.0);
+        let crater_noise1 = simple_noise(local.x * 15.0, local.y * 15.0 + local.z * 15.0);
+        let crater_noise2 = simple_noise(local.x * 25.0 + 100.0, local.y * 25.0 + local.z * 25.0 + 100.0);
         
-        // Deformación por cráteres
-        let crater_depth = 0.0;
+        // Deformación por cráteres, hacia el centro de la luna
         if crater_noise1 > 0.6 {
             let crater_intensity = (crater_noise1 - 0.6) * 2.5;
-            pos = pos * (1.0 - crater_intensity * 0.1);
+            pos -= vertex.normal * (crater_intensity * 0.03);
         }
         
         if crater_noise2 > 0.7 {
             let crater_intensity = (crater_noise2 - 0.7) * 3.0;
-            pos = pos * (1.0 - crater_intensity * 0.05);
+            pos -= vertex.normal * (crater_intensity * 0.015);
         }
         
         // Color base de la luna (gris lunar)
-        let surface_variation = simple_noise(pos.x * 10.0, pos.y * 10.0 + pos.z * 10.0);
+        let surface_variation = simple_noise(local.x * 10.0, local.y * 10.0 + local.z * 10.0);
         let base_gray = 0.4 + surface_variation * 0.2;
         
         let base_color = ShaderColor { 
@@ -705,10 +990,21 @@ impl MoonShader {
         color: ShaderColor,
         uniforms: &ShaderUniforms,
     ) -> ShaderColor {
-        // Iluminación básica
-        let light_dir = uniforms.light_direction.normalize();
-        let dot_product = normal.dot(&light_dir).max(0.0);
-        
+        shade_surface(&Self::surface_shader(world_pos, color), world_pos, normal, uniforms)
+    }
+    
+    pub fn surface_shader(world_pos: Vector3, color: ShaderColor) -> SurfaceSample {
+        SurfaceSample {
+            albedo: Self::surface_color(world_pos, color),
+            emission: ShaderColor::new(0.0, 0.0, 0.0, 0.0),
+            specular: 0.0,
+            material: MaterialId::Moon,
+            lighting: SurfaceLighting { ambient: 0.3, diffuse: 0.7, rim: 0.2, rim_power: 2.0, ..SurfaceLighting::MATTE },
+        }
+    }
+    
+    // Cráteres y detalle de superficie sobre el color base, sin iluminar
+    fn surface_color(world_pos: Vector3, color: ShaderColor) -> ShaderColor {
         // Crear variaciones de superficie
         let surface_detail = fbm(world_pos.x * 20.0, world_pos.y * 20.0 + world_pos.z * 20.0, 4);
         
@@ -738,20 +1034,6 @@ impl MoonShader {
         final_color.g += surface_detail * 0.1;
         final_color.b += surface_detail * 0.12;
         
-        // Aplicar iluminación
-        final_color.r *= 0.3 + dot_product * 0.7;
-        final_color.g *= 0.3 + dot_product * 0.7;
-        final_color.b *= 0.3 + dot_product * 0.7;
-        
-        // Rim lighting para dar más volumen
-        let view_dir = (uniforms.camera_position - world_pos).normalize();
-        let rim = 1.0 - normal.dot(&view_dir).abs();
-        let rim_intensity = rim.powf(2.0) * 0.2;
-        
-        final_color.r += rim_intensity;
-        final_color.g += rim_intensity;
-        final_color.b += rim_intensity * 1.1;
-        
         final_color
     }
-}
\ No newline at end of file
+}
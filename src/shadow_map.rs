@@ -0,0 +1,138 @@
+use crate::fragment::{triangle, ClipRect, TransformedVertex};
+use crate::matrix::{self, Matrix};
+use crate::shaders::ShaderColor;
+use crate::simd::{self, Vector3Soa};
+use crate::vector::Vector3;
+
+/// Resolution of the shadow map along each side, in texels
+pub const SHADOW_MAP_SIZE: u32 = 1024;
+
+/// Half width of the PCF kernel in texels (3x3 samples)
+const PCF_RADIUS: i32 = 1;
+
+/// Depth of the scene as seen from a directional light, covering a sphere around
+/// the scene. Every shadow caster is drawn into it before shading, then the shaders
+/// ask how much light reaches each point.
+pub struct ShadowMap {
+    size: u32,
+    /// Unit vector towards the light
+    light_direction: Vector3,
+    /// World space -> (texel x, texel y, depth)
+    light_matrix: Matrix,
+    /// World units covered by one texel
+    texel_size: f32,
+    /// Depth units per world unit along the light direction
+    depth_scale: f32,
+    depth: Vec<f32>,
+}
+
+impl ShadowMap {
+    /// Empty shadow map for a light coming from `light_direction` (pointing towards
+    /// the light, as in `ShaderUniforms`) that covers the sphere `center`, `radius`
+    pub fn new(light_direction: Vector3, center: Vector3, radius: f32, size: u32) -> Self {
+        let direction = light_direction.normalize();
+        let eye = center + direction * (radius * 2.0);
+        // Any up vector works as long as it is not parallel to the light
+        let up = if direction.y.abs() > 0.99 { Vector3::new(1.0, 0.0, 0.0) } else { Vector3::new(0.0, 1.0, 0.0) };
+        let (near, far) = (radius, radius * 3.0);
+
+        let view = matrix::create_view_matrix(eye, center, up);
+        let projection = matrix::create_orthographic_matrix(-radius, radius, -radius, radius, near, far);
+        let viewport = matrix::create_viewport_matrix(0.0, 0.0, size as f32, size as f32);
+
+        ShadowMap {
+            size,
+            light_direction: direction,
+            light_matrix: viewport.multiply(&projection.multiply(&view)),
+            texel_size: 2.0 * radius / size as f32,
+            depth_scale: 2.0 / (far - near),
+            depth: vec![f32::INFINITY; (size * size) as usize],
+        }
+    }
+
+    /// Draws the triangles of a mesh, with vertex positions in object space
+    pub fn render_mesh(&mut self, model: &Matrix, positions: &Vector3Soa, indices: &[u32]) {
+        let mut light_positions = Vector3Soa::default();
+        simd::transform_points(&self.light_matrix.multiply(model), positions, &mut light_positions);
+        let vertices: Vec<TransformedVertex> = (0..light_positions.len())
+            .map(|i| TransformedVertex {
+                screen_position: light_positions.get(i),
+                world_position: Vector3::zero(),
+                normal: Vector3::zero(),
+                color: ShaderColor::BLACK,
+                uv: (0.0, 0.0),
+            })
+            .collect();
+
+        let clip = ClipRect { min_x: 0, min_y: 0, max_x: self.size as i32 - 1, max_y: self.size as i32 - 1 };
+        let size = self.size as usize;
+        let depth = &mut self.depth;
+        for face in indices.chunks_exact(3) {
+            let [a, b, c] = [face[0], face[1], face[2]].map(|i| &vertices[i as usize]);
+            triangle(a, b, c, clip, |sample| {
+                let texel = &mut depth[sample.y as usize * size + sample.x as usize];
+                *texel = texel.min(sample.depth);
+            });
+        }
+    }
+
+    /// Draws a sphere. Seen from a directional light it is a disc whose depth
+    /// follows the cap facing the light.
+    pub fn render_sphere(&mut self, center: Vector3, radius: f32) {
+        let c = self.light_matrix.transform_vector(&center);
+        if !(c.x.is_finite() && c.y.is_finite() && c.z.is_finite()) {
+            return;
+        }
+        let texel_radius = (radius / self.texel_size).max(0.5);
+        let last = self.size as i32 - 1;
+        let min_x = ((c.x - texel_radius).floor() as i32).max(0);
+        let max_x = ((c.x + texel_radius).ceil() as i32).min(last);
+        let min_y = ((c.y - texel_radius).floor() as i32).max(0);
+        let max_y = ((c.y + texel_radius).ceil() as i32).min(last);
+
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let dx = (x as f32 + 0.5 - c.x) / texel_radius;
+                let dy = (y as f32 + 0.5 - c.y) / texel_radius;
+                let d2 = dx * dx + dy * dy;
+                if d2 > 1.0 {
+                    continue;
+                }
+                let depth = c.z - (1.0 - d2).sqrt() * radius * self.depth_scale;
+                let texel = &mut self.depth[(y as u32 * self.size + x as u32) as usize];
+                *texel = texel.min(depth);
+            }
+        }
+    }
+
+    /// Fraction of the light that reaches `position` (1 = fully lit), filtered over
+    /// a block of texels around it (PCF) so shadow edges are soft instead of stepped.
+    /// The point is pushed out along its normal first to avoid self-shadowing acne.
+    pub fn visibility(&self, position: Vector3, normal: Vector3) -> f32 {
+        let p = self.light_matrix.transform_vector(&(position + normal * (self.texel_size * 1.5)));
+        if !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite()) {
+            return 1.0;
+        }
+        // Slope-scaled bias: a surface at a grazing angle to the light changes depth
+        // quickly between neighbouring texels of the kernel
+        let cos_angle = normal.dot(&self.light_direction).abs().max(0.2);
+        let slope = (1.0 - cos_angle * cos_angle).sqrt() / cos_angle;
+        let depth = p.z - self.texel_size * self.depth_scale * (2.0 + 2.0 * slope);
+        let (cx, cy) = (p.x.floor() as i32, p.y.floor() as i32);
+        let size = self.size as i32;
+
+        let mut lit = 0;
+        let mut total = 0;
+        for y in (cy - PCF_RADIUS)..=(cy + PCF_RADIUS) {
+            for x in (cx - PCF_RADIUS)..=(cx + PCF_RADIUS) {
+                total += 1;
+                // Outside the map nothing casts shadows
+                let outside = x < 0 || y < 0 || x >= size || y >= size;
+                if outside || depth <= self.depth[(y * size + x) as usize] {
+                    lit += 1;
+                }
+            }
+        }
+        lit as f32 / total as f32
+    }
+}
@@ -1,4 +1,6 @@
 use crate::vector::Vector3;
+use crate::obj_loader::calculate_spherical_uv;
+use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
 pub struct Vertex {
@@ -7,10 +9,12 @@ pub struct Vertex {
     pub uv: (f32, f32),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
+    /// Optional per-vertex tangents (xyz + handedness sign), empty when not provided
+    pub tangents: Vec<(Vector3, f32)>,
 }
 
 impl Mesh {
@@ -18,6 +22,34 @@ impl Mesh {
         Mesh {
             vertices: Vec::new(),
             indices: Vec::new(),
+            tangents: Vec::new(),
+        }
+    }
+
+    /// Recomputes smooth vertex normals from the triangles.
+    /// Vertices sharing the exact same position (UV seams, per-face copies from OBJ)
+    /// are welded so the result stays smooth across them.
+    pub fn recompute_normals(&mut self) {
+        let key = |v: &Vector3| [v.x.to_bits(), v.y.to_bits(), v.z.to_bits()];
+        let mut accumulated: HashMap<[u32; 3], Vector3> = HashMap::new();
+
+        for face in self.indices.chunks_exact(3) {
+            let a = self.vertices[face[0] as usize].position;
+            let b = self.vertices[face[1] as usize].position;
+            let c = self.vertices[face[2] as usize].position;
+            // Area-weighted face normal
+            let face_normal = (b - a).cross(&(c - a));
+
+            for position in [a, b, c] {
+                let sum = accumulated.entry(key(&position)).or_insert(Vector3::zero());
+                *sum += face_normal;
+            }
+        }
+
+        for vertex in &mut self.vertices {
+            if let Some(sum) = accumulated.get(&key(&vertex.position)) {
+                vertex.normal = sum.normalize();
+            }
         }
     }
 
@@ -74,4 +106,87 @@ impl Mesh {
 
         mesh
     }
-}
\ No newline at end of file
+
+    /// Generates a geodesic sphere by subdividing an icosahedron.
+    /// Triangles are much more uniform than in a UV sphere, which suits displacement.
+    /// Vertices on the UV seam are duplicated so `u` does not wrap inside a triangle.
+    pub fn create_icosphere(radius: f32, subdivisions: u32) -> Self {
+        let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+        let mut directions: Vec<Vector3> = [
+            (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+            (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+            (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+        ]
+        .iter()
+        .map(|&(x, y, z)| Vector3::new(x, y, z).normalize())
+        .collect();
+
+        let mut faces: Vec<[u32; 3]> = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+        ];
+
+        // Split every triangle in four, sharing midpoints between neighbours
+        for _ in 0..subdivisions {
+            let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+            let mut midpoint = |a: u32, b: u32, directions: &mut Vec<Vector3>| {
+                let key = (a.min(b), a.max(b));
+                *midpoints.entry(key).or_insert_with(|| {
+                    let mid = (directions[a as usize] + directions[b as usize]).normalize();
+                    directions.push(mid);
+                    directions.len() as u32 - 1
+                })
+            };
+
+            let mut subdivided = Vec::with_capacity(faces.len() * 4);
+            for [a, b, c] in faces {
+                let ab = midpoint(a, b, &mut directions);
+                let bc = midpoint(b, c, &mut directions);
+                let ca = midpoint(c, a, &mut directions);
+                subdivided.push([a, ab, ca]);
+                subdivided.push([b, bc, ab]);
+                subdivided.push([c, ca, bc]);
+                subdivided.push([ab, bc, ca]);
+            }
+            faces = subdivided;
+        }
+
+        let mut mesh = Mesh::new();
+        for direction in &directions {
+            mesh.vertices.push(Vertex {
+                position: *direction * radius,
+                normal: *direction,
+                uv: calculate_spherical_uv(*direction),
+            });
+        }
+
+        // Fix the seam: triangles spanning more than half the texture get the
+        // low-u corners replaced by copies shifted by one full turn
+        let mut seam_copies: HashMap<u32, u32> = HashMap::new();
+        for face in &mut faces {
+            let us = face.map(|i| mesh.vertices[i as usize].uv.0);
+            let max_u = us.iter().cloned().fold(f32::MIN, f32::max);
+            let min_u = us.iter().cloned().fold(f32::MAX, f32::min);
+            if max_u - min_u <= 0.5 {
+                continue;
+            }
+
+            for index in face.iter_mut() {
+                if mesh.vertices[*index as usize].uv.0 < 0.5 {
+                    let original = *index;
+                    *index = *seam_copies.entry(original).or_insert_with(|| {
+                        let mut copy = mesh.vertices[original as usize].clone();
+                        copy.uv.0 += 1.0;
+                        mesh.vertices.push(copy);
+                        mesh.vertices.len() as u32 - 1
+                    });
+                }
+            }
+        }
+
+        mesh.indices = faces.iter().flatten().copied().collect();
+        mesh
+    }
+}
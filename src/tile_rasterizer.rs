@@ -0,0 +1,109 @@
+use crate::fragment::{triangle, ClipRect, TransformedVertex};
+use crate::framebuffer::Framebuffer;
+use crate::vector::Vector3;
+use rayon::prelude::*;
+
+/// Height in pixels of a screen tile. Tiles span full framebuffer rows so each
+/// one owns a contiguous slice of `Framebuffer::pixels` and `Framebuffer::zbuffer`.
+pub const TILE_HEIGHT: usize = 32;
+
+/// Triangle already transformed to screen space and shaded per vertex
+pub type ScreenTriangle = [TransformedVertex; 3];
+
+/// Triangle indices that touch each tile, in submission order
+pub struct TileBins {
+    pub bins: Vec<Vec<u32>>,
+}
+
+impl TileBins {
+    /// Bins every triangle into the tiles covered by its screen bounding box.
+    /// Triangles fully outside the screen or with invalid coordinates are dropped.
+    pub fn new(triangles: &[ScreenTriangle], width: u32, height: u32) -> Self {
+        let tile_count = (height as usize).div_ceil(TILE_HEIGHT);
+        let mut bins = vec![Vec::new(); tile_count];
+
+        for (index, tri) in triangles.iter().enumerate() {
+            let xs = [tri[0].screen_position.x, tri[1].screen_position.x, tri[2].screen_position.x];
+            let ys = [tri[0].screen_position.y, tri[1].screen_position.y, tri[2].screen_position.y];
+            if xs.iter().chain(ys.iter()).any(|c| !c.is_finite()) {
+                continue;
+            }
+
+            let min_x = xs[0].min(xs[1]).min(xs[2]);
+            let max_x = xs[0].max(xs[1]).max(xs[2]);
+            let min_y = ys[0].min(ys[1]).min(ys[2]).floor();
+            let max_y = ys[0].max(ys[1]).max(ys[2]).ceil();
+            if max_x < 0.0 || min_x >= width as f32 || max_y < 0.0 || min_y >= height as f32 {
+                continue;
+            }
+
+            let first_tile = min_y.max(0.0) as usize / TILE_HEIGHT;
+            let last_tile = (max_y as usize / TILE_HEIGHT).min(tile_count - 1);
+            for bin in &mut bins[first_tile..=last_tile] {
+                bin.push(index as u32);
+            }
+        }
+
+        TileBins { bins }
+    }
+}
+
+/// Tests a screen-space triangle against the framebuffer's hierarchical z-buffer,
+/// using its bounding box and nearest vertex depth. Hidden or fully off-screen
+/// triangles can be dropped before shading their vertices.
+pub fn is_triangle_hidden(framebuffer: &Framebuffer, corners: [Vector3; 3]) -> bool {
+    let [a, b, c] = corners;
+    let values = [a.x, a.y, a.z, b.x, b.y, b.z, c.x, c.y, c.z];
+    if values.iter().any(|v| !v.is_finite()) {
+        return false; // Let the rasterizer decide
+    }
+
+    let min_x = a.x.min(b.x).min(c.x).floor() as i32;
+    let min_y = a.y.min(b.y).min(c.y).floor() as i32;
+    let max_x = a.x.max(b.x).max(c.x).ceil() as i32;
+    let max_y = a.y.max(b.y).max(c.y).ceil() as i32;
+    framebuffer.is_hidden(min_x, min_y, max_x, max_y, a.z.min(b.z).min(c.z))
+}
+
+/// Rasterizes the triangles into the framebuffer, shading tiles in parallel.
+/// Within a tile triangles are drawn in submission order, so the result is
+/// identical to drawing them one after another on a single thread.
+pub fn rasterize_tiled(framebuffer: &mut Framebuffer, triangles: &[ScreenTriangle]) {
+    let width = framebuffer.width as usize;
+    let height = framebuffer.height;
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let tiles = TileBins::new(triangles, width as u32, height);
+    let tile_len = width * TILE_HEIGHT;
+
+    framebuffer
+        .hdr
+        .par_chunks_mut(tile_len)
+        .zip(framebuffer.zbuffer.par_chunks_mut(tile_len))
+        .zip(tiles.bins.par_iter())
+        .enumerate()
+        .for_each(|(tile, ((pixels, depths), bin))| {
+            let y0 = (tile * TILE_HEIGHT) as i32;
+            let rows = (pixels.len() / width) as i32;
+            let clip = ClipRect {
+                min_x: 0,
+                min_y: y0,
+                max_x: width as i32 - 1,
+                max_y: y0 + rows - 1,
+            };
+
+            for &index in bin {
+                let [v1, v2, v3] = &triangles[index as usize];
+                triangle(v1, v2, v3, clip, |sample| {
+                    let local = (sample.y - y0) as usize * width + sample.x as usize;
+                    // Early-z: attributes are only interpolated for pixels that pass the depth test
+                    if sample.depth < depths[local] {
+                        pixels[local] = sample.fragment().color;
+                        depths[local] = sample.depth;
+                    }
+                });
+            }
+        });
+}
@@ -0,0 +1,120 @@
+use crate::shaders::ShaderColor;
+use raylib::prelude::Color;
+
+/// Curve that compresses HDR radiance into the displayable [0, 1] range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapper {
+    /// No curve, values above 1 saturate (the look before the HDR buffer)
+    Clamp,
+    /// Extended Reinhard, reaching white at `REINHARD_WHITE`
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic reference curve
+    Aces,
+    /// Hable's filmic curve (Uncharted 2)
+    Filmic,
+}
+
+/// Radiance that extended Reinhard maps to pure white, the brightest crystal highlight
+const REINHARD_WHITE: f32 = 2.5;
+
+impl ToneMapper {
+    pub const ALL: [ToneMapper; 4] = [ToneMapper::Clamp, ToneMapper::Reinhard, ToneMapper::Aces, ToneMapper::Filmic];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneMapper::Clamp => "clamp",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::Aces => "aces",
+            ToneMapper::Filmic => "filmic",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mapper| mapper.name() == name)
+    }
+
+    /// Next curve in display order, wrapping around
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&mapper| mapper == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Maps one linear channel, already scaled by the exposure, to [0, 1]
+    fn apply(self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        match self {
+            ToneMapper::Clamp => x.min(1.0),
+            ToneMapper::Reinhard => (x * (1.0 + x / (REINHARD_WHITE * REINHARD_WHITE)) / (1.0 + x)).min(1.0),
+            ToneMapper::Aces => {
+                let x = x * 0.6;
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+            ToneMapper::Filmic => {
+                const WHITE: f32 = 11.2;
+                const EXPOSURE_BIAS: f32 = 2.0;
+                (hable(x * EXPOSURE_BIAS) / hable(WHITE)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+}
+
+/// Linear [0, 1] to the sRGB transfer curve the display expects
+fn srgb_encode(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Inverse of `srgb_encode`, for 8-bit images that have to be used as linear color
+pub fn srgb_decode(x: f32) -> f32 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// How the HDR color buffer becomes the 8-bit image on screen
+#[derive(Debug, Clone, Copy)]
+pub struct ToneMapping {
+    pub operator: ToneMapper,
+    /// Multiplies the radiance before the curve
+    pub exposure: f32,
+    /// Encode with the sRGB curve; off only for data views that must stay linear
+    pub srgb: bool,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping { operator: ToneMapper::Aces, exposure: 1.0, srgb: true }
+    }
+}
+
+impl ToneMapping {
+    /// Leaves [0, 1] values untouched, for debug views such as the G-buffer channels
+    pub fn passthrough() -> Self {
+        ToneMapping { operator: ToneMapper::Clamp, exposure: 1.0, srgb: false }
+    }
+
+    /// Exposure, curve and encoding of one color, still as floats in [0, 1]
+    pub fn apply(&self, color: ShaderColor) -> ShaderColor {
+        let channel = |value: f32| {
+            let mapped = self.operator.apply(value * self.exposure);
+            if self.srgb { srgb_encode(mapped) } else { mapped }
+        };
+        ShaderColor::new(channel(color.r), channel(color.g), channel(color.b), color.a.clamp(0.0, 1.0))
+    }
+
+    pub fn map(&self, color: ShaderColor) -> Color {
+        let mapped = self.apply(color);
+        let channel = |value: f32| (value * 255.0 + 0.5) as u8;
+        Color::new(channel(mapped.r), channel(mapped.g), channel(mapped.b), (mapped.a * 255.0) as u8)
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, Copy)]
+#![allow(dead_code)]
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vector3 {
     pub x: f32,
     pub y: f32,
@@ -18,6 +20,14 @@ impl Vector3 {
         (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
     }
 
+    pub fn length_squared(&self) -> f32 {
+        self.dot(self)
+    }
+
+    pub fn distance(&self, other: &Vector3) -> f32 {
+        (*other - *self).length()
+    }
+
     pub fn normalize(&self) -> Self {
         let len = self.length();
         if len > 0.0 {
@@ -38,6 +48,29 @@ impl Vector3 {
             self.x * other.y - self.y * other.x,
         )
     }
+
+    /// Linear interpolation: `self` at t = 0, `other` at t = 1
+    pub fn lerp(&self, other: &Vector3, t: f32) -> Vector3 {
+        *self + (*other - *self) * t
+    }
+
+    /// Mirrors a direction pointing towards the surface about its normal (unit)
+    pub fn reflect(&self, normal: &Vector3) -> Vector3 {
+        *self - *normal * (2.0 * normal.dot(self))
+    }
+
+    /// Direction refracted when a unit direction pointing towards the surface passes
+    /// into a medium with `eta` = n1 / n2, or `None` on total internal reflection
+    pub fn refract(&self, normal: &Vector3, eta: f32) -> Option<Vector3> {
+        let cos_i = -normal.dot(self);
+        let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+        (k >= 0.0).then(|| (*self * eta + *normal * (eta * cos_i - k.sqrt())).normalize())
+    }
+
+    /// Homogeneous coordinates: w = 1 for points, w = 0 for directions
+    pub fn extend(&self, w: f32) -> Vector4 {
+        Vector4::new(self.x, self.y, self.z, w)
+    }
 }
 
 impl std::ops::Add for Vector3 {
@@ -62,4 +95,328 @@ impl std::ops::Mul<f32> for Vector3 {
     fn mul(self, scalar: f32) -> Vector3 {
         Vector3::new(self.x * scalar, self.y * scalar, self.z * scalar)
     }
-}
\ No newline at end of file
+}
+
+impl std::ops::Mul<Vector3> for f32 {
+    type Output = Vector3;
+
+    fn mul(self, v: Vector3) -> Vector3 {
+        v * self
+    }
+}
+
+/// Component-wise product
+impl std::ops::Mul for Vector3 {
+    type Output = Vector3;
+
+    fn mul(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x * other.x, self.y * other.y, self.z * other.z)
+    }
+}
+
+impl std::ops::Div<f32> for Vector3 {
+    type Output = Vector3;
+
+    fn div(self, scalar: f32) -> Vector3 {
+        Vector3::new(self.x / scalar, self.y / scalar, self.z / scalar)
+    }
+}
+
+impl std::ops::Neg for Vector3 {
+    type Output = Vector3;
+
+    fn neg(self) -> Vector3 {
+        Vector3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl std::ops::AddAssign for Vector3 {
+    fn add_assign(&mut self, other: Vector3) {
+        *self = *self + other;
+    }
+}
+
+impl std::ops::SubAssign for Vector3 {
+    fn sub_assign(&mut self, other: Vector3) {
+        *self = *self - other;
+    }
+}
+
+impl std::ops::MulAssign<f32> for Vector3 {
+    fn mul_assign(&mut self, scalar: f32) {
+        *self = *self * scalar;
+    }
+}
+
+impl std::ops::DivAssign<f32> for Vector3 {
+    fn div_assign(&mut self, scalar: f32) {
+        *self = *self / scalar;
+    }
+}
+
+impl From<Vector3> for nalgebra::Vector3<f32> {
+    fn from(v: Vector3) -> Self {
+        nalgebra::Vector3::new(v.x, v.y, v.z)
+    }
+}
+
+impl From<nalgebra::Vector3<f32>> for Vector3 {
+    fn from(v: nalgebra::Vector3<f32>) -> Self {
+        Vector3::new(v.x, v.y, v.z)
+    }
+}
+
+/// 2D vector, for screen and texture coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vector2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vector2 {
+    pub fn new(x: f32, y: f32) -> Self {
+        Vector2 { x, y }
+    }
+
+    pub fn zero() -> Self {
+        Vector2::new(0.0, 0.0)
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let len = self.length();
+        if len > 0.0 {
+            Vector2::new(self.x / len, self.y / len)
+        } else {
+            *self
+        }
+    }
+
+    pub fn dot(&self, other: &Vector2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// z component of the 3D cross product: twice the signed area of the triangle
+    /// (0, self, other), positive when `other` is counterclockwise from `self`
+    pub fn perp_dot(&self, other: &Vector2) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+
+    pub fn lerp(&self, other: &Vector2, t: f32) -> Vector2 {
+        *self + (*other - *self) * t
+    }
+}
+
+impl std::ops::Add for Vector2 {
+    type Output = Vector2;
+
+    fn add(self, other: Vector2) -> Vector2 {
+        Vector2::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl std::ops::Sub for Vector2 {
+    type Output = Vector2;
+
+    fn sub(self, other: Vector2) -> Vector2 {
+        Vector2::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl std::ops::Mul<f32> for Vector2 {
+    type Output = Vector2;
+
+    fn mul(self, scalar: f32) -> Vector2 {
+        Vector2::new(self.x * scalar, self.y * scalar)
+    }
+}
+
+impl std::ops::Neg for Vector2 {
+    type Output = Vector2;
+
+    fn neg(self) -> Vector2 {
+        Vector2::new(-self.x, -self.y)
+    }
+}
+
+impl From<Vector2> for nalgebra::Vector2<f32> {
+    fn from(v: Vector2) -> Self {
+        nalgebra::Vector2::new(v.x, v.y)
+    }
+}
+
+impl From<nalgebra::Vector2<f32>> for Vector2 {
+    fn from(v: nalgebra::Vector2<f32>) -> Self {
+        Vector2::new(v.x, v.y)
+    }
+}
+
+/// Homogeneous 4D vector: what a projection matrix outputs before the divide by w
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vector4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Vector4 {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Vector4 { x, y, z, w }
+    }
+
+    pub fn dot(&self, other: &Vector4) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    /// The x, y and z components, without dividing
+    pub fn xyz(&self) -> Vector3 {
+        Vector3::new(self.x, self.y, self.z)
+    }
+
+    /// Perspective divide; infinite or NaN when w = 0, like `Matrix::transform_vector`
+    pub fn to_cartesian(self) -> Vector3 {
+        Vector3::new(self.x / self.w, self.y / self.w, self.z / self.w)
+    }
+}
+
+impl std::ops::Add for Vector4 {
+    type Output = Vector4;
+
+    fn add(self, other: Vector4) -> Vector4 {
+        Vector4::new(self.x + other.x, self.y + other.y, self.z + other.z, self.w + other.w)
+    }
+}
+
+impl std::ops::Sub for Vector4 {
+    type Output = Vector4;
+
+    fn sub(self, other: Vector4) -> Vector4 {
+        Vector4::new(self.x - other.x, self.y - other.y, self.z - other.z, self.w - other.w)
+    }
+}
+
+impl std::ops::Mul<f32> for Vector4 {
+    type Output = Vector4;
+
+    fn mul(self, scalar: f32) -> Vector4 {
+        Vector4::new(self.x * scalar, self.y * scalar, self.z * scalar, self.w * scalar)
+    }
+}
+
+impl From<Vector4> for nalgebra::Vector4<f32> {
+    fn from(v: Vector4) -> Self {
+        nalgebra::Vector4::new(v.x, v.y, v.z, v.w)
+    }
+}
+
+impl From<nalgebra::Vector4<f32>> for Vector4 {
+    fn from(v: nalgebra::Vector4<f32>) -> Self {
+        Vector4::new(v.x, v.y, v.z, v.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_vector(rng: &mut StdRng) -> Vector3 {
+        Vector3::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0))
+    }
+
+    #[test]
+    fn normalize_gives_unit_length() {
+        let mut rng = StdRng::seed_from_u64(21);
+        for _ in 0..500 {
+            let v = random_vector(&mut rng);
+            if v.length() > 1e-3 {
+                assert!((v.normalize().length() - 1.0).abs() < 1e-5);
+            }
+        }
+        let zero = Vector3::zero().normalize();
+        assert_eq!((zero.x, zero.y, zero.z), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn cross_product_is_orthogonal_and_anticommutative() {
+        let mut rng = StdRng::seed_from_u64(22);
+        for _ in 0..500 {
+            let (a, b) = (random_vector(&mut rng), random_vector(&mut rng));
+            let c = a.cross(&b);
+            let scale = a.length() * b.length();
+            assert!(c.dot(&a).abs() <= 1e-4 * scale * a.length());
+            assert!(c.dot(&b).abs() <= 1e-4 * scale * b.length());
+            assert!((c + b.cross(&a)).length() <= 1e-5 * scale);
+            // |a x b|² + (a · b)² = |a|² |b|²
+            let lagrange = c.dot(&c) + a.dot(&b).powi(2) - scale * scale;
+            assert!(lagrange.abs() <= 1e-4 * scale * scale);
+        }
+    }
+
+    #[test]
+    fn basis_vectors_follow_the_right_hand_rule() {
+        let (x, y, z) = (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!((x.cross(&y) - z).length(), 0.0);
+        assert_eq!((y.cross(&z) - x).length(), 0.0);
+        assert_eq!((z.cross(&x) - y).length(), 0.0);
+    }
+
+    #[test]
+    fn reflect_keeps_the_angle_and_refract_follows_snell() {
+        let mut rng = StdRng::seed_from_u64(23);
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        for _ in 0..500 {
+            let incident = Vector3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..-0.05), rng.gen_range(-1.0..1.0)).normalize();
+            let reflected = incident.reflect(&normal);
+            assert!((reflected.length() - 1.0).abs() < 1e-5);
+            assert!((reflected.dot(&normal) + incident.dot(&normal)).abs() < 1e-5);
+
+            // Snell: n1 sin(i) = n2 sin(t)
+            let eta = rng.gen_range(0.5..1.5);
+            let sin_i = incident.cross(&normal).length();
+            match incident.refract(&normal, eta) {
+                Some(refracted) => {
+                    assert!(refracted.dot(&normal) < 0.0, "the refracted ray must cross the surface");
+                    assert!((refracted.cross(&normal).length() - eta * sin_i).abs() < 1e-4);
+                }
+                None => assert!(eta * sin_i > 1.0 - 1e-4, "total internal reflection at eta {} sin {}", eta, sin_i),
+            }
+        }
+    }
+
+    #[test]
+    fn operators_and_interpolation() {
+        let (a, b) = (Vector3::new(1.0, -2.0, 3.0), Vector3::new(4.0, 0.5, -1.0));
+        assert_eq!(-a, Vector3::new(-1.0, 2.0, -3.0));
+        assert_eq!(a * b, Vector3::new(4.0, -1.0, -3.0));
+        assert_eq!(a / 2.0, a * 0.5);
+        assert_eq!(2.0 * a, a * 2.0);
+        let mut c = a;
+        c += b;
+        c -= b;
+        c *= 4.0;
+        c /= 4.0;
+        assert_eq!(c, a);
+        assert_eq!(a.lerp(&b, 0.0), a);
+        assert_eq!(a.lerp(&b, 1.0), b);
+        assert!((a.lerp(&b, 0.25).distance(&a) - 0.25 * a.distance(&b)).abs() < 1e-5);
+        assert_eq!(a.extend(1.0).xyz(), a);
+        assert_eq!(a.extend(2.0).to_cartesian(), a * 0.5);
+    }
+
+    #[test]
+    fn nalgebra_round_trip() {
+        let v = Vector3::new(0.25, -1.5, 8.0);
+        let converted: nalgebra::Vector3<f32> = v.into();
+        assert_eq!(Vector3::from(converted), v);
+        assert_eq!(converted.cross(&nalgebra::Vector3::new(1.0, 2.0, 3.0)), v.cross(&Vector3::new(1.0, 2.0, 3.0)).into());
+        let (p, h) = (Vector2::new(3.0, -4.0), Vector4::new(1.0, 2.0, 3.0, 4.0));
+        assert_eq!(Vector2::from(nalgebra::Vector2::from(p)), p);
+        assert_eq!(Vector4::from(nalgebra::Vector4::from(h)), h);
+    }
+}